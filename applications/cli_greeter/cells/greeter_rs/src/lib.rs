@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use body_core::{BodyBus, BusError, Cell, Envelope, MessageHandler};
+use body_core::{sync_handler, BodyBus, BusError, Cell, Envelope, MessageHandler};
 use serde_json::{json, Value};
 
 /// Simple greeter cell that combines name prompting and greeting logic
@@ -57,7 +57,8 @@ impl Cell for GreeterCell {
             GreeterCell::handle_say_hello(envelope)
         });
 
-        bus.subscribe("cbs.greeter.say_hello", handler).await
+        bus.subscribe("cbs.greeter.say_hello", sync_handler(handler)).await?.detach();
+        Ok(())
     }
 }
 
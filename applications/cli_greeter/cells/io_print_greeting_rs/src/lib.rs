@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use body_core::{BodyBus, BusError, Cell, Envelope, MessageHandler};
+use body_core::{sync_handler, BodyBus, BusError, Cell, Envelope, MessageHandler};
 use serde_json::{json, Value};
 use std::io::{self, Write};
 
@@ -38,7 +38,7 @@ impl PrinterCell {
             .as_ref()
             .and_then(|p| p.get("message"))
             .and_then(|m| m.as_str())
-            .ok_or_else(|| BusError::BadRequest("Missing 'message' field in payload".to_string()))?;
+            .ok_or_else(|| BusError::bad_request("Missing 'message' field in payload"))?;
 
         Self::print_message(message)?;
 
@@ -71,7 +71,8 @@ impl Cell for PrinterCell {
             PrinterCell::handle_print_request(envelope)
         });
 
-        bus.subscribe("cbs.printer.write", handler).await
+        bus.subscribe("cbs.printer.write", sync_handler(handler)).await?.detach();
+        Ok(())
     }
 }
 
@@ -146,7 +147,7 @@ mod tests {
         assert!(result.is_err());
         
         match result.unwrap_err() {
-            BusError::BadRequest(msg) => assert!(msg.contains("Missing 'message' field")),
+            BusError::BadRequest { message: msg, .. } => assert!(msg.contains("Missing 'message' field")),
             _ => panic!("Expected BadRequest error"),
         }
     }
@@ -164,7 +165,7 @@ mod tests {
         assert!(result.is_err());
         
         match result.unwrap_err() {
-            BusError::BadRequest(msg) => assert!(msg.contains("Missing 'message' field")),
+            BusError::BadRequest { message: msg, .. } => assert!(msg.contains("Missing 'message' field")),
             _ => panic!("Expected BadRequest error"),
         }
     }
@@ -182,7 +183,7 @@ mod tests {
         assert!(result.is_err());
         
         match result.unwrap_err() {
-            BusError::BadRequest(msg) => assert!(msg.contains("Missing 'message' field")),
+            BusError::BadRequest { message: msg, .. } => assert!(msg.contains("Missing 'message' field")),
             _ => panic!("Expected BadRequest error"),
         }
     }
@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use body_core::{BodyBus, BusError, Cell, Envelope, MessageHandler};
+use body_core::{async_handler, BodyBus, BusError, Cell, Envelope};
 use serde_json::{json, Value};
 use std::io::{self, BufRead, BufReader, Read, Write};
 
@@ -47,8 +47,8 @@ impl PromptNameCell {
         Ok(input.trim().to_string())
     }
 
-    /// Handle prompt name request
-    pub fn handle_prompt_request(envelope: Envelope) -> Result<Value, BusError> {
+    /// Handle prompt name request; stdin is read on a blocking thread
+    pub async fn handle_prompt_request(envelope: Envelope) -> Result<Value, BusError> {
         // Check if custom prompt is provided
         let _prompt = envelope
             .payload
@@ -66,11 +66,13 @@ impl PromptNameCell {
         {
             test_input.to_string()
         } else {
-            Self::prompt_for_name()?
+            tokio::task::spawn_blocking(Self::prompt_for_name)
+                .await
+                .map_err(|e| BusError::Internal(format!("Prompt task failed: {}", e)))??
         };
 
         if name.is_empty() {
-            return Err(BusError::BadRequest("Name cannot be empty".to_string()));
+            return Err(BusError::bad_request("Name cannot be empty"));
         }
 
         Ok(json!({
@@ -98,11 +100,10 @@ impl Cell for PromptNameCell {
     }
 
     async fn register(&self, bus: &dyn BodyBus) -> Result<(), BusError> {
-        let handler: MessageHandler = Box::new(|envelope| {
-            PromptNameCell::handle_prompt_request(envelope)
-        });
+        let handler = async_handler(|envelope, _| PromptNameCell::handle_prompt_request(envelope));
 
-        bus.subscribe("cbs.prompt_name.read", handler).await
+        bus.subscribe("cbs.prompt_name.read", handler).await?.detach();
+        Ok(())
     }
 }
 
@@ -173,8 +174,8 @@ mod tests {
         assert_eq!(result, "José María");
     }
 
-    #[test]
-    fn handle_prompt_request_with_test_input() {
+    #[tokio::test]
+    async fn handle_prompt_request_with_test_input() {
         let envelope = Envelope::new_request(
             "prompt_name",
             "read",
@@ -182,14 +183,14 @@ mod tests {
             json!({"test_input": "Charlie"})
         );
 
-        let result = PromptNameCell::handle_prompt_request(envelope).unwrap();
+        let result = PromptNameCell::handle_prompt_request(envelope).await.unwrap();
         assert_eq!(result["name"], "Charlie");
         assert_eq!(result["length"], 7);
         assert!(result["timestamp"].is_string());
     }
 
-    #[test]
-    fn handle_prompt_request_with_empty_test_input() {
+    #[tokio::test]
+    async fn handle_prompt_request_with_empty_test_input() {
         let envelope = Envelope::new_request(
             "prompt_name",
             "read",
@@ -197,17 +198,17 @@ mod tests {
             json!({"test_input": ""})
         );
 
-        let result = PromptNameCell::handle_prompt_request(envelope);
+        let result = PromptNameCell::handle_prompt_request(envelope).await;
         assert!(result.is_err());
         
         match result.unwrap_err() {
-            BusError::BadRequest(msg) => assert_eq!(msg, "Name cannot be empty"),
+            BusError::BadRequest { message: msg, .. } => assert_eq!(msg, "Name cannot be empty"),
             _ => panic!("Expected BadRequest error"),
         }
     }
 
-    #[test]
-    fn handle_prompt_request_with_custom_prompt() {
+    #[tokio::test]
+    async fn handle_prompt_request_with_custom_prompt() {
         let envelope = Envelope::new_request(
             "prompt_name",
             "read",
@@ -215,12 +216,12 @@ mod tests {
             json!({"prompt": "What's your name? ", "test_input": "Diana"})
         );
 
-        let result = PromptNameCell::handle_prompt_request(envelope).unwrap();
+        let result = PromptNameCell::handle_prompt_request(envelope).await.unwrap();
         assert_eq!(result["name"], "Diana");
     }
 
-    #[test]
-    fn handle_prompt_request_with_whitespace_test_input() {
+    #[tokio::test]
+    async fn handle_prompt_request_with_whitespace_test_input() {
         let envelope = Envelope::new_request(
             "prompt_name",
             "read",
@@ -228,7 +229,7 @@ mod tests {
             json!({"test_input": "  Eve  "})
         );
 
-        let result = PromptNameCell::handle_prompt_request(envelope).unwrap();
+        let result = PromptNameCell::handle_prompt_request(envelope).await.unwrap();
         assert_eq!(result["name"], "  Eve  "); // Should preserve whitespace in test mode
     }
 
@@ -260,4 +261,14 @@ mod tests {
         let cell = PromptNameCell::default();
         assert_eq!(cell.id(), "io_prompt_name");
     }
+
+    #[tokio::test]
+    async fn registered_handler_answers_on_the_bus() {
+        let bus = body_core::LocalBus::new();
+        PromptNameCell::new().register(&bus).await.unwrap();
+
+        let envelope = Envelope::new_request("prompt_name", "read", "demo/v1/Void", json!({"test_input": "Frank"}));
+        let reply = bus.request(envelope).await.unwrap();
+        assert_eq!(reply["name"], "Frank");
+    }
 }
\ No newline at end of file
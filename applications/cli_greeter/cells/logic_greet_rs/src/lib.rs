@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use body_core::{BodyBus, BusError, Cell, Envelope, MessageHandler};
+use body_core::{sync_handler, BodyBus, BusError, Cell, Envelope, MessageHandler};
 use serde_json::{json, Value};
 
 /// Logic cell that formats greeting messages
@@ -63,7 +63,8 @@ impl Cell for GreeterCell {
             GreeterCell::handle_greeting_request(envelope)
         });
 
-        bus.subscribe("cbs.greeter.say_hello", handler).await
+        bus.subscribe("cbs.greeter.say_hello", sync_handler(handler)).await?.detach();
+        Ok(())
     }
 }
 
@@ -0,0 +1,88 @@
+use body_core::{cell_registry, BusError, Cell, CellConfig, CellRegistry};
+use std::path::PathBuf;
+use std::sync::Arc;
+use web_server::{WebServerCell, WebServerConfig};
+
+/// Cells linked into the body binary, by the names app.yaml uses for them
+///
+/// To make a Rust cell available to applications, add its crate to
+/// `body/Cargo.toml` and an entry here.
+pub fn linked_cells() -> CellRegistry {
+    cell_registry! {
+        "greeter_rs" => default_cell::<greeter_rs::GreeterCell>,
+        "io_print_greeting_rs" => default_cell::<io_print_greeting_rs::PrinterCell>,
+        "io_prompt_name_rs" => default_cell::<io_prompt_name_rs::PromptNameCell>,
+        "logic_greet_rs" => default_cell::<logic_greet_rs::GreeterCell>,
+        "web_server" => web_server_cell,
+    }
+}
+
+/// Cell that takes no settings from its `config:` entry
+fn default_cell<C: Cell + Default + 'static>(_cell: &CellConfig) -> Result<Arc<dyn Cell>, BusError> {
+    Ok(Arc::new(C::default()))
+}
+
+/// Web server cell; `config:` may set `static_dir`, `port` and `enable_cors`
+fn web_server_cell(cell: &CellConfig) -> Result<Arc<dyn Cell>, BusError> {
+    let mut config = WebServerConfig::default();
+    if let Some(settings) = &cell.config {
+        if let Some(dir) = settings.get("static_dir").and_then(|v| v.as_str()) {
+            config.static_dir = PathBuf::from(dir);
+        }
+        if let Some(port) = settings.get("port") {
+            config.port = port
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .ok_or_else(|| BusError::bad_request(format!("Invalid web_server port: {}", port)))?;
+        }
+        if let Some(enable_cors) = settings.get("enable_cors").and_then(|v| v.as_bool()) {
+            config.enable_cors = enable_cors;
+        }
+    }
+    Ok(Arc::new(WebServerCell::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(config: Option<serde_json::Value>) -> CellConfig {
+        named("web_server", config)
+    }
+
+    fn named(name: &str, config: Option<serde_json::Value>) -> CellConfig {
+        CellConfig {
+            name: name.to_string(),
+            path: format!("cells/{}", name),
+            dependencies: vec![],
+            limits: None,
+            config,
+        }
+    }
+
+    #[test]
+    fn web_server_is_linked() {
+        let cell = linked_cells().instantiate(&entry(None)).unwrap();
+        assert_eq!(cell.id(), "web_server");
+    }
+
+    #[test]
+    fn cli_greeter_cells_are_linked() {
+        let cells = linked_cells();
+        for (name, id) in [
+            ("greeter_rs", "greeter"),
+            ("io_print_greeting_rs", "io_print_greeting"),
+            ("io_prompt_name_rs", "io_prompt_name"),
+            ("logic_greet_rs", "logic_greet"),
+        ] {
+            assert_eq!(cells.instantiate(&named(name, None)).unwrap().id(), id);
+        }
+    }
+
+    #[test]
+    fn web_server_rejects_invalid_port() {
+        let result = linked_cells().instantiate(&entry(Some(json!({"port": 70000}))));
+        assert!(result.is_err());
+    }
+}
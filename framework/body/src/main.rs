@@ -1,15 +1,16 @@
-use body_core::{AppLoader, BodyBus, BusError, Envelope};
-use serde_json::json;
+use body_bus::NatsBus;
+use body_core::{
+    AppLoader, BodyBus, CellHost, CellRegistry, CircuitBreaker, FlowEngine, IdempotencyMiddleware, LimitMiddleware,
+    LocalBus, LoggingMiddleware, MiddlewareBus, RetryMiddleware,
+};
+use serde_json::Value;
 use std::env;
 use std::process;
 use std::sync::Arc;
-use tokio;
-use tracing::{debug, error, info, warn};
+use tracing::{error, info, warn};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
-use tokio::sync::RwLock;
 
-// Import web server for serving applications
-use web_server;
+mod cells;
 
 /// Configuration for the Body framework
 #[derive(Debug, Clone)]
@@ -18,6 +19,10 @@ pub struct BodyConfig {
     pub use_mock_bus: bool,
     pub demo_mode: bool,
     pub app_name: Option<String>,
+    /// Flow to run once the cells have started, instead of waiting for Ctrl+C
+    pub flow: Option<String>,
+    /// JSON payload for the flow's first step
+    pub flow_input: Option<String>,
 }
 
 impl Default for BodyConfig {
@@ -27,6 +32,8 @@ impl Default for BodyConfig {
             use_mock_bus: false,
             demo_mode: false,
             app_name: None,
+            flow: None,
+            flow_input: None,
         }
     }
 }
@@ -63,6 +70,16 @@ impl BodyConfig {
                         config.app_name = Some(app_name.clone());
                     }
                 }
+                "--flow" => {
+                    if let Some(flow) = args.get(i + 1) {
+                        config.flow = Some(flow.clone());
+                    }
+                }
+                "--input" => {
+                    if let Some(input) = args.get(i + 1) {
+                        config.flow_input = Some(input.clone());
+                    }
+                }
                 "--demo" => {
                     config.demo_mode = true;
                 }
@@ -95,21 +112,25 @@ fn print_help() {
     info!("OPTIONS:");
     info!("    --app <NAME>        Load specific application from applications/ directory");
     info!("    --list-apps         List all available applications");
+    info!("    --flow <NAME>       Run a flow from app.yaml once the cells have started");
+    info!("    --input <JSON>      Payload for the flow's first step (default: {{}})");
     info!("    --nats-url <URL>    NATS server URL (default: nats://localhost:4222)");
     info!("    --demo              Run in demo mode with simulated input");
-    info!("    --mock-bus          Use mock bus instead of NATS (for testing)");
+    info!("    --mock-bus          Run all cells on an in-process bus instead of connecting to NATS");
     info!("    -h, --help          Print this help message");
     info!("");
     info!("ENVIRONMENT VARIABLES:");
     info!("    NATS_URL           NATS server URL");
     info!("    CBS_DEMO_MODE      Enable demo mode");
-    info!("    CBS_MOCK_BUS       Use mock bus");
+    info!("    CBS_MOCK_BUS       Run all cells on an in-process bus");
     info!("");
     info!("EXAMPLES:");
     info!("    body --list-apps                    # List available applications");
     info!("    body --app cli_greeter              # Run CLI greeter application");
     info!("    body --app flutter_flow_web         # Run Flutter web application");
     info!("    body --app my_app --demo            # Run application in demo mode");
+    info!("    body --app cli_greeter --mock-bus   # Run without a NATS server");
+    info!("    body --app cli_greeter --flow greet_user --input '{{\"test_input\": \"Ada\"}}'");
 }
 
 /// List available applications
@@ -138,50 +159,28 @@ fn list_applications() {
     }
 }
 
-/// Mock bus implementation for testing without NATS
-pub struct MockBus {
-    handlers: Arc<RwLock<std::collections::HashMap<String, body_core::MessageHandler>>>,
-}
-
-impl MockBus {
-    pub fn new() -> Self {
-        Self {
-            handlers: Arc::new(RwLock::new(std::collections::HashMap::new())),
-        }
-    }
-}
-
-#[async_trait::async_trait]
-impl BodyBus for MockBus {
-    async fn request(&self, envelope: Envelope) -> Result<serde_json::Value, BusError> {
-        let subject = envelope.subject();
-        
-        let handlers = self.handlers.read().await;
-        if let Some(handler) = handlers.get(&subject) {
-            handler(envelope)
-        } else {
-            Err(BusError::NotFound(format!("No handler for subject: {}", subject)))
-        }
-    }
-    
-    async fn subscribe(&self, subject: &str, handler: body_core::MessageHandler) -> Result<(), BusError> {
-        let mut handlers = self.handlers.write().await;
-        handlers.insert(subject.to_string(), handler);
-        info!(subject = %subject, "MockBus: Subscribed");
-        Ok(())
-    }
-}
-
 /// Main Body orchestrator
 pub struct Body {
     config: BodyConfig,
     app_loader: AppLoader,
+    registry: CellRegistry,
 }
 
 impl Body {
     pub fn new(config: BodyConfig) -> Self {
+        Self::with_registry(config, cells::linked_cells())
+    }
+    
+    /// Create a Body that builds application cells from `registry`
+    pub fn with_registry(config: BodyConfig, registry: CellRegistry) -> Self {
         let app_loader = AppLoader::new("./applications");
-        Self { config, app_loader }
+        Self { config, app_loader, registry }
+    }
+    
+    /// Load applications from `dir` instead of `./applications`
+    pub fn with_applications_dir(mut self, dir: &str) -> Self {
+        self.app_loader = AppLoader::new(dir);
+        self
     }
     
     /// Run the CBS framework
@@ -220,16 +219,17 @@ impl Body {
         // This is a simple heuristic - in the future, app.yaml should include type
         if app_name.contains("web") || app_name.contains("flutter") {
             self.run_web_application(&app_config).await
-        } else if app_name.contains("cli") || app_name.contains("greeter") {
-            self.run_cli_application(&app_config).await
         } else {
-            self.run_service_application(&app_config).await
+            self.run_service_application(&app_config).await.map(|_| ())
         }
     }
     
     /// Run a web application
     async fn run_web_application(&self, app_config: &body_core::AppConfig) -> Result<(), Box<dyn std::error::Error>> {
         info!(name = %app_config.name, "Starting Web Application");
+        if let Some(flow) = &self.config.flow {
+            return Err(format!("Cannot run flow '{}': web applications only serve static files", flow).into());
+        }
         
         // Set up web server configuration
         let web_dir = format!("./applications/{}/web", app_config.name);
@@ -255,40 +255,103 @@ impl Body {
         Ok(())
     }
     
-    /// Run a CLI application
-    async fn run_cli_application(&self, app_config: &body_core::AppConfig) -> Result<(), Box<dyn std::error::Error>> {
-        info!(name = %app_config.name, "Starting CLI Application");
-        
-        if self.config.demo_mode {
-            info!("Running in demo mode");
-            // In demo mode, we would simulate the application flow
-            self.show_application_info(app_config);
-            info!(name = %app_config.name, "Demo mode completed");
-        } else {
-            warn!("Interactive CLI mode not yet implemented in framework");
-            info!("Application cells would be loaded and orchestrated here");
-            self.show_application_info(app_config);
-        }
-        
-        Ok(())
-    }
-    
-    /// Run a service application
-    async fn run_service_application(&self, app_config: &body_core::AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+    /// Run a service application; the output of its `--flow`, if one ran
+    async fn run_service_application(&self, app_config: &body_core::AppConfig) -> Result<Option<Value>, Box<dyn std::error::Error>> {
         info!(name = %app_config.name, "Starting Service Application");
         
-        // Create MockBus for now (would use NATS in production)
-        let _bus = MockBus::new();
-        
-        info!("Using MockBus for cell communication");
-        info!(cells = app_config.cells.len(), "Application would register cells");
+        // Cells see the middleware stack through their handler contexts
+        let mut bus = MiddlewareBus::new(self.connect_bus().await?).layer(LoggingMiddleware);
+        if !app_config.retry.is_empty() {
+            info!(subjects = app_config.retry.subjects.len(), "Applying retry policies from app.yaml");
+            bus = bus.layer(RetryMiddleware::new(app_config.retry.clone())?);
+        }
+        if let Some(config) = &app_config.circuit_breaker {
+            info!(failure_threshold = config.failure_threshold, cool_down_ms = config.cool_down_ms, "Enabling circuit breakers");
+            bus = bus.layer(CircuitBreaker::new(config.clone()));
+        }
+        if !app_config.limits.outbound.is_empty() {
+            bus = bus.layer(LimitMiddleware::outbound(&app_config.limits.outbound)?);
+        }
+        if let Some(config) = &app_config.idempotency {
+            info!(ttl_ms = config.ttl_ms, subjects = config.subjects.len(), "Suppressing duplicate requests");
+            bus = bus.layer(IdempotencyMiddleware::in_memory(config)?);
+        }
+        if !app_config.limits.subjects.is_empty() {
+            bus = bus.layer(LimitMiddleware::inbound(&app_config.limits.subjects)?);
+        }
         
         self.show_application_info(app_config);
         
-        warn!("Service orchestration not yet implemented in framework");
-        info!("Cells would be loaded, registered, and coordinated here");
+        let bus: Arc<dyn BodyBus> = Arc::new(bus);
+        let mut cells = self.registry.host_for(app_config)?;
+        info!(order = ?cells.names(), "Instantiated application cells in dependency order");
+        limit_cells(&mut cells, &bus);
+        self.run_cells(cells, bus.as_ref(), &FlowEngine::new(&app_config.flows)).await
+    }
+    
+    /// The bus the cells talk over: NATS at `nats_url`, or the in-process
+    /// LocalBus with `--mock-bus`
+    async fn connect_bus(&self) -> Result<Arc<dyn BodyBus>, Box<dyn std::error::Error>> {
+        if self.config.use_mock_bus {
+            info!("Using in-process LocalBus for cell communication");
+            return Ok(Arc::new(LocalBus::new()));
+        }
+        let bus = NatsBus::connect(&self.config.nats_url)
+            .await
+            .map_err(|e| format!("Cannot reach NATS at {} ({}); start a server or pass --mock-bus", self.config.nats_url, e))?;
+        info!(nats_url = %self.config.nats_url, "Using NATS for cell communication");
+        Ok(Arc::new(bus))
+    }
+    
+    /// Start the cells, report their health, run the `--flow` if one was
+    /// given or wait for Ctrl+C otherwise (unless in demo mode), and stop the
+    /// cells again
+    async fn run_cells(
+        &self,
+        mut cells: CellHost,
+        bus: &dyn BodyBus,
+        flows: &FlowEngine,
+    ) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+        cells.start_all(bus).await?;
+        for (cell, status) in cells.health().await {
+            if status.is_healthy() {
+                info!(cell = %cell, status = %status, "Cell health");
+            } else {
+                warn!(cell = %cell, status = %status, "Cell health");
+            }
+        }
         
-        Ok(())
+        let mut outcome = Ok(None);
+        if let Some(flow) = &self.config.flow {
+            outcome = self.run_flow(flows, bus, flow).await.map(Some);
+        } else if self.config.demo_mode {
+            info!(cells = cells.len(), "Demo mode: cells started and checked");
+        } else if !cells.is_empty() {
+            info!(cells = cells.len(), "Cells running; press Ctrl+C to stop");
+            tokio::signal::ctrl_c().await?;
+        }
+        
+        // A failed flow is the error worth returning; stop failures are logged
+        let errors = cells.stop_all().await;
+        for error in &errors {
+            error!(cell = %error.cell, phase = %error.phase, error = %error.source, "Cell failed to stop cleanly");
+        }
+        match (outcome, errors.len()) {
+            (Ok(_), failed) if failed > 0 => Err(format!("{} cell(s) failed to stop cleanly", failed).into()),
+            (outcome, _) => outcome,
+        }
+    }
+    
+    /// Run one flow with the `--input` payload, log its output and return it
+    async fn run_flow(&self, flows: &FlowEngine, bus: &dyn BodyBus, flow: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        let input = match &self.config.flow_input {
+            Some(input) => serde_json::from_str(input).map_err(|e| format!("Invalid --input JSON: {}", e))?,
+            None => serde_json::json!({}),
+        };
+        info!(flow = %flow, available = ?flows.names(), "Running flow");
+        let output = flows.run(bus, flow, input).await?;
+        info!(flow = %flow, output = %output, "Flow completed");
+        Ok(output)
     }
     
     /// Show application information
@@ -334,6 +397,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     body.run().await
 }
 
+/// Give every cell with `limits:` in app.yaml its own bus that enforces them
+fn limit_cells(cells: &mut CellHost, bus: &Arc<dyn BodyBus>) {
+    let limited: Vec<(String, Arc<dyn BodyBus>)> = cells
+        .configs()
+        .filter_map(|config| {
+            let limits = config.limits.as_ref()?;
+            info!(cell = %config.name, limits = ?limits, "Applying cell limits");
+            let cell_bus = MiddlewareBus::new(Arc::clone(bus)).layer(LimitMiddleware::for_cell(&config.name, limits));
+            Some((config.name.clone(), Arc::new(cell_bus) as Arc<dyn BodyBus>))
+        })
+        .collect();
+    for (name, cell_bus) in limited {
+        cells.use_bus(&name, cell_bus);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -364,27 +443,213 @@ mod tests {
     }
     
     #[tokio::test]
-    async fn mock_bus_subscription() {
-        let bus = MockBus::new();
-        let handler = Box::new(|_env: Envelope| Ok(json!({"test": true})));
+    async fn run_cells_without_cells_returns_immediately() {
+        let body = Body::new(BodyConfig::default());
+        let bus = LocalBus::new();
+        assert!(body.run_cells(CellHost::new(), &bus, &FlowEngine::default()).await.is_ok());
+    }
+    
+    #[tokio::test]
+    async fn run_cells_runs_the_requested_flow() {
+        let app_config: body_core::AppConfig = serde_yaml::from_str(
+            "name: svc\nversion: 1.0.0\ndescription: test\ncells:\n  - name: echo\n    path: cells/echo\nflows:\n  - name: ping\n    steps:\n      - cell: echo\n        action: ping\n",
+        )
+        .unwrap();
+        let bus = LocalBus::new();
+        bus.subscribe("cbs.echo.ping", body_core::sync_handler(|envelope| Ok(envelope.payload.clone().unwrap_or_default())))
+            .await
+            .unwrap()
+            .detach();
+        let flows = FlowEngine::new(&app_config.flows);
+        
+        let body = Body::new(BodyConfig {
+            flow: Some("ping".to_string()),
+            flow_input: Some("{\"n\": 1}".to_string()),
+            ..BodyConfig::default()
+        });
+        assert!(body.run_cells(CellHost::new(), &bus, &flows).await.is_ok());
+        
+        let body = Body::new(BodyConfig {
+            flow: Some("pong".to_string()),
+            ..BodyConfig::default()
+        });
+        let error = body.run_cells(CellHost::new(), &bus, &flows).await.unwrap_err();
+        assert_eq!(error.to_string(), "Unknown flow: pong");
+    }
+    
+    /// Cell whose `stop` fails
+    struct Stubborn;
+    
+    #[async_trait::async_trait]
+    impl body_core::Cell for Stubborn {
+        fn id(&self) -> &str {
+            "stubborn"
+        }
+        
+        fn subjects(&self) -> Vec<String> {
+            vec![]
+        }
+        
+        async fn register(&self, _bus: &dyn BodyBus) -> Result<(), body_core::BusError> {
+            Ok(())
+        }
+        
+        async fn stop(&self) -> Result<(), body_core::BusError> {
+            Err(body_core::BusError::Internal("still busy".to_string()))
+        }
+    }
+    
+    fn stubborn_cells() -> CellHost {
+        let app_config: body_core::AppConfig = serde_yaml::from_str(
+            "name: svc\nversion: 1.0.0\ndescription: test\ncells:\n  - name: stubborn\n    path: cells/stubborn\n",
+        )
+        .unwrap();
+        let mut cells = CellHost::new();
+        cells.add(Arc::new(Stubborn), app_config.cells[0].clone());
+        cells
+    }
+    
+    #[tokio::test]
+    async fn flow_errors_outrank_stop_failures() {
+        let bus = LocalBus::new();
+        let body = Body::new(BodyConfig {
+            demo_mode: true,
+            ..BodyConfig::default()
+        });
+        let error = body.run_cells(stubborn_cells(), &bus, &FlowEngine::default()).await.unwrap_err();
+        assert_eq!(error.to_string(), "1 cell(s) failed to stop cleanly");
         
-        let result = bus.subscribe("test.subject", handler).await;
-        assert!(result.is_ok());
+        let body = Body::new(BodyConfig {
+            flow: Some("pong".to_string()),
+            ..BodyConfig::default()
+        });
+        let error = body.run_cells(stubborn_cells(), &bus, &FlowEngine::default()).await.unwrap_err();
+        assert_eq!(error.to_string(), "Unknown flow: pong");
     }
     
     #[tokio::test]
-    async fn mock_bus_request_with_handler() {
-        let bus = MockBus::new();
-        let handler = Box::new(|envelope: Envelope| {
-            Ok(json!({"received": envelope.service}))
+    async fn service_application_fails_on_unknown_cells() {
+        let body = Body::new(BodyConfig {
+            use_mock_bus: true,
+            ..BodyConfig::default()
         });
+        let app_config: body_core::AppConfig = serde_yaml::from_str(
+            "name: svc\nversion: 1.0.0\ndescription: test\ncells:\n  - name: no_such_cell\n    path: cells/no_such_cell\n",
+        )
+        .unwrap();
         
-        bus.subscribe("cbs.test.action", handler).await.unwrap();
+        let error = body.run_service_application(&app_config).await.unwrap_err();
+        assert!(error.to_string().contains("no_such_cell"));
+    }
+    
+    /// Cell answering `cbs.echo.ping` with its payload
+    struct Echo;
+    
+    #[async_trait::async_trait]
+    impl body_core::Cell for Echo {
+        fn id(&self) -> &str {
+            "echo"
+        }
         
-        let envelope = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
-        let result = bus.request(envelope).await.unwrap();
+        fn subjects(&self) -> Vec<String> {
+            vec!["cbs.echo.ping".to_string()]
+        }
         
-        assert_eq!(result["received"], "test");
+        async fn register(&self, bus: &dyn BodyBus) -> Result<(), body_core::BusError> {
+            let echo = body_core::sync_handler(|envelope| Ok(envelope.payload.clone().unwrap_or_default()));
+            bus.subscribe("cbs.echo.ping", echo).await?.detach();
+            Ok(())
+        }
+    }
+    
+    #[tokio::test]
+    async fn cells_with_limits_reply_overloaded() {
+        let app_config: body_core::AppConfig = serde_yaml::from_str(
+            "name: svc\nversion: 1.0.0\ndescription: test\ncells:\n  - name: echo\n    path: cells/echo\n    limits: { rate_per_sec: 0.001, burst: 1 }\n",
+        )
+        .unwrap();
+        let mut cells = CellHost::new();
+        cells.add(Arc::new(Echo), app_config.cells[0].clone());
+        let bus: Arc<dyn BodyBus> = Arc::new(LocalBus::new());
+        limit_cells(&mut cells, &bus);
+        cells.start_all(bus.as_ref()).await.unwrap();
+        
+        let ping = || body_core::Envelope::new_request("echo", "ping", "demo/v1/Ping", serde_json::json!({}));
+        assert!(bus.request(ping()).await.is_ok());
+        match bus.request(ping()).await {
+            Err(body_core::BusError::Overloaded(message)) => assert!(message.contains("cell echo")),
+            other => panic!("Expected Overloaded, got: {:?}", other),
+        }
+    }
+    
+    fn applications_dir() -> String {
+        format!("{}/../../applications", env!("CARGO_MANIFEST_DIR"))
+    }
+    
+    #[tokio::test]
+    async fn cli_greeter_runs_the_requested_flow() {
+        let body = Body::new(BodyConfig {
+            flow: Some("greet_user".to_string()),
+            flow_input: Some("{\"test_input\": \"Ada\"}".to_string()),
+            use_mock_bus: true,
+            ..BodyConfig::default()
+        })
+        .with_applications_dir(&applications_dir());
+        let app_config = body.app_loader.load_application("cli_greeter").unwrap();
+        let output = body.run_service_application(&app_config).await.unwrap().unwrap();
+        assert_eq!(output["printed"], true);
+        assert_eq!(output["message_length"], "Hello Ada!".len());
+        
+        // Flows route by subject, so no two cells may share one
+        let mut subjects: Vec<String> = app_config
+            .cells
+            .iter()
+            .flat_map(|config| body.registry.instantiate(config).unwrap().subjects())
+            .collect();
+        let listed = subjects.len();
+        subjects.sort();
+        subjects.dedup();
+        assert_eq!(subjects.len(), listed, "cells share a subject: {:?}", subjects);
+        
+        let body = Body::new(BodyConfig {
+            flow: Some("no_such_flow".to_string()),
+            use_mock_bus: true,
+            ..BodyConfig::default()
+        })
+        .with_applications_dir(&applications_dir());
+        let error = body.run_application("cli_greeter").await.unwrap_err();
+        assert_eq!(error.to_string(), "Unknown flow: no_such_flow");
+    }
+    
+    #[tokio::test]
+    async fn web_applications_reject_flows() {
+        let apps = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(apps.path().join("demo_web")).unwrap();
+        std::fs::write(
+            apps.path().join("demo_web/app.yaml"),
+            "name: demo_web\nversion: 1.0.0\ndescription: test\ncells: []\n",
+        )
+        .unwrap();
+        
+        let body = Body::new(BodyConfig {
+            flow: Some("greet_user".to_string()),
+            ..BodyConfig::default()
+        })
+        .with_applications_dir(apps.path().to_str().unwrap());
+        let error = body.run_application("demo_web").await.unwrap_err();
+        assert_eq!(error.to_string(), "Cannot run flow 'greet_user': web applications only serve static files");
+    }
+    
+    #[tokio::test]
+    async fn unreachable_nats_is_reported() {
+        let body = Body::new(BodyConfig {
+            nats_url: "nats://127.0.0.1:1".to_string(),
+            ..BodyConfig::default()
+        });
+        match body.connect_bus().await {
+            Err(error) => assert!(error.to_string().contains("--mock-bus")),
+            Ok(_) => panic!("Expected nothing to listen on port 1"),
+        }
     }
     
     #[test]
@@ -25,8 +25,11 @@ mod app_loading_integration_tests {
                 name: "flow_ui".to_string(),
                 path: "cells/flow_ui".to_string(),
                 dependencies: vec![],
+                limits: None,
+                config: None,
             }],
             shared_cells: vec!["cbs_sdk".to_string()],
+            ..Default::default()
         };
         
         let config_yaml = serde_yaml::to_string(&flutter_config).unwrap();
@@ -47,14 +50,19 @@ mod app_loading_integration_tests {
                     name: "greeter_rs".to_string(),
                     path: "cells/greeter_rs".to_string(),
                     dependencies: vec![],
+                    limits: None,
+                    config: None,
                 },
                 CellConfig {
                     name: "io_print_greeting_rs".to_string(),
                     path: "cells/io_print_greeting_rs".to_string(),
                     dependencies: vec![],
+                    limits: None,
+                    config: None,
                 },
             ],
             shared_cells: vec![],
+            ..Default::default()
         };
         
         let config_yaml = serde_yaml::to_string(&cli_config).unwrap();
@@ -206,7 +214,9 @@ shared_cells:
         
         let test_app_dir = apps_dir.join("deps_test_app");
         fs::create_dir_all(&test_app_dir).unwrap();
-        fs::create_dir_all(test_app_dir.join("cells/dependent_cell")).unwrap();
+        for cell in ["dependent_cell", "some_service", "another_dep"] {
+            fs::create_dir_all(test_app_dir.join("cells").join(cell)).unwrap();
+        }
         
         let yaml_with_deps = r#"
 name: deps_test_app
@@ -218,14 +228,47 @@ cells:
     dependencies:
       - some_service
       - another_dep
+  - name: some_service
+    path: cells/some_service
+  - name: another_dep
+    path: cells/another_dep
 "#;
         fs::write(test_app_dir.join("app.yaml"), yaml_with_deps).unwrap();
         
         let loader = AppLoader::new(&apps_dir);
         let config = loader.load_application("deps_test_app").unwrap();
         
-        assert_eq!(config.cells.len(), 1);
+        assert_eq!(config.cells.len(), 3);
         assert_eq!(config.cells[0].dependencies, vec!["some_service", "another_dep"]);
+        let order: Vec<&str> = config.startup_order().unwrap().iter().map(|cell| cell.name.as_str()).collect();
+        assert_eq!(order, vec!["some_service", "another_dep", "dependent_cell"]);
+    }
+    
+    #[test]
+    fn test_unknown_dependency_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let apps_dir = temp_dir.path().join("applications");
+        let test_app_dir = apps_dir.join("bad_deps_app");
+        fs::create_dir_all(test_app_dir.join("cells/dependent_cell")).unwrap();
+        
+        let yaml_with_deps = r#"
+name: bad_deps_app
+version: 1.0.0
+description: App depending on a cell it does not list
+cells:
+  - name: dependent_cell
+    path: cells/dependent_cell
+    dependencies: [some_service]
+"#;
+        fs::write(test_app_dir.join("app.yaml"), yaml_with_deps).unwrap();
+        
+        let loader = AppLoader::new(&apps_dir);
+        let error = loader.load_application("bad_deps_app").unwrap_err();
+        
+        assert_eq!(
+            error.to_string(),
+            "Invalid cell dependencies: Cell dependent_cell depends on unknown cell some_service"
+        );
     }
 }
 
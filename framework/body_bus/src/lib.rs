@@ -1,25 +1,39 @@
-use async_nats::{Client, ConnectOptions};
-use body_core::{BodyBus, BusError, Envelope, MessageHandler};
-use futures_util::StreamExt;
+use async_nats::{Client, ConnectOptions, HeaderMap};
+use body_core::cancel::{
+    cancel_key, cancel_signal, cancel_subject, cancelled_request_key, parent_cancelled, InFlightRequests, CANCEL_SUBJECT_PREFIX,
+};
+use body_core::codec::{CODEC_HEADER, ENCODING_HEADER};
+use body_core::scatter::{mark_scatter, scatter_subject, SCATTER_SUBJECT_PREFIX};
+use body_core::stream::{is_end_of_stream, STREAM_BUFFER};
+use body_core::{
+    headers, subject, BodyBus, BusError, CancellationToken, EncodedMessage, Envelope, RequestContext, ResponseStream,
+    ScatterOptions, ScatterReply, SharedHandler, StreamSink, Subscription, WireFormat,
+};
+use futures_util::{Stream, StreamExt};
 use serde_json::Value;
-use std::sync::Arc;
-use tracing::{debug, error, info, warn};
+use std::sync::{Arc, Mutex};
+use tracing::{error, warn};
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, OnceCell, OwnedSemaphorePermit, Semaphore};
 use tokio::time::timeout;
 
 /// NATS-based implementation of the BodyBus trait
+#[derive(Clone)]
 pub struct NatsBus {
     client: Client,
     config: NatsBusConfig,
-    handlers: Arc<RwLock<std::collections::HashMap<String, MessageHandler>>>,
+    /// Tokens of the requests this bus's handlers are working on
+    in_flight: Arc<InFlightRequests>,
+    /// Started with the first `subscribe`; turns cancel signals into cancelled tokens
+    cancel_listener: Arc<OnceCell<()>>,
+    /// Patterns of this bus's live request subscriptions, for most-specific-wins routing
+    request_patterns: Arc<Mutex<Vec<String>>>,
 }
 
 impl std::fmt::Debug for NatsBus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("NatsBus")
             .field("config", &self.config)
-            .field("handlers", &format!("{} handlers", self.handlers.try_read().map_or(0, |h| h.len())))
             .finish()
     }
 }
@@ -32,6 +46,10 @@ pub struct NatsBusConfig {
     pub connection_timeout: Duration,
     pub max_reconnect_attempts: usize,
     pub reconnect_delay: Duration,
+    /// Messages each subscription handles at the same time
+    pub max_concurrent_handlers: usize,
+    /// Codec and compression for outgoing messages; incoming ones are read by their headers
+    pub wire: WireFormat,
 }
 
 impl Default for NatsBusConfig {
@@ -42,6 +60,8 @@ impl Default for NatsBusConfig {
             connection_timeout: Duration::from_secs(10),
             max_reconnect_attempts: 10,
             reconnect_delay: Duration::from_millis(500),
+            max_concurrent_handlers: 64,
+            wire: WireFormat::default(),
         }
     }
 }
@@ -72,7 +92,9 @@ impl NatsBus {
         Ok(Self {
             client,
             config,
-            handlers: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            in_flight: Arc::new(InFlightRequests::new()),
+            cancel_listener: Arc::new(OnceCell::new()),
+            request_patterns: Arc::new(Mutex::new(Vec::new())),
         })
     }
 
@@ -87,20 +109,66 @@ impl NatsBus {
     }
 }
 
-#[async_trait::async_trait]
-impl BodyBus for NatsBus {
-    async fn request(&self, envelope: Envelope) -> Result<Value, BusError> {
+impl NatsBus {
+    /// Listen for cancel signals aimed at any service, once per bus
+    async fn listen_for_cancels(&self) -> Result<(), BusError> {
+        self.cancel_listener
+            .get_or_try_init(|| async {
+                let mut subscriber = self
+                    .client
+                    .subscribe(format!("{}.>", CANCEL_SUBJECT_PREFIX))
+                    .await
+                    .map_err(|e| BusError::Connection(format!("Failed to subscribe: {}", e)))?;
+                let (wire, in_flight) = (self.config.wire.clone(), Arc::clone(&self.in_flight));
+                tokio::spawn(async move {
+                    while let Some(message) = subscriber.next().await {
+                        match decode_message(&wire, &message) {
+                            Ok(signal) => {
+                                if let Some(key) = cancelled_request_key(&signal) {
+                                    in_flight.cancel(&key);
+                                }
+                            }
+                            Err(e) => warn!(error = %e, "Failed to deserialize cancel signal"),
+                        }
+                    }
+                });
+                Ok(())
+            })
+            .await
+            .map(|_| ())
+    }
+
+    /// Publish a request carrying the caller's deadline and metadata in its headers, then decode the reply
+    async fn send_request(
+        &self,
+        mut envelope: Envelope,
+        parent: Option<&RequestContext>,
+    ) -> Result<Value, BusError> {
+        // Never wait longer than the caller has left
+        let request_timeout = parent
+            .and_then(RequestContext::remaining)
+            .map_or(self.config.request_timeout, |remaining| {
+                remaining.min(self.config.request_timeout)
+            });
+        headers::stamp_outbound(&mut envelope, parent, Some(request_timeout));
+
         let subject = envelope.subject();
-        let payload = serde_json::to_vec(&envelope)
-            .map_err(|e| BusError::Serialization(format!("Failed to serialize envelope: {}", e)))?;
+        let message = self.config.wire.encode(&envelope)?;
+        let mut request = async_nats::Request::new();
+        if let Some(headers) = nats_headers(&message) {
+            request = request.headers(headers);
+        }
+        let request = request.payload(message.body.into());
 
-        let response = timeout(
-            self.config.request_timeout,
-            self.client.request(subject, payload.into()),
-        )
-        .await
-        .map_err(|_| BusError::Timeout)?
-        .map_err(|e| {
+        // Giving up on the reply, or dropping this future, tells the handler to stop
+        let pending = PendingCancel::new(&self.client, &self.config.wire, &envelope);
+        let exchange = timeout(request_timeout, self.client.send_request(subject, request));
+        let response = tokio::select! {
+            response = exchange => response.map_err(|_| BusError::Timeout)?,
+            _ = parent_cancelled(parent) => return Err(BusError::Cancelled),
+        };
+        pending.disarm();
+        let response = response.map_err(|e| {
             let error_str = e.to_string();
             if error_str.contains("no responders") {
                 BusError::NotFound("No subscribers for subject".to_string())
@@ -109,118 +177,505 @@ impl BodyBus for NatsBus {
             }
         })?;
 
-        let response_envelope: Envelope = serde_json::from_slice(&response.payload)
-            .map_err(|e| BusError::Serialization(format!("Failed to deserialize response: {}", e)))?;
+        let response_envelope = decode_message(&self.config.wire, &response)?;
 
         if response_envelope.is_error() {
-            let error = response_envelope.error.unwrap();
-            match error.code.as_str() {
-                "BadRequest" => Err(BusError::BadRequest(error.message)),
-                "NotFound" => Err(BusError::NotFound(error.message)),
-                "Timeout" => Err(BusError::Timeout),
-                _ => Err(BusError::Internal(error.message)),
-            }
+            Err(BusError::from_error_details(response_envelope.error.unwrap()))
         } else {
             response_envelope.payload.ok_or_else(|| {
                 BusError::Internal("Response envelope missing payload".to_string())
             })
         }
     }
+}
 
-    async fn subscribe(&self, subject: &str, handler: MessageHandler) -> Result<(), BusError> {
-        let subject_owned = subject.to_string();
-        
-        // Store handler for potential cleanup
-        {
-            let mut handlers = self.handlers.write().await;
-            handlers.insert(subject_owned.clone(), handler);
-        }
+#[async_trait::async_trait]
+impl BodyBus for NatsBus {
+    async fn request(&self, envelope: Envelope) -> Result<Value, BusError> {
+        self.send_request(envelope, None).await
+    }
 
-        let handlers_ref = Arc::clone(&self.handlers);
-        let subject_for_handler = subject_owned.clone();
+    async fn request_with_context(
+        &self,
+        envelope: Envelope,
+        parent: &RequestContext,
+    ) -> Result<Value, BusError> {
+        self.send_request(envelope, Some(parent)).await
+    }
+
+    async fn subscribe(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError> {
+        subject::validate_pattern(subject)?;
+        self.listen_for_cancels().await?;
+        let subscription = Subscription::new(subject);
+        let stats = subscription.stats();
+        let cancellation = subscription.cancellation();
         let client_ref = self.client.clone();
+        let wire = self.config.wire.clone();
+        let in_flight = Arc::clone(&self.in_flight);
+        let bus_ref: Arc<dyn BodyBus> = Arc::new(self.clone());
+        let slots = Arc::new(Semaphore::new(self.config.max_concurrent_handlers.max(1)));
 
-        let mut subscriber = self
+        let queued = self
             .client
-            .queue_subscribe(subject_owned.clone(), extract_queue_group(&subject_owned))
+            .queue_subscribe(subject.to_string(), queue_group(subject))
             .await
             .map_err(|e| BusError::Connection(format!("Failed to subscribe: {}", e)))?;
+        // Scatter requests reach every instance, so they bypass the queue group
+        let scattered = self
+            .client
+            .subscribe(scatter_subject(subject))
+            .await
+            .map_err(|e| BusError::Connection(format!("Failed to subscribe: {}", e)))?;
+        let responder = uuid::Uuid::new_v4().to_string();
+        let pattern = subject.to_string();
+        let patterns = Arc::clone(&self.request_patterns);
+        patterns.lock().unwrap().push(pattern.clone());
 
         tokio::spawn(async move {
-            while let Some(message) = subscriber.next().await {
-                let envelope: Envelope = match serde_json::from_slice(&message.payload) {
+            let mut subscriber = futures_util::stream::select(queued, scattered);
+            while let Some((message, permit)) = next_message(&mut subscriber, &slots, &cancellation).await {
+                let envelope = match decode_message(&wire, &message) {
                     Ok(env) => env,
                     Err(e) => {
                         error!(error = %e, "Failed to deserialize message");
+                        stats.record_undeliverable();
                         continue;
                     }
                 };
+                // A more specific subscription on this bus handles it, as on LocalBus
+                if defers_to_more_specific(&patterns, &pattern, &envelope.subject()) {
+                    continue;
+                }
+
+                let mut ctx = RequestContext::for_request(Arc::clone(&bus_ref), &envelope, None);
+                let token = ctx.cancellation().clone();
+                let registration = in_flight.register(&cancel_key(&envelope), token.clone());
+                let sink = envelope
+                    .header(headers::REPLY_TO)
+                    .map(|inbox| stream_to(&client_ref, &wire, inbox, &envelope, token.clone()));
+                if let Some(sink) = &sink {
+                    ctx = ctx.with_stream_sink(sink.clone());
+                }
+                let (handler, stats, client, wire, responder) = (
+                    Arc::clone(&handler),
+                    Arc::clone(&stats),
+                    client_ref.clone(),
+                    wire.clone(),
+                    responder.clone(),
+                );
+                tokio::spawn(async move {
+                    let response = handler.handle(envelope.clone(), ctx).await;
+                    stats.record(&response);
+                    drop(permit);
+                    drop(registration);
+                    if let Some(sink) = sink {
+                        sink.complete(response).await;
+                        return;
+                    }
+                    // Nobody is waiting for the reply of a cancelled request
+                    if token.is_cancelled() {
+                        return;
+                    }
+                    let mut response_envelope = reply_envelope(&envelope, response);
+                    if message.subject.starts_with(SCATTER_SUBJECT_PREFIX) {
+                        response_envelope
+                            .set_header(headers::RESPONDER, &responder)
+                            .expect("responder is not a reserved header");
+                    }
 
-                let response = {
-                    let handlers = handlers_ref.read().await;
-                    if let Some(handler) = handlers.get(&subject_for_handler) {
-                        handler(envelope.clone())
-                    } else {
-                        Err(BusError::NotFound("Handler not found".to_string()))
+                    match wire.encode(&response_envelope) {
+                        Ok(encoded) => {
+                            if let Some(reply) = message.reply {
+                                if let Err(e) = publish_encoded(&client, reply, encoded).await {
+                                    error!(error = %e, "Failed to send response");
+                                }
+                            }
+                        }
+                        Err(e) => error!(error = %e, "Failed to serialize response"),
                     }
+                });
+            }
+            let (queued, scattered) = subscriber.into_inner();
+            close_subscriber(queued).await;
+            close_subscriber(scattered).await;
+            let mut active = patterns.lock().unwrap();
+            if let Some(index) = active.iter().position(|active| *active == pattern) {
+                active.remove(index);
+            }
+        });
+
+        Ok(subscription)
+    }
+
+    /// Items arrive on a private inbox named in the request's `reply_to` header
+    async fn request_stream(&self, mut envelope: Envelope) -> Result<ResponseStream, BusError> {
+        let inbox = self.client.new_inbox();
+        let mut subscriber = self
+            .client
+            .subscribe(inbox.clone())
+            .await
+            .map_err(|e| BusError::Connection(format!("Failed to subscribe: {}", e)))?;
+
+        headers::stamp_outbound(&mut envelope, None, None);
+        envelope.set_header(headers::REPLY_TO, &inbox)?;
+        let message = self.config.wire.encode(&envelope)?;
+        publish_encoded(&self.client, envelope.subject(), message).await?;
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let wire = self.config.wire.clone();
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    _ = sender.closed() => {
+                        // Dropped before the end of the stream: tell the handler to stop
+                        let signal = cancel_signal(&envelope);
+                        if let Err(e) = publish_cancel(&client, &wire, &signal).await {
+                            warn!(id = %envelope.id, error = %e, "Failed to send cancel signal");
+                        }
+                        break;
+                    }
+                    message = subscriber.next() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
                 };
+                match decode_message(&wire, &message) {
+                    Ok(item) => {
+                        let end = is_end_of_stream(&item);
+                        if sender.send(item).await.is_err() || end {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!(error = %e, "Failed to deserialize stream item");
+                        break;
+                    }
+                }
+            }
+            close_subscriber(subscriber).await;
+        });
+
+        Ok(ResponseStream::new(receiver, Some(self.config.request_timeout)))
+    }
+
+    /// Publishes on the scatter subject with a private inbox as the reply subject; NATS cannot tell
+    /// how many instances exist, so without `expected` this waits out the whole timeout
+    async fn request_all(&self, mut envelope: Envelope, options: ScatterOptions) -> Result<Vec<ScatterReply>, BusError> {
+        let request_timeout = options.timeout.unwrap_or(self.config.request_timeout);
+        let inbox = self.client.new_inbox();
+        let mut subscriber = self
+            .client
+            .subscribe(inbox.clone())
+            .await
+            .map_err(|e| BusError::Connection(format!("Failed to subscribe: {}", e)))?;
+
+        headers::stamp_outbound(&mut envelope, None, Some(request_timeout));
+        mark_scatter(&mut envelope);
+        let message = self.config.wire.encode(&envelope)?;
+        publish_encoded_with_reply(&self.client, scatter_subject(&envelope.subject()), inbox, message).await?;
+
+        let mut replies = Vec::new();
+        let deadline = tokio::time::sleep(request_timeout);
+        tokio::pin!(deadline);
+        while options.expected.is_none_or(|expected| replies.len() < expected) {
+            let message = tokio::select! {
+                _ = &mut deadline => break,
+                message = subscriber.next() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+            match decode_message(&self.config.wire, &message) {
+                Ok(reply) => replies.push(ScatterReply {
+                    responder: reply.header(headers::RESPONDER).unwrap_or("unknown").to_string(),
+                    result: match reply.error {
+                        Some(error) => Err(BusError::from_error_details(error)),
+                        None => reply.payload.ok_or_else(|| {
+                            BusError::Internal("Response envelope missing payload".to_string())
+                        }),
+                    },
+                }),
+                Err(e) => error!(error = %e, "Failed to deserialize scatter reply"),
+            }
+        }
+        close_subscriber(subscriber).await;
+        Ok(replies)
+    }
+
+    async fn publish(&self, mut envelope: Envelope) -> Result<(), BusError> {
+        headers::stamp_outbound(&mut envelope, None, None);
+        let subject = envelope.subject();
+        let message = self.config.wire.encode(&envelope)?;
+
+        publish_encoded(&self.client, subject, message).await
+    }
+
+    async fn subscribe_events(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError> {
+        subject::validate_pattern(subject)?;
+        let subscription = Subscription::new(subject);
+        let stats = subscription.stats();
+        let cancellation = subscription.cancellation();
+        let wire = self.config.wire.clone();
+        let bus_ref: Arc<dyn BodyBus> = Arc::new(self.clone());
+        let slots = Arc::new(Semaphore::new(self.config.max_concurrent_handlers.max(1)));
+
+        // No queue group: every event subscriber receives its own copy
+        let mut subscriber = self
+            .client
+            .subscribe(subject.to_string())
+            .await
+            .map_err(|e| BusError::Connection(format!("Failed to subscribe: {}", e)))?;
 
-                let response_envelope = match response {
-                    Ok(payload) => Envelope::new_response(
-                        &envelope.id,
-                        &envelope.service,
-                        &envelope.verb,
-                        &envelope.schema,
-                        payload,
-                    ),
+        tokio::spawn(async move {
+            while let Some((message, permit)) = next_message(&mut subscriber, &slots, &cancellation).await {
+                let envelope = match decode_message(&wire, &message) {
+                    Ok(env) => env,
                     Err(e) => {
-                        let error_details = body_core::ErrorDetails::new(
-                            &match e {
-                                BusError::BadRequest(_) => "BadRequest",
-                                BusError::NotFound(_) => "NotFound", 
-                                BusError::Timeout => "Timeout",
-                                _ => "Internal",
-                            },
-                            &e.to_string(),
-                        );
-                        Envelope::new_error(
-                            &envelope.id,
-                            &envelope.service,
-                            &envelope.verb,
-                            &envelope.schema,
-                            error_details,
-                        )
+                        error!(error = %e, "Failed to deserialize event");
+                        stats.record_undeliverable();
+                        continue;
                     }
                 };
 
-                if let Ok(response_payload) = serde_json::to_vec(&response_envelope) {
-                    if let Some(reply) = message.reply {
-                        if let Err(e) = client_ref.publish(reply, response_payload.into()).await {
-                            error!(error = %e, "Failed to send response");
-                        }
+                let ctx = RequestContext::for_request(Arc::clone(&bus_ref), &envelope, None);
+                let (handler, stats) = (Arc::clone(&handler), Arc::clone(&stats));
+                tokio::spawn(async move {
+                    let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+                    let result = handler.handle(envelope, ctx).await;
+                    stats.record(&result);
+                    drop(permit);
+                    if let Err(e) = result {
+                        warn!(id = %id, service = %service, verb = %verb, error = %e, "Event handler failed");
                     }
-                }
+                });
+            }
+            close_subscriber(subscriber).await;
+        });
+
+        Ok(subscription)
+    }
+}
+
+/// Wait for a free handler slot and the next message, unless the subscription handle has been released
+async fn next_message(
+    subscriber: &mut (impl Stream<Item = async_nats::Message> + Unpin),
+    slots: &Arc<Semaphore>,
+    cancellation: &CancellationToken,
+) -> Option<(async_nats::Message, OwnedSemaphorePermit)> {
+    let permit = tokio::select! {
+        _ = cancellation.cancelled() => return None,
+        permit = Arc::clone(slots).acquire_owned() => permit.ok()?,
+    };
+    tokio::select! {
+        _ = cancellation.cancelled() => None,
+        message = subscriber.next() => message.map(|message| (message, permit)),
+    }
+}
+
+/// Sink whose items are published, in order, to the caller's stream inbox until `cancelled`
+fn stream_to(
+    client: &Client,
+    wire: &WireFormat,
+    inbox: &str,
+    request: &Envelope,
+    cancelled: CancellationToken,
+) -> StreamSink {
+    let (sender, mut items) = mpsc::channel::<Envelope>(STREAM_BUFFER);
+    let (client, wire, inbox) = (client.clone(), wire.clone(), inbox.to_string());
+    tokio::spawn(async move {
+        loop {
+            let item = tokio::select! {
+                _ = cancelled.cancelled() => break,
+                item = items.recv() => match item {
+                    Some(item) => item,
+                    None => break,
+                },
+            };
+            let end = is_end_of_stream(&item);
+            let sent = match wire.encode(&item) {
+                Ok(message) => publish_encoded(&client, inbox.clone(), message).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                error!(id = %item.id, service = %item.service, verb = %item.verb, error = %e, "Failed to send stream item");
+                break;
+            }
+            if end {
+                break;
+            }
+        }
+    });
+    StreamSink::new(request, sender)
+}
+
+/// Cancel signal sent for a request unless disarmed, including when the caller's future is dropped
+struct PendingCancel {
+    client: Client,
+    wire: WireFormat,
+    request: Option<Envelope>,
+}
+
+impl PendingCancel {
+    fn new(client: &Client, wire: &WireFormat, request: &Envelope) -> Self {
+        Self {
+            client: client.clone(),
+            wire: wire.clone(),
+            request: Some(request.clone()),
+        }
+    }
+
+    /// The handler replied; nothing to cancel
+    fn disarm(mut self) {
+        self.request = None;
+    }
+}
+
+impl Drop for PendingCancel {
+    fn drop(&mut self) {
+        let (Some(request), Ok(runtime)) = (self.request.take(), tokio::runtime::Handle::try_current()) else {
+            return;
+        };
+        let (client, wire) = (self.client.clone(), self.wire.clone());
+        runtime.spawn(async move {
+            if let Err(e) = publish_cancel(&client, &wire, &cancel_signal(&request)).await {
+                warn!(id = %request.id, service = %request.service, verb = %request.verb, error = %e, "Failed to send cancel signal");
             }
         });
+    }
+}
+
+/// Publish a cancel signal to the service handling the request
+async fn publish_cancel(client: &Client, wire: &WireFormat, signal: &Envelope) -> Result<(), BusError> {
+    let message = wire.encode(signal)?;
+    publish_encoded(client, cancel_subject(&signal.service), message).await
+}
+
+/// NATS headers tagging the codec and compression of an encoded body; plain JSON needs none
+fn nats_headers(message: &EncodedMessage) -> Option<HeaderMap> {
+    if message.headers.is_empty() {
+        return None;
+    }
+    let mut headers = HeaderMap::new();
+    for (name, value) in &message.headers {
+        headers.insert(*name, *value);
+    }
+    Some(headers)
+}
+
+async fn publish_encoded(client: &Client, subject: String, message: EncodedMessage) -> Result<(), BusError> {
+    let sent = match nats_headers(&message) {
+        Some(headers) => client.publish_with_headers(subject, headers, message.body.into()).await,
+        None => client.publish(subject, message.body.into()).await.map_err(Into::into),
+    };
+    sent.map_err(|e| BusError::Connection(format!("NATS publish failed: {}", e)))
+}
+
+async fn publish_encoded_with_reply(
+    client: &Client,
+    subject: String,
+    reply: String,
+    message: EncodedMessage,
+) -> Result<(), BusError> {
+    let publish_error = |e: &dyn std::fmt::Display| BusError::Connection(format!("NATS publish failed: {}", e));
+    match nats_headers(&message) {
+        Some(headers) => client
+            .publish_with_reply_and_headers(subject, reply, headers, message.body.into())
+            .await
+            .map_err(|e| publish_error(&e)),
+        None => client
+            .publish_with_reply(subject, reply, message.body.into())
+            .await
+            .map_err(|e| publish_error(&e)),
+    }
+}
+
+/// Decode a message body according to its codec and compression headers
+fn decode_message(wire: &WireFormat, message: &async_nats::Message) -> Result<Envelope, BusError> {
+    let header = |name: &str| {
+        message
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.get(name))
+            .and_then(|value| value.iter().next())
+            .map(String::as_str)
+    };
+    wire.decode(&message.payload, header(CODEC_HEADER), header(ENCODING_HEADER))
+}
 
-        Ok(())
+/// Tell the server to stop routing messages to a finished subscriber
+async fn close_subscriber(mut subscriber: async_nats::Subscriber) {
+    if let Err(e) = subscriber.unsubscribe().await {
+        warn!(error = %e, "Failed to unsubscribe from NATS");
     }
 }
 
-/// Extract queue group name from subject (service part)
-fn extract_queue_group(subject: &str) -> String {
-    // For subject "cbs.service.verb", extract "service" as queue group
-    if let Some(parts) = subject.strip_prefix("cbs.") {
-        if let Some(service) = parts.split('.').next() {
-            return service.to_string();
+/// Build the reply envelope for a handled request, correlated through its headers
+fn reply_envelope(request: &Envelope, response: Result<Value, BusError>) -> Envelope {
+    let mut reply = match response {
+        Ok(payload) => Envelope::new_response(
+            &request.id,
+            &request.service,
+            &request.verb,
+            &request.schema,
+            payload,
+        ),
+        Err(e) => {
+            Envelope::new_error(
+                &request.id,
+                &request.service,
+                &request.verb,
+                &request.schema,
+                e.to_error_details(),
+            )
         }
+    };
+    headers::stamp_reply(&mut reply, request);
+    reply
+}
+
+/// Queue group of a request subscription
+///
+/// Instances subscribed to the same exact `cbs.{service}.{verb}` share the
+/// `{service}` group. Each wildcard pattern gets a group of its own, named
+/// after the whole pattern with `*`, `>` and `%` percent-encoded, so
+/// `cbs.*.health` and `cbs.>` never compete with each other or with exact
+/// subscribers; the `pattern.` prefix keeps these names apart from service names.
+fn queue_group(pattern: &str) -> String {
+    if subject::has_wildcards(pattern) {
+        let tokens: Vec<String> = pattern
+            .split('.')
+            .map(|token| match token {
+                subject::SINGLE_WILDCARD => "%2A".to_string(),
+                subject::TAIL_WILDCARD => "%3E".to_string(),
+                literal => literal.replace('%', "%25"),
+            })
+            .collect();
+        return format!("pattern.{}", tokens.join("."));
+    }
+    subject::service_token(pattern)
+        .unwrap_or("default")
+        .to_string()
+}
+
+/// Whether a wildcard `pattern` should leave `subject` to a more specific live subscription
+///
+/// NATS delivers a request to one member of every matching queue group;
+/// LocalBus only to the most specific pattern. Within one bus the handlers
+/// that are not the best match stay silent so both buses agree.
+fn defers_to_more_specific(patterns: &Mutex<Vec<String>>, pattern: &str, subject: &str) -> bool {
+    if !subject::has_wildcards(pattern) {
+        return false;
     }
-    "default".to_string()
+    let patterns = patterns.lock().unwrap();
+    subject::best_match(patterns.iter().map(String::as_str), subject).is_some_and(|best| best != pattern)
 }
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use body_core::test_support::NullBus;
     use body_core::Envelope;
     use serde_json::json;
 
@@ -238,12 +693,91 @@ mod tests {
     }
 
     #[test]
-    fn extract_queue_group_from_subject() {
-        assert_eq!(extract_queue_group("cbs.greeter.say_hello"), "greeter");
-        assert_eq!(extract_queue_group("cbs.prompt_name.read"), "prompt_name");
-        assert_eq!(extract_queue_group("cbs.printer.write"), "printer");
-        assert_eq!(extract_queue_group("invalid.subject"), "default");
-        assert_eq!(extract_queue_group("cbs.service"), "service");
+    fn queue_group_from_subject() {
+        assert_eq!(queue_group("cbs.greeter.say_hello"), "greeter");
+        assert_eq!(queue_group("cbs.prompt_name.read"), "prompt_name");
+        assert_eq!(queue_group("cbs.printer.write"), "printer");
+        assert_eq!(queue_group("invalid.subject"), "default");
+        assert_eq!(queue_group("cbs.service"), "service");
+    }
+
+    #[test]
+    fn wildcard_patterns_get_their_own_queue_group() {
+        assert_eq!(queue_group("cbs.*.health"), "pattern.cbs.%2A.health");
+        assert_eq!(queue_group("cbs.greeter.*"), "pattern.cbs.greeter.%2A");
+        assert_eq!(queue_group("cbs.>"), "pattern.cbs.%3E");
+        assert_ne!(queue_group("cbs.%2A.*"), queue_group("cbs.*.*"));
+    }
+
+    #[test]
+    fn wildcards_defer_to_more_specific_subscriptions() {
+        let patterns = Mutex::new(vec![
+            "cbs.>".to_string(),
+            "cbs.*.health".to_string(),
+            "cbs.greeter.health".to_string(),
+        ]);
+        assert!(defers_to_more_specific(&patterns, "cbs.>", "cbs.greeter.health"));
+        assert!(defers_to_more_specific(&patterns, "cbs.*.health", "cbs.greeter.health"));
+        assert!(!defers_to_more_specific(&patterns, "cbs.greeter.health", "cbs.greeter.health"));
+        assert!(!defers_to_more_specific(&patterns, "cbs.*.health", "cbs.printer.health"));
+        assert!(defers_to_more_specific(&patterns, "cbs.>", "cbs.printer.health"));
+        assert!(!defers_to_more_specific(&patterns, "cbs.>", "cbs.printer.write"));
+    }
+
+    #[test]
+    fn reply_envelope_is_correlated_with_request() {
+        let mut request = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+        headers::stamp_outbound(&mut request, None, None);
+
+        let reply = reply_envelope(&request, Ok(json!({"ok": true})));
+        assert_eq!(reply.id, request.id);
+        assert_eq!(reply.header(headers::CORRELATION_ID), Some(request.id.as_str()));
+        assert_eq!(reply.header(headers::TRACE_ID), request.header(headers::TRACE_ID));
+        assert_eq!(reply.payload.unwrap()["ok"], true);
+    }
+
+    #[test]
+    fn error_reply_envelope_carries_code() {
+        let request = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+        let reply = reply_envelope(&request, Err(BusError::bad_request("nope")));
+        assert!(reply.is_error());
+        assert_eq!(reply.header(headers::CORRELATION_ID), Some(request.id.as_str()));
+        assert_eq!(reply.error.unwrap().code, "BadRequest");
+    }
+
+    #[test]
+    fn error_reply_envelope_keeps_code_message_and_details() {
+        let request = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+        let error = BusError::Conflict {
+            message: "version mismatch".to_string(),
+            details: Some(json!({"expected": 2})),
+        };
+        let reply = reply_envelope(&request, Err(error));
+        let details = reply.error.unwrap();
+        assert_eq!(details.code, "Conflict");
+        assert_eq!(details.message, "version mismatch");
+        assert_eq!(details.details, Some(json!({"expected": 2})));
+        assert_eq!(details.http_status(), 409);
+    }
+
+    #[test]
+    fn inbound_context_is_rebuilt_from_envelope_headers() {
+        let bus: Arc<dyn BodyBus> = Arc::new(NullBus);
+        let parent = RequestContext::new(Arc::clone(&bus))
+            .with_timeout(Duration::from_secs(2))
+            .with_metadata("trace_id", "trace-1").unwrap();
+        let mut envelope = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+        headers::stamp_outbound(&mut envelope, Some(&parent), parent.remaining());
+
+        // Round-trip through the wire format before rebuilding the context
+        let wire = serde_json::to_vec(&envelope).unwrap();
+        let envelope: Envelope = serde_json::from_slice(&wire).unwrap();
+        let ctx = RequestContext::for_request(bus, &envelope, None);
+
+        assert_eq!(ctx.trace_id(), Some("trace-1"));
+        let remaining = ctx.remaining().unwrap();
+        assert!(remaining <= Duration::from_secs(2));
+        assert!(remaining > Duration::from_secs(1));
     }
 
     #[test]
@@ -253,5 +787,23 @@ mod tests {
         assert_eq!(config.request_timeout, Duration::from_secs(5));
         assert_eq!(config.connection_timeout, Duration::from_secs(10));
         assert_eq!(config.max_reconnect_attempts, 10);
+        assert_eq!(config.wire.codec().name(), "json");
+    }
+
+    #[test]
+    fn codec_headers_are_read_back_from_nats_messages() {
+        let envelope = Envelope::new_request("test", "action", "demo/v1/Test", json!({"n": 1}));
+        assert!(nats_headers(&WireFormat::default().encode(&envelope).unwrap()).is_none());
+
+        let encoded = WireFormat::named("cbor").unwrap().encode(&envelope).unwrap();
+        let message = async_nats::Message {
+            subject: envelope.subject(),
+            reply: None,
+            headers: nats_headers(&encoded),
+            payload: encoded.body.into(),
+            status: None,
+            description: None,
+        };
+        assert_eq!(decode_message(&WireFormat::default(), &message).unwrap(), envelope);
     }
 }
\ No newline at end of file
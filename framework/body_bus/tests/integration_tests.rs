@@ -1,5 +1,8 @@
 use body_bus::{NatsBus, NatsBusConfig};
-use body_core::{BodyBus, BusError, Envelope, MessageHandler};
+use body_core::{
+    async_handler, headers, stream_handler, sync_handler, BodyBus, BusError, Envelope, MetricsMiddleware, MiddlewareBus,
+    RequestContext, ScatterOptions, WireFormat,
+};
 use serde_json::json;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
@@ -66,11 +69,11 @@ async fn request_reply_success() {
     let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
     
     // Set up a simple echo handler
-    let echo_handler: MessageHandler = Box::new(|envelope| {
+    let echo_handler = sync_handler(|envelope| {
         Ok(envelope.payload.unwrap_or(json!({})))
     });
     
-    bus.subscribe("cbs.test.echo", echo_handler).await.unwrap();
+    let _echo = bus.subscribe("cbs.test.echo", echo_handler).await.unwrap();
     
     // Give subscription time to register
     sleep(Duration::from_millis(100)).await;
@@ -102,6 +105,183 @@ async fn request_timeout_no_subscribers() {
     }
 }
 
+#[tokio::test]
+async fn request_reply_async_handler() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    
+    // Handler awaits before replying without blocking the subscriber task
+    let slow_handler = async_handler(|envelope: Envelope, _ctx| async move {
+        sleep(Duration::from_millis(20)).await;
+        Ok(json!({"verb": envelope.verb}))
+    });
+    
+    let _slow = bus.subscribe("cbs.test.slow_echo", slow_handler).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("test", "slow_echo", "demo/v1/Test", json!({}));
+    let response = bus.request(request).await.unwrap();
+    
+    assert_eq!(response["verb"], "slow_echo");
+}
+
+#[tokio::test]
+async fn nested_request_propagates_context() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    
+    let inner_handler = async_handler(|envelope: Envelope, ctx: RequestContext| async move {
+        Ok(json!({
+            "trace_id": ctx.trace_id(),
+            "tenant": ctx.metadata_value("tenant"),
+            "causation_id": envelope.header(headers::CAUSATION_ID),
+            "remaining_ms": ctx.remaining().map(|r| r.as_millis() as u64),
+        }))
+    });
+    let outer_handler = async_handler(|envelope: Envelope, ctx: RequestContext| async move {
+        let nested = Envelope::new_request("ctxtest", "inner", "demo/v1/Test", json!({}));
+        let mut response = ctx.request(nested).await?;
+        response["outer_id"] = json!(envelope.id);
+        Ok(response)
+    });
+    
+    let _inner = bus.subscribe("cbs.ctxtest.inner", inner_handler).await.unwrap();
+    let _outer = bus.subscribe("cbs.ctxtest.outer", outer_handler).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("ctxtest", "outer", "demo/v1/Test", json!({}))
+        .with_header("tenant", "acme").unwrap();
+    let root_id = request.id.clone();
+    let response = bus.request(request).await.unwrap();
+    
+    assert_eq!(response["trace_id"], root_id);
+    assert_eq!(response["tenant"], "acme");
+    assert_eq!(response["causation_id"], response["outer_id"]);
+    assert!(response["remaining_ms"].as_u64().unwrap() <= 5000);
+}
+
+#[tokio::test]
+async fn publish_fans_out_to_event_subscribers() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let received = Arc::new(AtomicUsize::new(0));
+    
+    let mut subscriptions = Vec::new();
+    for _ in 0..2 {
+        let received = Arc::clone(&received);
+        subscriptions.push(bus.subscribe_events("cbs.eventtest.happened", sync_handler(move |_| {
+            received.fetch_add(1, Ordering::SeqCst);
+            Ok(json!({}))
+        })).await.unwrap());
+    }
+    
+    // Request/reply on the same service keeps working alongside the events
+    let _query = bus.subscribe("cbs.eventtest.query", sync_handler(|_| Ok(json!({"ok": true})))).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let event = Envelope::new_request("eventtest", "happened", "demo/v1/Test", json!({}));
+    bus.publish(event).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    assert_eq!(received.load(Ordering::SeqCst), 2);
+    
+    let request = Envelope::new_request("eventtest", "query", "demo/v1/Test", json!({}));
+    assert_eq!(bus.request(request).await.unwrap()["ok"], true);
+}
+
+#[tokio::test]
+async fn wildcard_event_subscription() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let received = Arc::new(AtomicUsize::new(0));
+    
+    let counter = Arc::clone(&received);
+    let _monitor = bus.subscribe_events("cbs.wildtest.>", sync_handler(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(json!({}))
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    for verb in ["created", "deleted"] {
+        let event = Envelope::new_request("wildtest", verb, "demo/v1/Test", json!({}));
+        bus.publish(event).await.unwrap();
+    }
+    sleep(Duration::from_millis(100)).await;
+    
+    assert_eq!(received.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn wildcard_request_subscription() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let _health = bus.subscribe("cbs.*.wildhealth", sync_handler(|envelope| {
+        Ok(json!({"service": envelope.service}))
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("printer", "wildhealth", "demo/v1/Test", json!({}));
+    assert_eq!(bus.request(request).await.unwrap()["service"], "printer");
+}
+
+#[tokio::test]
+async fn most_specific_request_subscription_wins() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let _wildcard = bus.subscribe("cbs.*.pickhealth", sync_handler(|_| Ok(json!({"from": "wildcard"})))).await.unwrap();
+    let _exact = bus.subscribe("cbs.picky.pickhealth", sync_handler(|_| Ok(json!({"from": "exact"})))).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    // Both patterns have their own queue group, but only the best match replies
+    for _ in 0..10 {
+        let request = Envelope::new_request("picky", "pickhealth", "demo/v1/Test", json!({}));
+        assert_eq!(bus.request(request).await.unwrap()["from"], "exact");
+    }
+    let request = Envelope::new_request("other", "pickhealth", "demo/v1/Test", json!({}));
+    assert_eq!(bus.request(request).await.unwrap()["from"], "wildcard");
+}
+
+#[tokio::test]
+async fn invalid_subject_pattern_rejected() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let result = bus.subscribe("cbs.>.broken", sync_handler(|_| Ok(json!({})))).await;
+    assert!(matches!(result, Err(BusError::BadRequest { .. })));
+}
+
+#[tokio::test]
+async fn unsubscribe_stops_delivery() {
+    require_nats!();
+    
+    let config = NatsBusConfig {
+        request_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let bus = NatsBus::connect_with_config(config).await.unwrap();
+    let subscription = bus.subscribe("cbs.unsubtest.ping", sync_handler(|_| Ok(json!({"pong": true})))).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("unsubtest", "ping", "demo/v1/Test", json!({}));
+    assert_eq!(bus.request(request.clone()).await.unwrap()["pong"], true);
+    assert_eq!(subscription.delivered(), 1);
+    assert_eq!(subscription.failed(), 0);
+    
+    subscription.unsubscribe();
+    sleep(Duration::from_millis(100)).await;
+    
+    match bus.request(request).await {
+        Err(BusError::NotFound(_)) | Err(BusError::Timeout) => {}
+        other => panic!("Expected no responders after unsubscribe, got: {:?}", other),
+    }
+}
+
 #[tokio::test]
 async fn queue_group_load_balancing() {
     require_nats!();
@@ -112,20 +292,20 @@ async fn queue_group_load_balancing() {
     
     // Create two handlers that increment the same counter
     let counter1 = Arc::clone(&counter);
-    let handler1: MessageHandler = Box::new(move |_| {
+    let handler1 = sync_handler(move |_| {
         counter1.fetch_add(1, Ordering::SeqCst);
         Ok(json!({"handler": 1}))
     });
     
     let counter2 = Arc::clone(&counter);
-    let handler2: MessageHandler = Box::new(move |_| {
+    let handler2 = sync_handler(move |_| {
         counter2.fetch_add(1, Ordering::SeqCst);
         Ok(json!({"handler": 2}))
     });
     
     // Both handlers subscribe to the same subject (same queue group)
-    bus.subscribe("cbs.loadtest.work", handler1).await.unwrap();
-    bus.subscribe("cbs.loadtest.work", handler2).await.unwrap();
+    let _first = bus.subscribe("cbs.loadtest.work", handler1).await.unwrap();
+    let _second = bus.subscribe("cbs.loadtest.work", handler2).await.unwrap();
     
     sleep(Duration::from_millis(100)).await;
     
@@ -151,11 +331,11 @@ async fn error_propagation_in_handler() {
     let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
     
     // Handler that always returns an error
-    let error_handler: MessageHandler = Box::new(|_| {
-        Err(BusError::BadRequest("Invalid input".to_string()))
+    let error_handler = sync_handler(|_| {
+        Err(BusError::bad_request("Invalid input"))
     });
     
-    bus.subscribe("cbs.error.test", error_handler).await.unwrap();
+    let _errors = bus.subscribe("cbs.error.test", error_handler).await.unwrap();
     sleep(Duration::from_millis(100)).await;
     
     let request = Envelope::new_request("error", "test", "demo/v1/Test", json!({}));
@@ -163,11 +343,222 @@ async fn error_propagation_in_handler() {
     
     assert!(result.is_err());
     match result.unwrap_err() {
-        BusError::BadRequest(msg) => assert_eq!(msg, "Invalid input"),
+        BusError::BadRequest { message: msg, .. } => assert_eq!(msg, "Invalid input"),
         e => panic!("Expected BadRequest error, got: {:?}", e),
     }
 }
 
+#[tokio::test]
+async fn bad_request_details_survive_the_wire() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let _strict = bus.subscribe("cbs.error.strict", sync_handler(|_| {
+        Err(BusError::bad_request_with_details("Invalid name", json!({"fields": [{"path": "name"}]})))
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("error", "strict", "demo/v1/Test", json!({}));
+    match bus.request(request).await {
+        Err(BusError::BadRequest { details: Some(details), .. }) => {
+            assert_eq!(details["fields"][0]["path"], "name");
+        }
+        other => panic!("Expected BadRequest with details, got: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn custom_error_codes_survive_the_wire() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let _quota = bus.subscribe("cbs.error.quota", sync_handler(|_| {
+        Err(BusError::Custom {
+            code: "QuotaExceeded".to_string(),
+            message: "Monthly quota used".to_string(),
+            details: Some(json!({"limit": 100})),
+        })
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("error", "quota", "demo/v1/Test", json!({}));
+    match bus.request(request).await {
+        Err(BusError::Custom { code, message, details }) => {
+            assert_eq!(code, "QuotaExceeded");
+            assert_eq!(message, "Monthly quota used");
+            assert_eq!(details, Some(json!({"limit": 100})));
+        }
+        other => panic!("Expected Custom error, got: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn middleware_wraps_nats_bus() {
+    require_nats!();
+    
+    let metrics = Arc::new(MetricsMiddleware::new());
+    let nats = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let bus = MiddlewareBus::new(Arc::new(nats)).layer_shared(metrics.clone());
+    let _echo = bus.subscribe("cbs.middleware.echo", sync_handler(|envelope| {
+        Ok(envelope.payload.unwrap_or(json!({})))
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("middleware", "echo", "demo/v1/Test", json!({"n": 1}));
+    assert_eq!(bus.request(request).await.unwrap(), json!({"n": 1}));
+    
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot["cbs.middleware.echo"].sent, 1);
+    assert_eq!(snapshot["cbs.middleware.echo"].handled, 1);
+}
+
+#[tokio::test]
+async fn mixed_codecs_interoperate() {
+    require_nats!();
+    
+    let msgpack = NatsBus::connect_with_config(NatsBusConfig {
+        wire: WireFormat::named("msgpack").unwrap(),
+        ..NatsBusConfig::default()
+    }).await.unwrap();
+    let cbor = NatsBus::connect_with_config(NatsBusConfig {
+        wire: WireFormat::named("cbor").unwrap(),
+        ..NatsBusConfig::default()
+    }).await.unwrap();
+    let json = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    
+    let _echo = msgpack.subscribe("cbs.codec.echo", sync_handler(|envelope| {
+        Ok(envelope.payload.unwrap_or(json!({})))
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    for bus in [&cbor, &json] {
+        let request = Envelope::new_request("codec", "echo", "demo/v1/Test", json!({"n": 1, "x": 0.5}));
+        assert_eq!(bus.request(request).await.unwrap(), json!({"n": 1, "x": 0.5}));
+    }
+}
+
+#[tokio::test]
+async fn request_stream_over_nats() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let _monitor = bus.subscribe("cbs.stream.list", stream_handler(|_envelope, _ctx, sink| async move {
+        for n in 0..5 {
+            sink.send(json!({"n": n})).await?;
+        }
+        Ok(())
+    })).await.unwrap();
+    let _failing = bus.subscribe("cbs.stream.fail", stream_handler(|_envelope, _ctx, sink| async move {
+        sink.send(json!({"n": 0})).await?;
+        Err(BusError::Unavailable("log rotated".to_string()))
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("stream", "list", "demo/v1/List", json!({}));
+    let items = bus.request_stream(request).await.unwrap().collect_payloads().await.unwrap();
+    assert_eq!(items, (0..5).map(|n| json!({"n": n})).collect::<Vec<_>>());
+    
+    let request = Envelope::new_request("stream", "fail", "demo/v1/List", json!({}));
+    let mut stream = bus.request_stream(request).await.unwrap();
+    assert!(stream.next_item().await.unwrap().is_ok());
+    assert!(matches!(stream.next_item().await, Some(Err(BusError::Unavailable(_)))));
+    assert!(stream.next_item().await.is_none());
+}
+
+#[tokio::test]
+async fn abandoned_requests_cancel_remote_handlers() {
+    require_nats!();
+    
+    let config = NatsBusConfig {
+        request_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let bus = NatsBus::connect_with_config(config).await.unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let _slow = bus.subscribe("cbs.slow.work", async_handler(move |_envelope, ctx: RequestContext| {
+        let tx = tx.clone();
+        async move {
+            tokio::select! {
+                _ = ctx.cancellation().cancelled() => tx.send("cancelled").unwrap(),
+                _ = sleep(Duration::from_secs(5)) => tx.send("finished").unwrap(),
+            }
+            Ok(json!({}))
+        }
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+    assert!(matches!(bus.request(request).await, Err(BusError::Timeout)));
+    assert_eq!(tokio::time::timeout(Duration::from_secs(2), rx.recv()).await.unwrap(), Some("cancelled"));
+    
+    let request = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+    drop(bus.request_stream(request).await.unwrap());
+    assert_eq!(tokio::time::timeout(Duration::from_secs(2), rx.recv()).await.unwrap(), Some("cancelled"));
+}
+
+#[tokio::test]
+async fn wildcard_subscribers_never_see_cancel_signals() {
+    require_nats!();
+    
+    let watcher = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let _everything = watcher.subscribe("cbs.>", sync_handler(move |envelope| {
+        tx.send(envelope.schema.clone()).unwrap();
+        Ok(json!({}))
+    })).await.unwrap();
+    
+    let config = NatsBusConfig {
+        request_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let bus = NatsBus::connect_with_config(config).await.unwrap();
+    let _slow = bus.subscribe("cbs.quiet.work", async_handler(|_envelope, _ctx| async move {
+        sleep(Duration::from_secs(1)).await;
+        Ok(json!({}))
+    })).await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("quiet", "work", "demo/v1/Test", json!({}));
+    assert!(matches!(bus.request(request).await, Err(BusError::Timeout)));
+    sleep(Duration::from_millis(200)).await;
+    
+    let mut seen = Vec::new();
+    while let Ok(schema) = rx.try_recv() {
+        seen.push(schema);
+    }
+    assert!(!seen.is_empty());
+    assert!(seen.iter().all(|schema| schema != "cbs/v1/Cancel"), "{:?}", seen);
+}
+
+#[tokio::test]
+async fn request_all_reaches_every_instance() {
+    require_nats!();
+    
+    let bus = NatsBus::connect("nats://localhost:4222").await.unwrap();
+    let mut subscriptions = Vec::new();
+    for instance in 0..3 {
+        let handler = sync_handler(move |_| match instance {
+            2 => Err(BusError::Unavailable("draining".to_string())),
+            _ => Ok(json!({"instance": instance})),
+        });
+        subscriptions.push(bus.subscribe("cbs.scatter.health", handler).await.unwrap());
+    }
+    sleep(Duration::from_millis(100)).await;
+    
+    let request = Envelope::new_request("scatter", "health", "demo/v1/Void", json!({}));
+    let options = ScatterOptions::new().with_timeout(Duration::from_secs(2)).expecting(3);
+    let replies = bus.request_all(request, options).await.unwrap();
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies.iter().filter(|reply| reply.is_ok()).count(), 2);
+    assert!(replies.iter().all(|reply| reply.responder != "unknown"));
+    
+    // A plain request still reaches exactly one instance
+    let request = Envelope::new_request("scatter", "health", "demo/v1/Void", json!({}));
+    let _ = bus.request(request).await;
+    let delivered: u64 = subscriptions.iter().map(|subscription| subscription.delivered()).sum();
+    assert_eq!(delivered, 4);
+}
+
 #[tokio::test]
 async fn server_info_retrieval() {
     require_nats!();
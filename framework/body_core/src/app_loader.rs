@@ -2,6 +2,13 @@ use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+use crate::circuit_breaker::CircuitBreakerConfig;
+use crate::dependencies::{startup_order, DependencyError};
+use crate::flow::{validate_flows, FlowConfig, FlowError};
+use crate::idempotency::IdempotencyConfig;
+use crate::limits::{LimitConfig, LimitsConfig};
+use crate::retry::RetryConfig;
+
 /// Application configuration loaded from app.yaml
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct AppConfig {
@@ -11,6 +18,21 @@ pub struct AppConfig {
     pub cells: Vec<CellConfig>,
     #[serde(default)]
     pub shared_cells: Vec<String>,
+    /// Retry policies for outbound requests
+    #[serde(default, skip_serializing_if = "RetryConfig::is_empty")]
+    pub retry: RetryConfig,
+    /// Per-service circuit breaking for outbound requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Rate and concurrency limits per subject
+    #[serde(default, skip_serializing_if = "LimitsConfig::is_empty")]
+    pub limits: LimitsConfig,
+    /// Duplicate suppression for handled requests, keyed by envelope id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency: Option<IdempotencyConfig>,
+    /// Named sequences of requests the Body can run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flows: Vec<FlowConfig>,
 }
 
 /// Cell configuration within an application
@@ -20,6 +42,12 @@ pub struct CellConfig {
     pub path: String,
     #[serde(default)]
     pub dependencies: Vec<String>,
+    /// Rate and concurrency limits for every message this cell handles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<LimitConfig>,
+    /// Cell-specific settings, handed to the cell's `init`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<serde_json::Value>,
 }
 
 /// Errors that can occur during application loading
@@ -33,6 +61,10 @@ pub enum AppLoadError {
     InvalidConfig(String),
     #[error("Cell directory not found: {0}")]
     CellNotFound(String),
+    #[error("Invalid cell dependencies: {0}")]
+    Dependencies(#[from] DependencyError),
+    #[error(transparent)]
+    Flow(#[from] FlowError),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("YAML parsing error: {0}")]
@@ -91,6 +123,8 @@ impl AppLoader {
 
         // Validate cell paths exist
         self.validate_cell_paths(&app_dir, &config)?;
+        config.startup_order()?;
+        validate_flows(&config.flows, &config.cells)?;
 
         Ok(config)
     }
@@ -116,6 +150,13 @@ impl AppLoader {
     }
 }
 
+impl AppConfig {
+    /// Cells in the order they start: each after the cells it depends on
+    pub fn startup_order(&self) -> Result<Vec<&CellConfig>, DependencyError> {
+        startup_order(&self.cells)
+    }
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
@@ -124,6 +165,11 @@ impl Default for AppConfig {
             description: "CBS Application".to_string(),
             cells: vec![],
             shared_cells: vec![],
+            retry: RetryConfig::default(),
+            circuit_breaker: None,
+            limits: LimitsConfig::default(),
+            idempotency: None,
+            flows: vec![],
         }
     }
 }
@@ -153,8 +199,11 @@ mod tests {
                 name: "test_cell".to_string(),
                 path: "cells/test_cell".to_string(),
                 dependencies: vec![],
+                limits: None,
+                config: None,
             }],
             shared_cells: vec![],
+            ..Default::default()
         };
         
         let config_yaml = serde_yaml::to_string(&app_config).unwrap();
@@ -266,6 +315,37 @@ mod tests {
         assert!(matches!(result, Err(AppLoadError::Yaml(_))));
     }
 
+    #[test]
+    fn load_application_dependency_cycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let apps_dir = temp_dir.path().join("applications");
+        let app_dir = apps_dir.join("cyclic_app");
+        fs::create_dir_all(app_dir.join("cells/a")).unwrap();
+        fs::create_dir_all(app_dir.join("cells/b")).unwrap();
+        fs::write(
+            app_dir.join("app.yaml"),
+            r#"
+name: cyclic_app
+version: 1.0.0
+description: Cells that wait on each other
+cells:
+  - name: a
+    path: cells/a
+    dependencies: [b]
+  - name: b
+    path: cells/b
+    dependencies: [a]
+"#,
+        )
+        .unwrap();
+        
+        let loader = AppLoader::new(&apps_dir);
+        let error = loader.load_application("cyclic_app").unwrap_err();
+        
+        assert!(matches!(error, AppLoadError::Dependencies(DependencyError::Cycle(_))));
+        assert_eq!(error.to_string(), "Invalid cell dependencies: Dependency cycle: a -> b -> a");
+    }
+
     #[test]
     fn load_application_cell_not_found() {
         let temp_dir = TempDir::new().unwrap();
@@ -283,8 +363,11 @@ mod tests {
                 name: "missing_cell".to_string(),
                 path: "cells/missing_cell".to_string(),
                 dependencies: vec![],
+                limits: None,
+                config: None,
             }],
             shared_cells: vec![],
+            ..Default::default()
         };
         
         let config_yaml = serde_yaml::to_string(&app_config).unwrap();
@@ -306,8 +389,11 @@ mod tests {
                 name: "cell1".to_string(),
                 path: "cells/cell1".to_string(),
                 dependencies: vec!["dep1".to_string()],
+                limits: None,
+                config: None,
             }],
             shared_cells: vec!["shared1".to_string()],
+            ..Default::default()
         };
         
         let yaml = serde_yaml::to_string(&config).unwrap();
@@ -342,6 +428,8 @@ cells: []
             name: "test_cell".to_string(),
             path: "cells/test".to_string(),
             dependencies: vec!["dep1".to_string(), "dep2".to_string()],
+            limits: None,
+            config: None,
         };
         
         assert_eq!(cell.dependencies.len(), 2);
@@ -366,6 +454,7 @@ cells: []
                 description: format!("{} description", app_name),
                 cells: vec![],
                 shared_cells: vec![],
+                ..Default::default()
             };
             
             let config_yaml = serde_yaml::to_string(&config).unwrap();
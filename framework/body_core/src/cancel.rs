@@ -0,0 +1,201 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::{headers, CancellationToken, Envelope, RequestContext};
+
+/// Subjects cancel signals travel on: `_cbs.cancel.{service}`
+///
+/// They sit outside the `cbs.` namespace, so wildcard subscriptions such as
+/// `cbs.>` never receive them as requests.
+pub const CANCEL_SUBJECT_PREFIX: &str = "_cbs.cancel";
+
+/// Schema of a cancel signal envelope; its payload is `{"id": <request id>}`,
+/// plus `"attempt"` when the request carried an `attempt` header
+pub const CANCEL_SCHEMA: &str = "cbs/v1/Cancel";
+
+/// Subject for cancel signals aimed at `service`
+pub fn cancel_subject(service: &str) -> String {
+    format!("{}.{}", CANCEL_SUBJECT_PREFIX, service)
+}
+
+/// Key a handler's cancellation token is registered under
+///
+/// Retries reuse the envelope id, so the key adds the `attempt` header when
+/// there is one; a late cancel for an abandoned attempt then cannot stop the
+/// attempt that replaced it.
+pub fn cancel_key(request: &Envelope) -> String {
+    key(&request.id, request.header(headers::ATTEMPT))
+}
+
+fn key(id: &str, attempt: Option<&str>) -> String {
+    match attempt {
+        Some(attempt) => format!("{}#{}", id, attempt),
+        None => id.to_string(),
+    }
+}
+
+/// Signal asking whoever is handling `request` to stop
+pub fn cancel_signal(request: &Envelope) -> Envelope {
+    let mut payload = json!({"id": request.id});
+    if let Some(attempt) = request.header(headers::ATTEMPT) {
+        payload["attempt"] = json!(attempt);
+    }
+    let mut signal = Envelope::new_request(&request.service, &request.verb, CANCEL_SCHEMA, payload);
+    signal.headers_mut().set(headers::CAUSATION_ID, &request.id);
+    if let Some(trace_id) = request.header(headers::TRACE_ID) {
+        signal.headers_mut().set(headers::TRACE_ID, trace_id);
+    }
+    signal
+}
+
+/// `cancel_key` of the request a cancel signal refers to
+pub fn cancelled_request_key(signal: &Envelope) -> Option<String> {
+    if signal.schema != CANCEL_SCHEMA {
+        return None;
+    }
+    let payload = signal.payload.as_ref()?;
+    Some(key(payload.get("id")?.as_str()?, payload.get("attempt").and_then(Value::as_str)))
+}
+
+/// Resolves when `parent` is cancelled; never without a parent
+pub async fn parent_cancelled(parent: Option<&RequestContext>) {
+    match parent {
+        Some(parent) => parent.cancellation().cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Cancellation tokens of the requests a bus is handling, by `cancel_key`
+///
+/// Lets a transport turn a cancel signal into a cancelled `RequestContext`.
+#[derive(Debug, Default)]
+pub struct InFlightRequests {
+    next_key: AtomicU64,
+    tokens: Mutex<HashMap<String, Vec<(u64, CancellationToken)>>>,
+}
+
+impl InFlightRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track `token` until the returned guard is dropped
+    pub fn register(self: &Arc<Self>, id: &str, token: CancellationToken) -> InFlightGuard {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        self.lock().entry(id.to_string()).or_default().push((key, token));
+        InFlightGuard {
+            registry: Arc::clone(self),
+            id: id.to_string(),
+            key,
+        }
+    }
+
+    /// Cancel every handler of request `id`; false if none is in flight here
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.lock().get(id) {
+            Some(tokens) => {
+                tokens.iter().for_each(|(_, token)| token.cancel());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of distinct request ids in flight
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<(u64, CancellationToken)>>> {
+        self.tokens.lock().expect("in-flight registry lock poisoned")
+    }
+}
+
+/// Registration of one in-flight handler; unregisters on drop
+#[derive(Debug)]
+pub struct InFlightGuard {
+    registry: Arc<InFlightRequests>,
+    id: String,
+    key: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut tokens = self.registry.lock();
+        if let Some(entries) = tokens.get_mut(&self.id) {
+            entries.retain(|(key, _)| *key != self.key);
+            if entries.is_empty() {
+                tokens.remove(&self.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subject;
+
+    #[test]
+    fn cancel_signal_names_the_request() {
+        let request = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}))
+            .with_header(headers::TRACE_ID, "trace-1").unwrap();
+        let signal = cancel_signal(&request);
+
+        assert_eq!(cancel_subject(&request.service), "_cbs.cancel.greeter");
+        assert_eq!(cancelled_request_key(&signal), Some(request.id.clone()));
+        assert_eq!(signal.header(headers::TRACE_ID), Some("trace-1"));
+        assert_eq!(signal.header(headers::CAUSATION_ID), Some(request.id.as_str()));
+        assert_eq!(cancelled_request_key(&request), None);
+    }
+
+    #[test]
+    fn cancel_subjects_escape_request_wildcards() {
+        for pattern in ["cbs.>", "cbs.*.*", "cbs.*.>"] {
+            assert!(!subject::matches(pattern, &cancel_subject("greeter")), "{} matched", pattern);
+        }
+    }
+
+    #[test]
+    fn cancelling_an_abandoned_attempt_spares_its_retry() {
+        let request = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+        let first = request.clone().with_header(headers::ATTEMPT, "1").unwrap();
+        let second = request.with_header(headers::ATTEMPT, "2").unwrap();
+
+        let registry = Arc::new(InFlightRequests::new());
+        let retry = CancellationToken::new();
+        let _guard = registry.register(&cancel_key(&second), retry.clone());
+
+        // The signal for the timed-out first attempt arrives after the retry started
+        let late = cancelled_request_key(&cancel_signal(&first)).unwrap();
+        assert!(!registry.cancel(&late));
+        assert!(!retry.is_cancelled());
+
+        assert!(registry.cancel(&cancelled_request_key(&cancel_signal(&second)).unwrap()));
+        assert!(retry.is_cancelled());
+    }
+
+    #[test]
+    fn registry_cancels_registered_tokens_until_released() {
+        let registry = Arc::new(InFlightRequests::new());
+        let (first, second) = (CancellationToken::new(), CancellationToken::new());
+        let first_guard = registry.register("a", first.clone());
+        let second_guard = registry.register("a", second.clone());
+        assert_eq!(registry.len(), 1);
+
+        assert!(registry.cancel("a"));
+        assert!(first.is_cancelled() && second.is_cancelled());
+        assert!(!registry.cancel("b"));
+
+        drop(first_guard);
+        assert_eq!(registry.len(), 1);
+        drop(second_guard);
+        assert!(registry.is_empty());
+    }
+}
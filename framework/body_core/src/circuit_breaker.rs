@@ -0,0 +1,536 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::middleware::{Middleware, RequestNext, ScatterNext, StreamNext};
+use crate::{BusError, Envelope, RequestContext, ResponseStream, ScatterOptions, ScatterReply};
+
+/// When a breaker trips and how long it stays open
+///
+/// Every field has a default, so `app.yaml` only lists what it changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures that open the circuit
+    pub failure_threshold: u32,
+    /// How long an open circuit fails fast before letting trial requests through
+    pub cool_down_ms: u64,
+    /// Trial requests allowed at once while half-open
+    pub half_open_max_calls: u32,
+    /// `BusError::code()` values that count as failures of the target service
+    pub trip_on: Vec<String>,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cool_down_ms: 10_000,
+            half_open_max_calls: 1,
+            trip_on: vec!["Timeout".to_string(), "Connection".to_string(), "Internal".to_string()],
+        }
+    }
+}
+
+/// Observable state of one service's circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow; failures are counted
+    Closed,
+    /// Requests fail fast with `BusError::CircuitOpen`
+    Open,
+    /// A limited number of trial requests decide whether to close again
+    HalfOpen,
+}
+
+/// Point-in-time view of a service's breaker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CircuitSnapshot {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// Time left before an open circuit lets a trial request through
+    pub retry_after: Option<Duration>,
+    /// Requests rejected without reaching the service
+    pub rejected: u64,
+}
+
+#[derive(Debug)]
+enum Phase {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { in_flight: u32 },
+}
+
+#[derive(Debug)]
+struct Breaker {
+    phase: Phase,
+    consecutive_failures: u32,
+    rejected: u64,
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Self {
+            phase: Phase::Closed,
+            consecutive_failures: 0,
+            rejected: 0,
+        }
+    }
+
+    /// Open circuits turn half-open once their cool-down has passed
+    fn refresh(&mut self, now: Instant) {
+        if let Phase::Open { until } = self.phase {
+            if now >= until {
+                self.phase = Phase::HalfOpen { in_flight: 0 };
+            }
+        }
+    }
+
+    fn snapshot(&mut self, now: Instant) -> CircuitSnapshot {
+        self.refresh(now);
+        let (state, retry_after) = match self.phase {
+            Phase::Closed => (CircuitState::Closed, None),
+            Phase::Open { until } => (CircuitState::Open, Some(until.saturating_duration_since(now))),
+            Phase::HalfOpen { .. } => (CircuitState::HalfOpen, None),
+        };
+        CircuitSnapshot {
+            state,
+            consecutive_failures: self.consecutive_failures,
+            retry_after,
+            rejected: self.rejected,
+        }
+    }
+}
+
+/// Middleware that fails fast on services that keep failing
+///
+/// One breaker per target service. After `failure_threshold` consecutive
+/// failures the circuit opens and requests fail with `BusError::CircuitOpen`
+/// until `cool_down_ms` passes; then up to `half_open_max_calls` trial
+/// requests go through. A successful trial closes the circuit, a failed one
+/// reopens it. Add it with `layer_shared` to keep a handle for introspection.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl CircuitBreaker {
+    /// Breakers for every service, created on first use
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Settings shared by every service's breaker
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    /// Current state for `service`; services never called are closed
+    pub fn state(&self, service: &str) -> CircuitState {
+        self.snapshot(service).state
+    }
+
+    /// Current breaker details for `service`
+    pub fn snapshot(&self, service: &str) -> CircuitSnapshot {
+        let mut breakers = self.lock();
+        match breakers.get_mut(service) {
+            Some(breaker) => breaker.snapshot(Instant::now()),
+            None => Breaker::new().snapshot(Instant::now()),
+        }
+    }
+
+    /// Breaker details for every service seen so far
+    pub fn snapshots(&self) -> BTreeMap<String, CircuitSnapshot> {
+        let now = Instant::now();
+        self.lock()
+            .iter_mut()
+            .map(|(service, breaker)| (service.clone(), breaker.snapshot(now)))
+            .collect()
+    }
+
+    /// Close the circuit for `service` and forget its failures
+    pub fn reset(&self, service: &str) {
+        if self.lock().remove(service).is_some() {
+            info!(service = %service, "Circuit reset");
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Breaker>> {
+        self.breakers.lock().expect("circuit breaker lock poisoned")
+    }
+
+    /// Admit a request or fail fast
+    fn acquire(&self, service: &str) -> Result<Admission<'_>, BusError> {
+        self.admit(service)?;
+        Ok(Admission {
+            breaker: self,
+            service: service.to_string(),
+            settled: false,
+        })
+    }
+
+    fn admit(&self, service: &str) -> Result<(), BusError> {
+        let now = Instant::now();
+        let mut breakers = self.lock();
+        let breaker = breakers.entry(service.to_string()).or_insert_with(Breaker::new);
+        breaker.refresh(now);
+
+        match &mut breaker.phase {
+            Phase::Closed => Ok(()),
+            Phase::HalfOpen { in_flight } if *in_flight < self.config.half_open_max_calls.max(1) => {
+                *in_flight += 1;
+                Ok(())
+            }
+            Phase::HalfOpen { .. } => {
+                breaker.rejected += 1;
+                Err(BusError::CircuitOpen {
+                    service: service.to_string(),
+                    retry_after: Duration::ZERO,
+                })
+            }
+            Phase::Open { until } => {
+                let retry_after = until.saturating_duration_since(now);
+                breaker.rejected += 1;
+                Err(BusError::CircuitOpen {
+                    service: service.to_string(),
+                    retry_after,
+                })
+            }
+        }
+    }
+
+    /// Give back the trial slot of an admitted request that never finished
+    fn release(&self, service: &str) {
+        if let Some(Breaker {
+            phase: Phase::HalfOpen { in_flight },
+            ..
+        }) = self.lock().get_mut(service)
+        {
+            *in_flight = in_flight.saturating_sub(1);
+        }
+    }
+
+    /// Record the outcome of an admitted request
+    ///
+    /// Outcomes arriving while the circuit is open belong to requests admitted
+    /// before it tripped; they neither close it early nor extend the cool-down.
+    fn complete(&self, service: &str, error: Option<&BusError>) {
+        let failed = matches!(error, Some(e) if self.config.trip_on.iter().any(|code| code == e.code()));
+        let mut breakers = self.lock();
+        let Some(breaker) = breakers.get_mut(service) else {
+            return;
+        };
+        if let Phase::Open { .. } = breaker.phase {
+            return;
+        }
+
+        let half_open = matches!(breaker.phase, Phase::HalfOpen { .. });
+        if !failed {
+            if half_open {
+                info!(service = %service, "Circuit closed after successful trial request");
+            }
+            breaker.phase = Phase::Closed;
+            breaker.consecutive_failures = 0;
+            return;
+        }
+
+        breaker.consecutive_failures += 1;
+        if half_open || breaker.consecutive_failures >= self.config.failure_threshold.max(1) {
+            let cool_down = Duration::from_millis(self.config.cool_down_ms);
+            warn!(service = %service, failures = breaker.consecutive_failures, cool_down_ms = self.config.cool_down_ms, "Circuit opened");
+            breaker.phase = Phase::Open {
+                until: Instant::now() + cool_down,
+            };
+        }
+    }
+}
+
+/// A request let through by a breaker
+///
+/// Dropped without `complete`, e.g. when the caller stops waiting, it gives
+/// its half-open trial slot back, so the circuit cannot stay stuck half-open
+/// waiting on a trial that will never report.
+struct Admission<'a> {
+    breaker: &'a CircuitBreaker,
+    service: String,
+    settled: bool,
+}
+
+impl Admission<'_> {
+    fn complete(mut self, error: Option<&BusError>) {
+        self.settled = true;
+        self.breaker.complete(&self.service, error);
+    }
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        if !self.settled {
+            self.breaker.release(&self.service);
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(CircuitBreakerConfig::default())
+    }
+}
+
+#[async_trait]
+impl Middleware for CircuitBreaker {
+    async fn request(
+        &self,
+        envelope: Envelope,
+        parent: Option<&RequestContext>,
+        next: RequestNext,
+    ) -> Result<Value, BusError> {
+        let admission = self.acquire(&envelope.service)?;
+        let result = next.run(envelope, parent).await;
+        admission.complete(result.as_ref().err());
+        result
+    }
+
+    /// Opening the stream is the outcome recorded; items that follow are not
+    async fn request_stream(&self, envelope: Envelope, next: StreamNext) -> Result<ResponseStream, BusError> {
+        let admission = self.acquire(&envelope.service)?;
+        let result = next.run(envelope).await;
+        admission.complete(result.as_ref().err());
+        result
+    }
+
+    /// Fails fast while open; a scatter with any successful reply counts as a
+    /// success, one with none decides nothing
+    async fn request_all(
+        &self,
+        envelope: Envelope,
+        options: ScatterOptions,
+        next: ScatterNext,
+    ) -> Result<Vec<ScatterReply>, BusError> {
+        let admission = self.acquire(&envelope.service)?;
+        let result = next.run(envelope, options).await;
+        match &result {
+            Ok(replies) if replies.iter().any(ScatterReply::is_ok) => admission.complete(None),
+            Ok(_) => {}
+            Err(e) => admission.complete(Some(e)),
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_handler, sync_handler, BodyBus, LocalBus, MiddlewareBus};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn config(cool_down_ms: u64) -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_threshold: 2,
+            cool_down_ms,
+            ..CircuitBreakerConfig::default()
+        }
+    }
+
+    /// Bus with a `flaky` service whose health is switched by the returned flag
+    async fn flaky_bus(breaker: Arc<CircuitBreaker>) -> (MiddlewareBus, Arc<AtomicBool>, crate::Subscription) {
+        let healthy = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&healthy);
+        let local = LocalBus::new();
+        let subscription = local
+            .subscribe(
+                "cbs.flaky.call",
+                sync_handler(move |_| {
+                    if flag.load(Ordering::SeqCst) {
+                        Ok(json!({"ok": true}))
+                    } else {
+                        Err(BusError::Internal("down".to_string()))
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+        let bus = MiddlewareBus::new(Arc::new(local)).layer_shared(breaker);
+        (bus, healthy, subscription)
+    }
+
+    fn call() -> Envelope {
+        Envelope::new_request("flaky", "call", "demo/v1/Test", json!({}))
+    }
+
+    #[tokio::test]
+    async fn opens_after_threshold_and_fails_fast() {
+        let breaker = Arc::new(CircuitBreaker::new(config(60_000)));
+        let (bus, _healthy, flaky) = flaky_bus(Arc::clone(&breaker)).await;
+
+        for _ in 0..2 {
+            assert!(matches!(bus.request(call()).await, Err(BusError::Internal(_))));
+        }
+        assert_eq!(breaker.state("flaky"), CircuitState::Open);
+
+        match bus.request(call()).await {
+            Err(BusError::CircuitOpen { service, retry_after }) => {
+                assert_eq!(service, "flaky");
+                assert!(retry_after > Duration::from_secs(59));
+            }
+            other => panic!("Expected CircuitOpen, got: {:?}", other),
+        }
+        // The rejected request never reached the handler
+        assert_eq!(flaky.delivered(), 2);
+
+        let snapshot = &breaker.snapshots()["flaky"];
+        assert_eq!(snapshot.consecutive_failures, 2);
+        assert_eq!(snapshot.rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn half_open_trial_closes_or_reopens() {
+        let breaker = Arc::new(CircuitBreaker::new(config(20)));
+        let (bus, healthy, _flaky) = flaky_bus(Arc::clone(&breaker)).await;
+
+        for _ in 0..2 {
+            let _ = bus.request(call()).await;
+        }
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(breaker.state("flaky"), CircuitState::HalfOpen);
+
+        // Failed trial reopens immediately
+        assert!(matches!(bus.request(call()).await, Err(BusError::Internal(_))));
+        assert_eq!(breaker.state("flaky"), CircuitState::Open);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        healthy.store(true, Ordering::SeqCst);
+        assert_eq!(bus.request(call()).await.unwrap(), json!({"ok": true}));
+        assert_eq!(breaker.state("flaky"), CircuitState::Closed);
+        assert_eq!(breaker.snapshot("flaky").consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn caller_errors_do_not_trip_and_reset_closes() {
+        let breaker = Arc::new(CircuitBreaker::new(config(60_000)));
+        let local = LocalBus::new();
+        let _strict = local
+            .subscribe("cbs.strict.call", sync_handler(|_| Err(BusError::bad_request("nope"))))
+            .await
+            .unwrap();
+        let bus = MiddlewareBus::new(Arc::new(local)).layer_shared(breaker.clone());
+
+        for _ in 0..5 {
+            let envelope = Envelope::new_request("strict", "call", "demo/v1/Test", json!({}));
+            assert!(matches!(bus.request(envelope).await, Err(BusError::BadRequest { .. })));
+        }
+        assert_eq!(breaker.state("strict"), CircuitState::Closed);
+
+        let (bus, _healthy, _flaky) = flaky_bus(Arc::clone(&breaker)).await;
+        for _ in 0..2 {
+            let _ = bus.request(call()).await;
+        }
+        assert_eq!(breaker.state("flaky"), CircuitState::Open);
+        breaker.reset("flaky");
+        assert_eq!(breaker.state("flaky"), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn late_outcomes_leave_an_open_circuit_alone() {
+        let breaker = Arc::new(CircuitBreaker::new(config(60_000)));
+        // The first call waits for the gate and succeeds, later calls fail
+        let gate = Arc::new(tokio::sync::Notify::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (opened, seen) = (Arc::clone(&gate), Arc::clone(&calls));
+        let local = LocalBus::new();
+        let _slow = local
+            .subscribe(
+                "cbs.flaky.call",
+                async_handler(move |_, _| {
+                    let call = seen.fetch_add(1, Ordering::SeqCst);
+                    let opened = Arc::clone(&opened);
+                    async move {
+                        if call == 0 {
+                            opened.notified().await;
+                            Ok(json!({"ok": true}))
+                        } else {
+                            Err(BusError::Internal("down".to_string()))
+                        }
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+        let bus = Arc::new(MiddlewareBus::new(Arc::new(local)).layer_shared(breaker.clone()));
+
+        let held = tokio::spawn({
+            let bus = Arc::clone(&bus);
+            async move { bus.request(call()).await }
+        });
+        while calls.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        for _ in 0..2 {
+            let _ = bus.request(call()).await;
+        }
+        assert_eq!(breaker.state("flaky"), CircuitState::Open);
+        let retry_after = breaker.snapshot("flaky").retry_after.unwrap();
+
+        gate.notify_one();
+        assert_eq!(held.await.unwrap().unwrap(), json!({"ok": true}));
+        let snapshot = breaker.snapshot("flaky");
+        assert_eq!(snapshot.state, CircuitState::Open);
+        assert_eq!(snapshot.consecutive_failures, 2);
+        assert!(snapshot.retry_after.unwrap() <= retry_after);
+    }
+
+    #[tokio::test]
+    async fn dropped_trial_gives_its_slot_back() {
+        let breaker = Arc::new(CircuitBreaker::new(config(20)));
+        // Calls fail twice, then hang once, then succeed
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::clone(&calls);
+        let local = LocalBus::new();
+        let _slow = local
+            .subscribe(
+                "cbs.flaky.call",
+                async_handler(move |_, _| {
+                    let call = seen.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        match call {
+                            0 | 1 => Err(BusError::Internal("down".to_string())),
+                            2 => {
+                                tokio::time::sleep(Duration::from_secs(60)).await;
+                                Ok(json!({"ok": false}))
+                            }
+                            _ => Ok(json!({"ok": true})),
+                        }
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+        let bus = MiddlewareBus::new(Arc::new(local)).layer_shared(breaker.clone());
+
+        for _ in 0..2 {
+            let _ = bus.request(call()).await;
+        }
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(breaker.state("flaky"), CircuitState::HalfOpen);
+
+        // The caller gives up on the trial request mid-flight
+        let abandoned = tokio::time::timeout(Duration::from_millis(20), bus.request(call())).await;
+        assert!(abandoned.is_err());
+        assert_eq!(breaker.state("flaky"), CircuitState::HalfOpen);
+
+        assert_eq!(bus.request(call()).await.unwrap(), json!({"ok": true}));
+        assert_eq!(breaker.state("flaky"), CircuitState::Closed);
+        assert_eq!(breaker.snapshot("flaky").rejected, 0);
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{BusError, Envelope};
+
+/// Transport header naming the codec of a message body; absent means JSON
+pub const CODEC_HEADER: &str = "Cbs-Codec";
+
+/// Transport header naming the compression applied after encoding, e.g. `zstd`
+pub const ENCODING_HEADER: &str = "Cbs-Encoding";
+
+/// Turns envelopes into message bodies and back
+///
+/// Every codec carries the same envelope structure, so the JSON schemas in
+/// `docs/schemas` stay the contract whatever the wire format: validation runs
+/// on the decoded envelope.
+pub trait Codec: Send + Sync + fmt::Debug {
+    /// Name carried in `CODEC_HEADER`
+    fn name(&self) -> &'static str;
+
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>, BusError>;
+
+    fn decode(&self, bytes: &[u8]) -> Result<Envelope, BusError>;
+}
+
+/// JSON, the default and the format every peer understands
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>, BusError> {
+        serde_json::to_vec(envelope).map_err(|e| encode_error(self, e))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Envelope, BusError> {
+        serde_json::from_slice(bytes).map_err(|e| decode_error(self, e))
+    }
+}
+
+/// MessagePack with named fields, so optional envelope fields can be omitted
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePackCodec;
+
+impl Codec for MessagePackCodec {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>, BusError> {
+        rmp_serde::to_vec_named(envelope).map_err(|e| encode_error(self, e))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Envelope, BusError> {
+        rmp_serde::from_slice(bytes).map_err(|e| decode_error(self, e))
+    }
+}
+
+/// CBOR (RFC 8949)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+impl Codec for CborCodec {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode(&self, envelope: &Envelope) -> Result<Vec<u8>, BusError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(envelope, &mut bytes).map_err(|e| encode_error(self, e))?;
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Envelope, BusError> {
+        ciborium::from_reader(bytes).map_err(|e| decode_error(self, e))
+    }
+}
+
+fn encode_error(codec: &dyn Codec, e: impl fmt::Display) -> BusError {
+    BusError::Serialization(format!("Failed to encode envelope as {}: {}", codec.name(), e))
+}
+
+fn decode_error(codec: &dyn Codec, e: impl fmt::Display) -> BusError {
+    BusError::Serialization(format!("Failed to decode {} envelope: {}", codec.name(), e))
+}
+
+/// Built-in codec for a `CODEC_HEADER` value
+pub fn codec_by_name(name: &str) -> Option<Arc<dyn Codec>> {
+    match name {
+        "json" => Some(Arc::new(JsonCodec)),
+        "msgpack" => Some(Arc::new(MessagePackCodec)),
+        "cbor" => Some(Arc::new(CborCodec)),
+        _ => None,
+    }
+}
+
+/// A message body and the transport headers describing it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedMessage {
+    pub body: Vec<u8>,
+    pub headers: Vec<(&'static str, &'static str)>,
+}
+
+impl EncodedMessage {
+    /// Look up a transport header value
+    pub fn header(&self, name: &str) -> Option<&'static str> {
+        self.headers.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+    }
+}
+
+/// How a bus writes envelopes: a codec plus optional compression of large bodies
+///
+/// Reading is independent of the local choice. Any built-in codec named in
+/// the headers is accepted, so peers using different codecs interoperate.
+#[derive(Debug, Clone)]
+pub struct WireFormat {
+    codec: Arc<dyn Codec>,
+    compress_above: Option<usize>,
+}
+
+impl Default for WireFormat {
+    fn default() -> Self {
+        Self::new(JsonCodec)
+    }
+}
+
+impl WireFormat {
+    pub fn new<C: Codec + 'static>(codec: C) -> Self {
+        Self {
+            codec: Arc::new(codec),
+            compress_above: None,
+        }
+    }
+
+    /// Wire format for a `CODEC_HEADER` value, e.g. from configuration
+    pub fn named(name: &str) -> Result<Self, BusError> {
+        let codec = codec_by_name(name).ok_or_else(|| BusError::bad_request(format!("Unknown codec: {}", name)))?;
+        Ok(Self {
+            codec,
+            compress_above: None,
+        })
+    }
+
+    /// Compress encoded bodies larger than `threshold` bytes with zstd
+    #[cfg(feature = "zstd")]
+    pub fn with_zstd(mut self, threshold: usize) -> Self {
+        self.compress_above = Some(threshold);
+        self
+    }
+
+    pub fn codec(&self) -> &dyn Codec {
+        self.codec.as_ref()
+    }
+
+    pub fn encode(&self, envelope: &Envelope) -> Result<EncodedMessage, BusError> {
+        let mut body = self.codec.encode(envelope)?;
+        let mut headers = Vec::new();
+        if self.codec.name() != JsonCodec.name() {
+            headers.push((CODEC_HEADER, self.codec.name()));
+        }
+        if self.compress_above.is_some_and(|threshold| body.len() > threshold) {
+            body = compress(&body)?;
+            headers.push((ENCODING_HEADER, "zstd"));
+        }
+        Ok(EncodedMessage { body, headers })
+    }
+
+    /// Decode a body tagged with the given `CODEC_HEADER` and `ENCODING_HEADER` values
+    pub fn decode(&self, body: &[u8], codec: Option<&str>, encoding: Option<&str>) -> Result<Envelope, BusError> {
+        let decompressed;
+        let body = match encoding {
+            None | Some("") | Some("identity") => body,
+            Some("zstd") => {
+                decompressed = decompress(body)?;
+                &decompressed
+            }
+            Some(other) => return Err(BusError::Serialization(format!("Unsupported encoding: {}", other))),
+        };
+        match codec.unwrap_or("json") {
+            name if name == self.codec.name() => self.codec.decode(body),
+            name => codec_by_name(name)
+                .ok_or_else(|| BusError::Serialization(format!("Unsupported codec: {}", name)))?
+                .decode(body),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compress(body: &[u8]) -> Result<Vec<u8>, BusError> {
+    zstd::encode_all(body, 0).map_err(|e| BusError::Serialization(format!("zstd compression failed: {}", e)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress(_body: &[u8]) -> Result<Vec<u8>, BusError> {
+    Err(BusError::Serialization("zstd support is not enabled".to_string()))
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(body: &[u8]) -> Result<Vec<u8>, BusError> {
+    zstd::decode_all(body).map_err(|e| BusError::Serialization(format!("zstd decompression failed: {}", e)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_body: &[u8]) -> Result<Vec<u8>, BusError> {
+    Err(BusError::Serialization(
+        "Received a zstd-compressed message but zstd support is not enabled".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorDetails, SchemaRegistry};
+    use serde_json::json;
+
+    fn sample() -> Envelope {
+        Envelope::new_request(
+            "greeter",
+            "say_hello",
+            "demo/v1/Name",
+            json!({"name": "Ada", "age": 36, "score": 0.5, "tags": ["a", "b"], "extra": null}),
+        )
+        .with_header(crate::headers::TRACE_ID, "trace-1").unwrap()
+    }
+
+    #[test]
+    fn every_codec_roundtrips_envelopes() {
+        let error = Envelope::new_error(
+            "id",
+            "greeter",
+            "say_hello",
+            "demo/v1/Error",
+            ErrorDetails::with_details("BadRequest", "bad", json!({"fields": [{"path": "name"}]})),
+        );
+        for name in ["json", "msgpack", "cbor"] {
+            let codec = codec_by_name(name).unwrap();
+            assert_eq!(codec.name(), name);
+            for envelope in [sample(), error.clone()] {
+                let bytes = codec.encode(&envelope).unwrap();
+                assert_eq!(codec.decode(&bytes).unwrap(), envelope, "{} roundtrip", name);
+            }
+        }
+        assert!(codec_by_name("xml").is_none());
+    }
+
+    #[test]
+    fn binary_codecs_are_smaller_than_json() {
+        let envelope = sample();
+        let json = JsonCodec.encode(&envelope).unwrap().len();
+        assert!(MessagePackCodec.encode(&envelope).unwrap().len() < json);
+        assert!(CborCodec.encode(&envelope).unwrap().len() < json);
+    }
+
+    #[test]
+    fn wire_format_tags_non_json_codecs() {
+        let json = WireFormat::default().encode(&sample()).unwrap();
+        assert!(json.headers.is_empty());
+
+        let msgpack = WireFormat::named("msgpack").unwrap().encode(&sample()).unwrap();
+        assert_eq!(msgpack.header(CODEC_HEADER), Some("msgpack"));
+        assert!(msgpack.header(ENCODING_HEADER).is_none());
+
+        assert!(WireFormat::named("xml").is_err());
+    }
+
+    #[test]
+    fn any_tagged_codec_is_readable() {
+        let (reader, envelope) = (WireFormat::default(), sample());
+        for writer in [WireFormat::new(MessagePackCodec), WireFormat::new(CborCodec), WireFormat::default()] {
+            let message = writer.encode(&envelope).unwrap();
+            let decoded = reader
+                .decode(&message.body, message.header(CODEC_HEADER), message.header(ENCODING_HEADER))
+                .unwrap();
+            assert_eq!(decoded, envelope);
+        }
+
+        let result = reader.decode(b"{}", Some("xml"), None);
+        assert!(matches!(result, Err(BusError::Serialization(message)) if message.contains("xml")));
+    }
+
+    #[test]
+    fn decoded_envelopes_validate_against_the_json_schema() {
+        let registry = SchemaRegistry::new();
+        let message = WireFormat::new(CborCodec).encode(&sample()).unwrap();
+        let decoded = WireFormat::default()
+            .decode(&message.body, message.header(CODEC_HEADER), None)
+            .unwrap();
+        assert!(registry.validate_envelope(&decoded).is_ok());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn large_bodies_are_compressed_with_zstd() {
+        let format = WireFormat::new(MessagePackCodec).with_zstd(256);
+
+        let small = format.encode(&sample()).unwrap();
+        assert!(small.header(ENCODING_HEADER).is_none());
+
+        let large_payload = json!({"text": "hello ".repeat(1000)});
+        let large = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", large_payload);
+        let message = format.encode(&large).unwrap();
+        assert_eq!(message.header(ENCODING_HEADER), Some("zstd"));
+        assert!(message.body.len() < 1000);
+
+        let decoded = WireFormat::default()
+            .decode(&message.body, message.header(CODEC_HEADER), message.header(ENCODING_HEADER))
+            .unwrap();
+        assert_eq!(decoded, large);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn compressed_bodies_need_the_zstd_feature() {
+        let result = WireFormat::default().decode(b"", None, Some("zstd"));
+        assert!(matches!(result, Err(BusError::Serialization(_))));
+    }
+}
@@ -0,0 +1,299 @@
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::validation::SchemaError;
+
+/// Whether a schema change can break existing payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Payloads valid under the old schema may be rejected by the new one
+    Breaking,
+    /// Every payload valid under the old schema stays valid
+    Compatible,
+}
+
+/// One difference between two versions of a schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaChange {
+    pub severity: Severity,
+    /// Dotted path of the affected field; `.` for the root
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Breaking => "breaking",
+            Severity::Compatible => "compatible",
+        };
+        write!(f, "[{}] {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// Result of comparing an old and a new version of a payload schema
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatibilityReport {
+    pub changes: Vec<SchemaChange>,
+}
+
+impl CompatibilityReport {
+    /// Changes that require a major version bump
+    pub fn breaking(&self) -> impl Iterator<Item = &SchemaChange> {
+        self.changes.iter().filter(|c| c.severity == Severity::Breaking)
+    }
+
+    /// Whether payloads written against the old schema still validate
+    pub fn is_compatible(&self) -> bool {
+        self.breaking().next().is_none()
+    }
+
+    fn push(&mut self, severity: Severity, path: &str, message: String) {
+        self.changes.push(SchemaChange {
+            severity,
+            path: if path.is_empty() { ".".to_string() } else { path.to_string() },
+            message,
+        });
+    }
+}
+
+/// Compare two JSON Schemas for backward compatibility
+///
+/// Covers the keywords CBS payload schemas use: `type`, `properties`,
+/// `required`, `additionalProperties`, `enum` and `items`.
+pub fn check_compatibility(old: &Value, new: &Value) -> CompatibilityReport {
+    let mut report = CompatibilityReport::default();
+    compare(old, new, "", &mut report);
+    report
+}
+
+/// Compare two schema files, e.g. `schemas/demo/v1/Name.json` and `schemas/demo/v2/Name.json`
+pub fn check_schema_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    old: P,
+    new: Q,
+) -> Result<CompatibilityReport, SchemaError> {
+    Ok(check_compatibility(&read_schema(old.as_ref())?, &read_schema(new.as_ref())?))
+}
+
+fn read_schema(path: &Path) -> Result<Value, SchemaError> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|source| SchemaError::Json {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+fn compare(old: &Value, new: &Value, path: &str, report: &mut CompatibilityReport) {
+    compare_types(old, new, path, report);
+    compare_enums(old, new, path, report);
+    compare_properties(old, new, path, report);
+
+    if let (Some(old_items), Some(new_items)) = (old.get("items"), new.get("items")) {
+        compare(old_items, new_items, &format!("{}[]", path), report);
+    }
+}
+
+fn compare_types(old: &Value, new: &Value, path: &str, report: &mut CompatibilityReport) {
+    let (Some(old_types), Some(new_types)) = (types(old), types(new)) else {
+        if old.get("type").is_none() && new.get("type").is_some() {
+            report.push(Severity::Breaking, path, format!("type restricted to {}", new["type"]));
+        }
+        return;
+    };
+
+    // An integer is also a number
+    let accepts = |t: &str| new_types.contains(t) || (t == "integer" && new_types.contains("number"));
+    let removed: Vec<_> = old_types.iter().filter(|t| !accepts(t)).cloned().collect();
+    if !removed.is_empty() {
+        report.push(Severity::Breaking, path, format!("type no longer accepts {}", removed.join(", ")));
+    }
+    let added: Vec<_> = new_types.difference(&old_types).cloned().collect();
+    if !added.is_empty() {
+        report.push(Severity::Compatible, path, format!("type now also accepts {}", added.join(", ")));
+    }
+}
+
+fn types(schema: &Value) -> Option<BTreeSet<String>> {
+    match schema.get("type")? {
+        Value::String(t) => Some(BTreeSet::from([t.clone()])),
+        Value::Array(ts) => Some(ts.iter().filter_map(|t| t.as_str().map(String::from)).collect()),
+        _ => None,
+    }
+}
+
+fn compare_enums(old: &Value, new: &Value, path: &str, report: &mut CompatibilityReport) {
+    let old_values = old.get("enum").and_then(Value::as_array);
+    let new_values = new.get("enum").and_then(Value::as_array);
+    match (old_values, new_values) {
+        (Some(old_values), Some(new_values)) => {
+            for value in old_values.iter().filter(|v| !new_values.contains(v)) {
+                report.push(Severity::Breaking, path, format!("enum value {} removed", value));
+            }
+            for value in new_values.iter().filter(|v| !old_values.contains(v)) {
+                report.push(Severity::Compatible, path, format!("enum value {} added", value));
+            }
+        }
+        (None, Some(_)) => report.push(Severity::Breaking, path, "values restricted to an enum".to_string()),
+        (Some(_), None) => report.push(Severity::Compatible, path, "enum restriction removed".to_string()),
+        (None, None) => {}
+    }
+}
+
+fn compare_properties(old: &Value, new: &Value, path: &str, report: &mut CompatibilityReport) {
+    let empty = serde_json::Map::new();
+    let old_props = old.get("properties").and_then(Value::as_object).unwrap_or(&empty);
+    let new_props = new.get("properties").and_then(Value::as_object).unwrap_or(&empty);
+    let old_required = required(old);
+    let new_required = required(new);
+    let closed = new.get("additionalProperties") == Some(&Value::Bool(false));
+
+    if old.get("additionalProperties") != Some(&Value::Bool(false)) && closed {
+        report.push(Severity::Breaking, path, "additional properties no longer allowed".to_string());
+    }
+
+    for (name, old_schema) in old_props {
+        let field = join(path, name);
+        match new_props.get(name) {
+            Some(new_schema) => compare(old_schema, new_schema, &field, report),
+            None if closed => report.push(Severity::Breaking, &field, "field removed".to_string()),
+            None => report.push(Severity::Compatible, &field, "field removed; still accepted as an additional property".to_string()),
+        }
+    }
+
+    for name in new_props.keys().filter(|name| !old_props.contains_key(*name)) {
+        if !new_required.contains(name) {
+            report.push(Severity::Compatible, &join(path, name), "optional field added".to_string());
+        }
+    }
+
+    for name in new_required.difference(&old_required) {
+        report.push(Severity::Breaking, &join(path, name), "field is now required".to_string());
+    }
+    for name in old_required.difference(&new_required) {
+        report.push(Severity::Compatible, &join(path, name), "field is no longer required".to_string());
+    }
+}
+
+fn required(schema: &Value) -> BTreeSet<String> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(|n| n.as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn name_v1() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "title": { "type": "string", "enum": ["mr", "ms", "dr"] },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["name"]
+        })
+    }
+
+    fn messages(report: &CompatibilityReport, severity: Severity) -> Vec<String> {
+        report
+            .changes
+            .iter()
+            .filter(|c| c.severity == severity)
+            .map(|c| format!("{}: {}", c.path, c.message))
+            .collect()
+    }
+
+    #[test]
+    fn identical_schemas_have_no_changes() {
+        let report = check_compatibility(&name_v1(), &name_v1());
+        assert!(report.changes.is_empty());
+        assert!(report.is_compatible());
+    }
+
+    #[test]
+    fn additive_changes_are_compatible() {
+        let mut v2 = name_v1();
+        v2["properties"]["nickname"] = json!({ "type": "string" });
+        v2["properties"]["title"]["enum"] = json!(["mr", "ms", "dr", "mx"]);
+        v2["properties"]["tags"]["items"]["type"] = json!(["string", "null"]);
+
+        let report = check_compatibility(&name_v1(), &v2);
+        assert!(report.is_compatible(), "{:?}", report);
+        assert_eq!(
+            messages(&report, Severity::Compatible),
+            vec![
+                "tags[]: type now also accepts null",
+                "title: enum value \"mx\" added",
+                "nickname: optional field added",
+            ]
+        );
+    }
+
+    #[test]
+    fn breaking_changes_are_reported_with_paths() {
+        let v2 = json!({
+            "type": "object",
+            "properties": {
+                "first": { "type": "string" },
+                "title": { "type": "string", "enum": ["mr", "ms"] },
+                "tags": { "type": "array", "items": { "type": "integer" } }
+            },
+            "required": ["first"],
+            "additionalProperties": false
+        });
+
+        let report = check_compatibility(&name_v1(), &v2);
+        assert!(!report.is_compatible());
+        assert_eq!(
+            messages(&report, Severity::Breaking),
+            vec![
+                ".: additional properties no longer allowed",
+                "name: field removed",
+                "tags[]: type no longer accepts string",
+                "title: enum value \"dr\" removed",
+                "first: field is now required",
+            ]
+        );
+    }
+
+    #[test]
+    fn integer_to_number_is_compatible() {
+        let report = check_compatibility(&json!({"type": "integer"}), &json!({"type": "number"}));
+        assert!(report.is_compatible());
+    }
+
+    #[test]
+    fn compares_schema_files() {
+        let dir = TempDir::new().unwrap();
+        let old = dir.path().join("v1.json");
+        let new = dir.path().join("v2.json");
+        fs::write(&old, name_v1().to_string()).unwrap();
+        fs::write(&new, json!({"type": "object", "required": ["name", "email"]}).to_string()).unwrap();
+
+        let report = check_schema_files(&old, &new).unwrap();
+        let breaking: Vec<_> = report.breaking().map(|c| c.to_string()).collect();
+        assert_eq!(breaking, vec!["[breaking] email: field is now required"]);
+
+        assert!(matches!(
+            check_schema_files(&old, dir.path().join("missing.json")),
+            Err(SchemaError::Io(_))
+        ));
+    }
+}
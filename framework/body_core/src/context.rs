@@ -0,0 +1,285 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+pub use tokio_util::sync::CancellationToken;
+
+pub use crate::headers::TRACE_ID;
+use crate::{headers, BodyBus, BusError, Envelope, StreamSink};
+
+/// Per-request context handed to handlers alongside the envelope
+///
+/// Carries a bus handle for nested requests, the caller's deadline, a
+/// cancellation token and string metadata. Requests made through
+/// [`RequestContext::request`] inherit all of these automatically.
+#[derive(Clone)]
+pub struct RequestContext {
+    bus: Arc<dyn BodyBus>,
+    deadline: Option<Instant>,
+    cancellation: CancellationToken,
+    metadata: HashMap<String, String>,
+    envelope_id: Option<String>,
+    stream: Option<StreamSink>,
+}
+
+impl RequestContext {
+    /// Create a root context with no deadline and empty metadata
+    pub fn new(bus: Arc<dyn BodyBus>) -> Self {
+        Self {
+            bus,
+            deadline: None,
+            cancellation: CancellationToken::new(),
+            metadata: HashMap::new(),
+            envelope_id: None,
+            stream: None,
+        }
+    }
+
+    /// Build the context for a handler serving `envelope`
+    ///
+    /// A nested call inherits the parent's deadline and metadata and gets a
+    /// child cancellation token. Propagated envelope headers become metadata
+    /// and a `deadline` header tightens the deadline, so a context rebuilt
+    /// on the far side of a transport matches the caller's. The trace id
+    /// defaults to the id of the envelope that started the chain.
+    pub fn for_request(
+        bus: Arc<dyn BodyBus>,
+        envelope: &Envelope,
+        parent: Option<&RequestContext>,
+    ) -> Self {
+        let mut ctx = match parent {
+            Some(parent) => parent.child().with_bus(bus),
+            None => Self::new(bus),
+        };
+        if let Some(headers) = &envelope.headers {
+            for (key, value) in headers.propagated() {
+                ctx.metadata
+                    .entry(key.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+            if let Some(deadline) = headers.deadline() {
+                let remaining = deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO);
+                ctx = ctx.with_timeout(remaining);
+            }
+        }
+        ctx.metadata
+            .entry(TRACE_ID.to_string())
+            .or_insert_with(|| envelope.id.clone());
+        ctx.envelope_id = Some(envelope.id.clone());
+        ctx
+    }
+
+    /// Derive a context sharing this deadline and metadata with a child cancellation token
+    ///
+    /// The child does not inherit the stream sink; nested requests reply on their own.
+    pub fn child(&self) -> Self {
+        Self {
+            bus: Arc::clone(&self.bus),
+            deadline: self.deadline,
+            cancellation: self.cancellation.child_token(),
+            metadata: self.metadata.clone(),
+            envelope_id: self.envelope_id.clone(),
+            stream: None,
+        }
+    }
+
+    /// Replace the bus used for nested requests
+    pub fn with_bus(mut self, bus: Arc<dyn BodyBus>) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    /// Set an absolute deadline, keeping the earlier one if already set
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(match self.deadline {
+            Some(current) => current.min(deadline),
+            None => deadline,
+        });
+        self
+    }
+
+    /// Set a deadline relative to now
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Use an existing cancellation token
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Attach the sink a streamed request is answered through
+    pub fn with_stream_sink(mut self, sink: StreamSink) -> Self {
+        self.stream = Some(sink);
+        self
+    }
+
+    /// Add a metadata entry; metadata becomes request headers, so `version` is reserved
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Result<Self, BusError> {
+        headers::check_key(key)?;
+        self.metadata.insert(key.to_string(), value.to_string());
+        Ok(self)
+    }
+
+    /// Bus handle for nested requests
+    pub fn bus(&self) -> &Arc<dyn BodyBus> {
+        &self.bus
+    }
+
+    /// Absolute deadline, if the caller set one
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Time left before the deadline, zero once it has passed
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether the deadline has passed
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_some_and(|remaining| remaining.is_zero())
+    }
+
+    /// Cancellation token for cooperative cancellation
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Whether the request has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// All request metadata
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// Look up a single metadata value
+    pub fn metadata_value(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// Trace id shared across the call chain
+    pub fn trace_id(&self) -> Option<&str> {
+        self.metadata_value(TRACE_ID)
+    }
+
+    /// Id of the envelope being handled, recorded as the cause of nested requests
+    pub fn envelope_id(&self) -> Option<&str> {
+        self.envelope_id.as_deref()
+    }
+
+    /// Sink for the reply when the caller used `request_stream`
+    pub fn stream_sink(&self) -> Option<&StreamSink> {
+        self.stream.as_ref()
+    }
+
+    /// Send a nested request that inherits this context
+    pub async fn request(&self, envelope: Envelope) -> Result<Value, BusError> {
+        if self.is_expired() {
+            return Err(BusError::Timeout);
+        }
+        self.bus.request_with_context(envelope, self).await
+    }
+
+    /// Publish a fire-and-forget event on the same bus, carrying this context's headers
+    pub async fn publish(&self, mut envelope: Envelope) -> Result<(), BusError> {
+        headers::stamp_outbound(&mut envelope, Some(self), None);
+        self.bus.publish(envelope).await
+    }
+}
+
+impl std::fmt::Debug for RequestContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestContext")
+            .field("deadline", &self.deadline)
+            .field("cancelled", &self.is_cancelled())
+            .field("metadata", &self.metadata)
+            .field("envelope_id", &self.envelope_id)
+            .field("streaming", &self.stream.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NullBus;
+    use serde_json::json;
+
+    fn envelope() -> Envelope {
+        Envelope::new_request("test", "action", "demo/v1/Test", json!({}))
+    }
+
+    #[test]
+    fn root_context_uses_envelope_id_as_trace_id() {
+        let envelope = envelope();
+        let ctx = RequestContext::for_request(Arc::new(NullBus), &envelope, None);
+        assert_eq!(ctx.trace_id(), Some(envelope.id.as_str()));
+        assert!(ctx.deadline().is_none());
+        assert!(!ctx.is_cancelled());
+    }
+
+    #[test]
+    fn nested_context_inherits_parent() {
+        let parent = RequestContext::new(Arc::new(NullBus))
+            .with_timeout(Duration::from_secs(5))
+            .with_metadata(TRACE_ID, "trace-1").unwrap();
+        let ctx = RequestContext::for_request(Arc::new(NullBus), &envelope(), Some(&parent));
+
+        assert_eq!(ctx.trace_id(), Some("trace-1"));
+        assert_eq!(ctx.deadline(), parent.deadline());
+
+        parent.cancellation().cancel();
+        assert!(ctx.is_cancelled());
+    }
+
+    #[test]
+    fn deadline_only_tightens() {
+        let ctx = RequestContext::new(Arc::new(NullBus))
+            .with_timeout(Duration::from_millis(10))
+            .with_timeout(Duration::from_secs(60));
+        assert!(ctx.remaining().unwrap() <= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn headers_rebuild_remote_context() {
+        let mut envelope = envelope()
+            .with_header(TRACE_ID, "trace-3").unwrap()
+            .with_header("tenant", "acme").unwrap()
+            .with_header(headers::SOURCE_CELL, "greeter").unwrap();
+        envelope
+            .headers_mut()
+            .set_deadline(SystemTime::now() + Duration::from_secs(2));
+
+        let ctx = RequestContext::for_request(Arc::new(NullBus), &envelope, None);
+        assert_eq!(ctx.trace_id(), Some("trace-3"));
+        assert_eq!(ctx.metadata_value("tenant"), Some("acme"));
+        assert_eq!(ctx.metadata_value(headers::SOURCE_CELL), None);
+        assert_eq!(ctx.envelope_id(), Some(envelope.id.as_str()));
+        assert!(ctx.remaining().unwrap() <= Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn nested_request_propagates_context() {
+        let ctx = RequestContext::new(Arc::new(NullBus))
+            .with_timeout(Duration::from_secs(5))
+            .with_metadata(TRACE_ID, "trace-2").unwrap();
+        let result = ctx.request(envelope()).await.unwrap();
+        assert_eq!(result["trace_id"], "trace-2");
+        assert_eq!(result["has_deadline"], true);
+    }
+
+    #[tokio::test]
+    async fn expired_context_fails_fast() {
+        let ctx = RequestContext::new(Arc::new(NullBus)).with_timeout(Duration::ZERO);
+        assert!(matches!(ctx.request(envelope()).await, Err(BusError::Timeout)));
+    }
+}
@@ -0,0 +1,312 @@
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::{BodyBus, BusError, Envelope, Handler, RequestContext, SharedHandler, Subscription};
+
+/// Typed binding of a subject, a payload schema and its request/response types
+pub trait Contract: Send + Sync + 'static {
+    /// Service token of the subject
+    const SERVICE: &'static str;
+    /// Verb token of the subject
+    const VERB: &'static str;
+    /// Payload schema id, e.g. `demo/v1/Name`
+    const SCHEMA: &'static str;
+
+    type Request: Serialize + DeserializeOwned + Send + Sync + 'static;
+    type Response: Serialize + DeserializeOwned + Send + Sync + 'static;
+
+    /// NATS subject the contract is served on
+    fn subject() -> String {
+        format!("cbs.{}.{}", Self::SERVICE, Self::VERB)
+    }
+
+    /// Build the request envelope for a typed request
+    fn envelope(request: &Self::Request) -> Result<Envelope, BusError> {
+        let payload = serde_json::to_value(request).map_err(|e| {
+            BusError::Serialization(format!("Failed to serialize {} request: {}", Self::SCHEMA, e))
+        })?;
+        Ok(Envelope::new_request(Self::SERVICE, Self::VERB, Self::SCHEMA, payload))
+    }
+}
+
+/// Deserialize a payload, reporting the offending field on failure
+///
+/// Errors are `BadRequest` with details of the form
+/// `{"schema": "...", "fields": [{"path": "user.name", "error": "..."}]}`.
+pub fn decode_payload<T: DeserializeOwned>(schema: &str, payload: Value) -> Result<T, BusError> {
+    serde_path_to_error::deserialize(payload).map_err(|e| {
+        let path = field_path(&e);
+        let error = e.into_inner().to_string();
+        BusError::bad_request_with_details(
+            format!("Invalid {} payload at `{}`: {}", schema, path, error),
+            json!({
+                "schema": schema,
+                "fields": [{ "path": path, "error": error }],
+            }),
+        )
+    })
+}
+
+/// Dotted path of the failing field, including the name of a missing field
+fn field_path(error: &serde_path_to_error::Error<serde_json::Error>) -> String {
+    let parent = error.path().to_string();
+    let message = error.inner().to_string();
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next());
+    match (parent.as_str(), missing) {
+        (".", Some(field)) => field.to_string(),
+        (_, Some(field)) => format!("{}.{}", parent, field),
+        _ => parent,
+    }
+}
+
+/// Adapter that runs a typed async closure as a `Handler` for contract `C`
+pub struct ContractHandler<C, F> {
+    f: F,
+    contract: PhantomData<fn() -> C>,
+}
+
+#[async_trait]
+impl<C, F, Fut> Handler for ContractHandler<C, F>
+where
+    C: Contract,
+    F: Fn(C::Request, RequestContext) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<C::Response, BusError>> + Send,
+{
+    async fn handle(&self, envelope: Envelope, ctx: RequestContext) -> Result<Value, BusError> {
+        let payload = envelope.payload.unwrap_or(Value::Null);
+        let request = decode_payload::<C::Request>(C::SCHEMA, payload)?;
+        let response = (self.f)(request, ctx).await?;
+        serde_json::to_value(response).map_err(|e| {
+            BusError::Serialization(format!("Failed to serialize {} response: {}", C::SCHEMA, e))
+        })
+    }
+}
+
+/// Wrap a typed async closure for `BodyBus::subscribe` on `C::subject()`
+pub fn contract_handler<C, F, Fut>(f: F) -> SharedHandler
+where
+    C: Contract,
+    F: Fn(C::Request, RequestContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<C::Response, BusError>> + Send + 'static,
+{
+    Arc::new(ContractHandler::<C, F> {
+        f,
+        contract: PhantomData,
+    })
+}
+
+/// Typed request/response on top of any `BodyBus`
+#[async_trait]
+pub trait TypedBus {
+    /// Send a typed request and decode the typed response
+    async fn call<C: Contract>(&self, request: C::Request) -> Result<C::Response, BusError>;
+
+    /// Serve contract `C` with a typed handler
+    async fn serve<C, F, Fut>(&self, handler: F) -> Result<Subscription, BusError>
+    where
+        C: Contract,
+        F: Fn(C::Request, RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<C::Response, BusError>> + Send + 'static;
+}
+
+#[async_trait]
+impl<B: BodyBus + ?Sized> TypedBus for B {
+    async fn call<C: Contract>(&self, request: C::Request) -> Result<C::Response, BusError> {
+        let envelope = C::envelope(&request)?;
+        let response = self.request(envelope).await?;
+        decode_response::<C>(response)
+    }
+
+    async fn serve<C, F, Fut>(&self, handler: F) -> Result<Subscription, BusError>
+    where
+        C: Contract,
+        F: Fn(C::Request, RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<C::Response, BusError>> + Send + 'static,
+    {
+        self.subscribe(&C::subject(), contract_handler::<C, F, Fut>(handler))
+            .await
+    }
+}
+
+impl RequestContext {
+    /// Send a typed nested request that inherits this context
+    pub async fn call<C: Contract>(&self, request: C::Request) -> Result<C::Response, BusError> {
+        let envelope = C::envelope(&request)?;
+        let response = self.request(envelope).await?;
+        decode_response::<C>(response)
+    }
+}
+
+/// A reply that does not match the contract is the responder's fault, not the caller's
+fn decode_response<C: Contract>(response: Value) -> Result<C::Response, BusError> {
+    decode_payload::<C::Response>(C::SCHEMA, response).map_err(|e| match e {
+        BusError::BadRequest { message, .. } => BusError::Serialization(message),
+        other => other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LocalBus;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct NameRequest {
+        name: String,
+        #[serde(default)]
+        address: Option<Address>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Address {
+        city: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Greeting {
+        message: String,
+    }
+
+    struct SayHello;
+
+    impl Contract for SayHello {
+        const SERVICE: &'static str = "greeter";
+        const VERB: &'static str = "say_hello";
+        const SCHEMA: &'static str = "demo/v1/Name";
+        type Request = NameRequest;
+        type Response = Greeting;
+    }
+
+    struct Relay;
+
+    impl Contract for Relay {
+        const SERVICE: &'static str = "relay";
+        const VERB: &'static str = "forward";
+        const SCHEMA: &'static str = "demo/v1/Name";
+        type Request = NameRequest;
+        type Response = Greeting;
+    }
+
+    async fn greeter_bus() -> (LocalBus, Subscription) {
+        let bus = LocalBus::new();
+        let subscription = bus
+            .serve::<SayHello, _, _>(|request, _ctx| async move {
+                Ok(Greeting {
+                    message: format!("Hello {}!", request.name),
+                })
+            })
+            .await
+            .unwrap();
+        (bus, subscription)
+    }
+
+    #[test]
+    fn contract_builds_subject_and_envelope() {
+        assert_eq!(SayHello::subject(), "cbs.greeter.say_hello");
+
+        let envelope = SayHello::envelope(&NameRequest {
+            name: "Ada".to_string(),
+            address: None,
+        })
+        .unwrap();
+        assert_eq!(envelope.subject(), "cbs.greeter.say_hello");
+        assert_eq!(envelope.schema, "demo/v1/Name");
+        assert_eq!(envelope.payload.unwrap()["name"], "Ada");
+    }
+
+    #[tokio::test]
+    async fn typed_call_roundtrip() {
+        let (bus, _greeter) = greeter_bus().await;
+
+        let greeting = bus
+            .call::<SayHello>(NameRequest {
+                name: "Ada".to_string(),
+                address: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(greeting.message, "Hello Ada!");
+    }
+
+    #[tokio::test]
+    async fn typed_call_through_context() {
+        let (bus, _greeter) = greeter_bus().await;
+        let _relay = bus
+            .serve::<Relay, _, _>(|request, ctx: RequestContext| async move {
+                ctx.call::<SayHello>(request).await
+            })
+            .await
+            .unwrap();
+
+        let greeting = bus
+            .call::<Relay>(NameRequest {
+                name: "Grace".to_string(),
+                address: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(greeting.message, "Hello Grace!");
+    }
+
+    #[tokio::test]
+    async fn missing_field_is_bad_request_with_details() {
+        let (bus, _greeter) = greeter_bus().await;
+
+        let envelope = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+        match bus.request(envelope).await {
+            Err(BusError::BadRequest { message, details }) => {
+                assert!(message.contains("`name`"));
+                let details = details.unwrap();
+                assert_eq!(details["schema"], "demo/v1/Name");
+                assert_eq!(details["fields"][0]["path"], "name");
+                assert_eq!(details["fields"][0]["error"], "missing field `name`");
+            }
+            other => panic!("Expected BadRequest, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_type_error_reports_path() {
+        let payload = json!({"name": "Ada", "address": {"city": 42}});
+        let error = decode_payload::<NameRequest>("demo/v1/Name", payload).unwrap_err();
+
+        let details = error.details().unwrap();
+        assert_eq!(details["fields"][0]["path"], "address.city");
+        assert!(details["fields"][0]["error"]
+            .as_str()
+            .unwrap()
+            .contains("invalid type"));
+    }
+
+    #[test]
+    fn nested_missing_field_reports_full_path() {
+        let payload = json!({"name": "Ada", "address": {}});
+        let error = decode_payload::<NameRequest>("demo/v1/Name", payload).unwrap_err();
+        assert_eq!(error.details().unwrap()["fields"][0]["path"], "address.city");
+    }
+
+    #[tokio::test]
+    async fn mismatched_response_is_serialization_error() {
+        let bus = LocalBus::new();
+        let _greeter = bus
+            .subscribe("cbs.greeter.say_hello", crate::sync_handler(|_| Ok(json!({"unexpected": true}))))
+            .await
+            .unwrap();
+
+        let result = bus
+            .call::<SayHello>(NameRequest {
+                name: "Ada".to_string(),
+                address: None,
+            })
+            .await;
+        assert!(matches!(result, Err(BusError::Serialization(_))));
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+use crate::CellConfig;
+
+/// Problems with the `dependencies:` lists of an application's cells
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DependencyError {
+    #[error("Cell {0} is listed more than once")]
+    DuplicateCell(String),
+    #[error("Cell {cell} depends on unknown cell {dependency}")]
+    UnknownDependency { cell: String, dependency: String },
+    #[error("Dependency cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// Cells in an order that starts every cell after its dependencies
+///
+/// Cells that do not depend on each other keep their app.yaml order. A
+/// cycle is reported as the path that closes it, e.g. `a -> b -> a`.
+pub fn startup_order(cells: &[CellConfig]) -> Result<Vec<&CellConfig>, DependencyError> {
+    let mut index = HashMap::new();
+    for (position, cell) in cells.iter().enumerate() {
+        if index.insert(cell.name.as_str(), position).is_some() {
+            return Err(DependencyError::DuplicateCell(cell.name.clone()));
+        }
+    }
+    for cell in cells {
+        if let Some(dependency) = cell.dependencies.iter().find(|d| !index.contains_key(d.as_str())) {
+            return Err(DependencyError::UnknownDependency {
+                cell: cell.name.clone(),
+                dependency: dependency.clone(),
+            });
+        }
+    }
+
+    let mut placed = HashSet::new();
+    let mut order = Vec::with_capacity(cells.len());
+    while order.len() < cells.len() {
+        let ready = cells.iter().find(|cell| {
+            !placed.contains(cell.name.as_str()) && cell.dependencies.iter().all(|d| placed.contains(d.as_str()))
+        });
+        match ready {
+            Some(cell) => {
+                placed.insert(cell.name.as_str());
+                order.push(cell);
+            }
+            None => return Err(DependencyError::Cycle(find_cycle(cells, &index, &placed))),
+        }
+    }
+    Ok(order)
+}
+
+/// Follow dependencies among the unplaced cells until one repeats
+fn find_cycle(cells: &[CellConfig], index: &HashMap<&str, usize>, placed: &HashSet<&str>) -> Vec<String> {
+    let mut current = cells.iter().find(|cell| !placed.contains(cell.name.as_str())).expect("an unplaced cell");
+    let mut path: Vec<&str> = Vec::new();
+    loop {
+        if let Some(start) = path.iter().position(|name| *name == current.name) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|name| name.to_string()).collect();
+            cycle.push(current.name.clone());
+            return cycle;
+        }
+        path.push(&current.name);
+        // Every unplaced cell has at least one unplaced dependency
+        let next = current
+            .dependencies
+            .iter()
+            .find(|d| !placed.contains(d.as_str()))
+            .expect("an unplaced dependency");
+        current = &cells[index[next.as_str()]];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppConfig;
+
+    fn cell(name: &str, dependencies: &[&str]) -> CellConfig {
+        CellConfig {
+            name: name.to_string(),
+            path: format!("cells/{}", name),
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            limits: None,
+            config: None,
+        }
+    }
+
+    fn names(order: Vec<&CellConfig>) -> Vec<&str> {
+        order.into_iter().map(|cell| cell.name.as_str()).collect()
+    }
+
+    #[test]
+    fn dependencies_start_first_and_ties_keep_declaration_order() {
+        let cells = [cell("ui", &["logic", "store"]), cell("logic", &["store"]), cell("store", &[]), cell("log", &[])];
+        assert_eq!(names(startup_order(&cells).unwrap()), ["store", "logic", "ui", "log"]);
+    }
+
+    #[test]
+    fn birthmap_dependencies_are_ordered() {
+        let birthmap: AppConfig =
+            serde_yaml::from_str(include_str!("../../../applications/flutter_flow_web/birthmap.yaml")).unwrap();
+        let order = names(birthmap.startup_order().unwrap());
+        let position = |name| order.iter().position(|cell| *cell == name).unwrap();
+        for cell in &birthmap.cells {
+            for dependency in &cell.dependencies {
+                assert!(position(dependency.as_str()) < position(cell.name.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn cycles_are_reported_as_a_path() {
+        let cells = [cell("a", &["b"]), cell("b", &["c"]), cell("c", &["b"]), cell("d", &[])];
+        assert_eq!(
+            startup_order(&cells).unwrap_err(),
+            DependencyError::Cycle(vec!["b".to_string(), "c".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            startup_order(&[cell("a", &["a"])]).unwrap_err().to_string(),
+            "Dependency cycle: a -> a"
+        );
+    }
+
+    #[test]
+    fn unknown_and_duplicate_cells_are_rejected() {
+        let unknown = startup_order(&[cell("a", &["ghost"])]).unwrap_err();
+        assert_eq!(unknown.to_string(), "Cell a depends on unknown cell ghost");
+        let duplicate = startup_order(&[cell("a", &[]), cell("a", &[])]).unwrap_err();
+        assert_eq!(duplicate, DependencyError::DuplicateCell("a".to_string()));
+    }
+}
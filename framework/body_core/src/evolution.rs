@@ -0,0 +1,487 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::middleware::{DispatchNext, Middleware};
+use crate::validation::SchemaError;
+use crate::{subject, BusError, Envelope, Handler, RequestContext, SharedHandler};
+
+/// Parsed envelope `schema` id of the form `domain/v{n}/Type`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SchemaId {
+    pub domain: String,
+    pub version: u32,
+    pub name: String,
+}
+
+impl SchemaId {
+    /// Parse `domain/v{n}/Type`
+    pub fn parse(id: &str) -> Result<Self, BusError> {
+        let invalid = || BusError::bad_request(format!("Invalid schema id: {}", id));
+        let mut parts = id.split('/');
+        let (Some(domain), Some(version), Some(name), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let version = version
+            .strip_prefix('v')
+            .and_then(|n| n.parse::<u32>().ok())
+            .ok_or_else(invalid)?;
+        if domain.is_empty() || name.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            domain: domain.to_string(),
+            version,
+            name: name.to_string(),
+        })
+    }
+
+    /// Same type at another version
+    pub fn with_version(&self, version: u32) -> Self {
+        Self {
+            version,
+            ..self.clone()
+        }
+    }
+
+    /// Whether both ids name the same type, regardless of version
+    pub fn same_type(&self, other: &SchemaId) -> bool {
+        self.domain == other.domain && self.name == other.name
+    }
+
+    fn type_key(&self) -> String {
+        format!("{}/{}", self.domain, self.name)
+    }
+}
+
+impl fmt::Display for SchemaId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/v{}/{}", self.domain, self.version, self.name)
+    }
+}
+
+/// Payload conversion between adjacent versions of a type
+pub type Converter = Arc<dyn Fn(Value) -> Result<Value, BusError> + Send + Sync>;
+
+/// Upcasters (`vN` -> `vN+1`) and downcasters (`vN+1` -> `vN`) per payload type
+///
+/// Conversions between distant versions chain the adjacent steps.
+#[derive(Default)]
+pub struct SchemaEvolution {
+    upcasters: HashMap<(String, u32), Converter>,
+    downcasters: HashMap<(String, u32), Converter>,
+}
+
+impl SchemaEvolution {
+    /// Registry without converters; only same-version payloads pass
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the conversion from `from` (e.g. `demo/v1/Name`) to the next version
+    pub fn register_upcaster<F>(&mut self, from: &str, f: F) -> Result<(), SchemaError>
+    where
+        F: Fn(Value) -> Result<Value, BusError> + Send + Sync + 'static,
+    {
+        let id = parse_for_registration(from)?;
+        debug!(schema = %id, to = id.version + 1, "SchemaEvolution: Registered upcaster");
+        self.upcasters.insert((id.type_key(), id.version), Arc::new(f));
+        Ok(())
+    }
+
+    /// Register the conversion from `from` (e.g. `demo/v2/Name`) to the previous version
+    pub fn register_downcaster<F>(&mut self, from: &str, f: F) -> Result<(), SchemaError>
+    where
+        F: Fn(Value) -> Result<Value, BusError> + Send + Sync + 'static,
+    {
+        let id = parse_for_registration(from)?;
+        if id.version == 0 {
+            return Err(SchemaError::InvalidSchema {
+                id: from.to_string(),
+                message: "v0 has no previous version".to_string(),
+            });
+        }
+        debug!(schema = %id, to = id.version - 1, "SchemaEvolution: Registered downcaster");
+        self.downcasters.insert((id.type_key(), id.version), Arc::new(f));
+        Ok(())
+    }
+
+    /// Whether `from` can be converted to `to` with the registered steps
+    pub fn can_convert(&self, from: &SchemaId, to: &SchemaId) -> bool {
+        from.same_type(to) && self.path(from, to).is_some()
+    }
+
+    /// Convert a payload between two versions of the same type
+    ///
+    /// Fails with `BadRequest` when the ids name different types or a step is missing.
+    pub fn convert(&self, payload: Value, from: &SchemaId, to: &SchemaId) -> Result<Value, BusError> {
+        if !from.same_type(to) {
+            return Err(BusError::bad_request(format!(
+                "Cannot convert {} to {}: different types",
+                from, to
+            )));
+        }
+        let steps = self.path(from, to).ok_or_else(|| {
+            BusError::bad_request(format!("No conversion registered from {} to {}", from, to))
+        })?;
+
+        steps.into_iter().try_fold(payload, |payload, step| step(payload))
+    }
+
+    fn path(&self, from: &SchemaId, to: &SchemaId) -> Option<Vec<Converter>> {
+        let key = from.type_key();
+        let (converters, versions): (_, Vec<u32>) = if from.version <= to.version {
+            (&self.upcasters, (from.version..to.version).collect())
+        } else {
+            (&self.downcasters, (to.version + 1..=from.version).rev().collect())
+        };
+        versions
+            .into_iter()
+            .map(|version| converters.get(&(key.clone(), version)).cloned())
+            .collect()
+    }
+}
+
+impl fmt::Debug for SchemaEvolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut upcasters: Vec<_> = self.upcasters.keys().collect();
+        upcasters.sort();
+        let mut downcasters: Vec<_> = self.downcasters.keys().collect();
+        downcasters.sort();
+        f.debug_struct("SchemaEvolution")
+            .field("upcasters", &upcasters)
+            .field("downcasters", &downcasters)
+            .finish()
+    }
+}
+
+fn parse_for_registration(id: &str) -> Result<SchemaId, SchemaError> {
+    SchemaId::parse(id).map_err(|e| SchemaError::InvalidSchema {
+        id: id.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Version a handler speaks, and the reply type to convert back for callers
+#[derive(Debug, Clone)]
+struct HandlerVersion {
+    speaks: SchemaId,
+    replies: Option<SchemaId>,
+}
+
+impl HandlerVersion {
+    fn parse(speaks: &str, replies: Option<&str>) -> Result<Self, BusError> {
+        Ok(Self {
+            speaks: SchemaId::parse(speaks)?,
+            replies: replies.map(SchemaId::parse).transpose()?,
+        })
+    }
+
+    /// Run `handle` on `envelope` converted to this version, converting the reply back
+    async fn serve<F, Fut>(
+        &self,
+        evolution: &SchemaEvolution,
+        mut envelope: Envelope,
+        ctx: RequestContext,
+        handle: F,
+    ) -> Result<Value, BusError>
+    where
+        F: FnOnce(Envelope, RequestContext) -> Fut,
+        Fut: Future<Output = Result<Value, BusError>>,
+    {
+        let caller = match SchemaId::parse(&envelope.schema) {
+            Ok(caller) if caller.same_type(&self.speaks) && caller.version != self.speaks.version => caller,
+            _ => return handle(envelope, ctx).await,
+        };
+
+        debug!(from = %caller, to = %self.speaks, "Converting request payload");
+        if let Some(payload) = envelope.payload.take() {
+            envelope.payload = Some(evolution.convert(payload, &caller, &self.speaks)?);
+        }
+        envelope.schema = self.speaks.to_string();
+
+        let response = handle(envelope, ctx).await?;
+        match &self.replies {
+            // The caller reads the reply type at the version it asked with
+            Some(replies) => {
+                let target = replies.with_version(caller.version);
+                debug!(from = %replies, to = %target, "Converting reply payload");
+                evolution
+                    .convert(response, replies, &target)
+                    .map_err(|e| BusError::Internal(format!("Reply conversion failed: {}", e)))
+            }
+            None => Ok(response),
+        }
+    }
+}
+
+/// Handler wrapper that converts payloads between the caller's and the handler's version
+pub struct VersionedHandler {
+    inner: SharedHandler,
+    evolution: Arc<SchemaEvolution>,
+    version: HandlerVersion,
+}
+
+#[async_trait]
+impl Handler for VersionedHandler {
+    async fn handle(&self, envelope: Envelope, ctx: RequestContext) -> Result<Value, BusError> {
+        self.version
+            .serve(&self.evolution, envelope, ctx, |envelope, ctx| self.inner.handle(envelope, ctx))
+            .await
+    }
+}
+
+/// Serve `handler`, which speaks `speaks`, to callers on any convertible version
+///
+/// Requests on other versions of the same type are upcast or downcast before
+/// the handler runs; other schemas pass through untouched.
+pub fn versioned_handler(
+    evolution: Arc<SchemaEvolution>,
+    speaks: &str,
+    handler: SharedHandler,
+) -> Result<SharedHandler, BusError> {
+    Ok(Arc::new(VersionedHandler {
+        inner: handler,
+        evolution,
+        version: HandlerVersion::parse(speaks, None)?,
+    }))
+}
+
+/// Like `versioned_handler`, also converting the `replies` payload back to the caller's version
+pub fn versioned_handler_with_reply(
+    evolution: Arc<SchemaEvolution>,
+    speaks: &str,
+    replies: &str,
+    handler: SharedHandler,
+) -> Result<SharedHandler, BusError> {
+    Ok(Arc::new(VersionedHandler {
+        inner: handler,
+        evolution,
+        version: HandlerVersion::parse(speaks, Some(replies))?,
+    }))
+}
+
+/// Middleware that converts payloads for every handler it knows the version of
+///
+/// Handlers subscribed through the wrapped bus on a mapped subject pattern
+/// receive requests at the version they speak, whatever version the caller
+/// used, and their replies go back at the caller's version, without wrapping
+/// each handler in `versioned_handler`. The most specific pattern applies;
+/// unmapped subjects and other schemas pass through untouched. Layer it before
+/// `ValidationMiddleware` so inbound checks see the converted payload.
+#[derive(Debug)]
+pub struct EvolutionMiddleware {
+    evolution: Arc<SchemaEvolution>,
+    handlers: Vec<(String, HandlerVersion)>,
+}
+
+impl EvolutionMiddleware {
+    /// Middleware with no handler versions yet; everything passes through
+    pub fn new(evolution: Arc<SchemaEvolution>) -> Self {
+        Self {
+            evolution,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Handlers on `pattern` speak `speaks`
+    pub fn handler(self, pattern: &str, speaks: &str) -> Result<Self, BusError> {
+        self.with_version(pattern, HandlerVersion::parse(speaks, None)?)
+    }
+
+    /// Handlers on `pattern` speak `speaks` and reply with `replies`, converted back for callers
+    pub fn handler_with_reply(self, pattern: &str, speaks: &str, replies: &str) -> Result<Self, BusError> {
+        self.with_version(pattern, HandlerVersion::parse(speaks, Some(replies))?)
+    }
+
+    fn with_version(mut self, pattern: &str, version: HandlerVersion) -> Result<Self, BusError> {
+        subject::validate_pattern(pattern)?;
+        self.handlers.retain(|(p, _)| p != pattern);
+        self.handlers.push((pattern.to_string(), version));
+        Ok(self)
+    }
+
+    fn version_for(&self, subject: &str) -> Option<&HandlerVersion> {
+        let pattern = subject::best_match(self.handlers.iter().map(|(pattern, _)| pattern.as_str()), subject)?;
+        self.handlers.iter().find(|(p, _)| p == pattern).map(|(_, version)| version)
+    }
+}
+
+#[async_trait]
+impl Middleware for EvolutionMiddleware {
+    async fn dispatch(
+        &self,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        match self.version_for(&envelope.subject()) {
+            Some(version) => version.serve(&self.evolution, envelope, ctx, |envelope, ctx| next.run(envelope, ctx)).await,
+            None => next.run(envelope, ctx).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync_handler, BodyBus, LocalBus, MiddlewareBus};
+    use serde_json::json;
+
+    /// v1 `{"name"}` becomes v2 `{"first", "last"}`; v1 `{"message"}` greetings become v2 `{"text"}`
+    fn evolution() -> Arc<SchemaEvolution> {
+        let mut evolution = SchemaEvolution::new();
+        evolution
+            .register_upcaster("demo/v1/Name", |payload| {
+                let name = payload["name"].as_str().unwrap_or_default();
+                let (first, last) = name.split_once(' ').unwrap_or((name, ""));
+                Ok(json!({"first": first, "last": last}))
+            })
+            .unwrap();
+        evolution
+            .register_downcaster("demo/v2/Name", |payload| {
+                let name = format!("{} {}", payload["first"].as_str().unwrap_or_default(), payload["last"].as_str().unwrap_or_default());
+                Ok(json!({"name": name.trim()}))
+            })
+            .unwrap();
+        evolution
+            .register_upcaster("demo/v2/Name", |mut payload| {
+                payload["middle"] = json!(null);
+                Ok(payload)
+            })
+            .unwrap();
+        evolution
+            .register_downcaster("demo/v2/Greeting", |payload| Ok(json!({"message": payload["text"]})))
+            .unwrap();
+        Arc::new(evolution)
+    }
+
+    #[test]
+    fn parses_schema_ids() {
+        let id = SchemaId::parse("user_service/v12/Profile").unwrap();
+        assert_eq!(id.domain, "user_service");
+        assert_eq!(id.version, 12);
+        assert_eq!(id.name, "Profile");
+        assert_eq!(id.to_string(), "user_service/v12/Profile");
+
+        for invalid in ["demo/Name", "demo/1/Name", "demo/vx/Name", "demo/v1/Name/extra", "/v1/Name"] {
+            assert!(SchemaId::parse(invalid).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[test]
+    fn chains_upcasters_and_downcasters() {
+        let evolution = evolution();
+        let v1 = SchemaId::parse("demo/v1/Name").unwrap();
+        let v3 = v1.with_version(3);
+
+        let up = evolution.convert(json!({"name": "Ada Lovelace"}), &v1, &v3).unwrap();
+        assert_eq!(up, json!({"first": "Ada", "last": "Lovelace", "middle": null}));
+
+        // No v3 -> v2 downcaster
+        assert!(!evolution.can_convert(&v3, &v1));
+        let down = evolution
+            .convert(json!({"first": "Ada", "last": "Lovelace"}), &v1.with_version(2), &v1)
+            .unwrap();
+        assert_eq!(down, json!({"name": "Ada Lovelace"}));
+    }
+
+    #[test]
+    fn missing_steps_and_type_mismatches_are_rejected() {
+        let evolution = evolution();
+        let name = SchemaId::parse("demo/v1/Name").unwrap();
+        let other = SchemaId::parse("demo/v2/Other").unwrap();
+
+        assert!(matches!(
+            evolution.convert(json!({}), &name, &other),
+            Err(BusError::BadRequest { .. })
+        ));
+        assert!(matches!(
+            evolution.convert(json!({}), &name, &name.with_version(5)),
+            Err(BusError::BadRequest { .. })
+        ));
+        assert!(SchemaEvolution::new().register_downcaster("demo/v0/Name", Ok).is_err());
+        assert!(SchemaEvolution::new().register_upcaster("bogus", Ok).is_err());
+    }
+
+    #[tokio::test]
+    async fn bus_converts_between_caller_and_handler_versions() {
+        let bus = LocalBus::new();
+        let handler = sync_handler(|envelope| {
+            assert_eq!(envelope.schema, "demo/v2/Name");
+            let payload = envelope.payload.unwrap();
+            Ok(json!({"text": format!("Hello {}!", payload["first"].as_str().unwrap())}))
+        });
+        let _greeter = bus
+            .subscribe(
+                "cbs.greeter.say_hello",
+                versioned_handler_with_reply(evolution(), "demo/v2/Name", "demo/v2/Greeting", handler).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // v1 caller gets a v1 reply
+        let v1 = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({"name": "Ada Lovelace"}));
+        assert_eq!(bus.request(v1).await.unwrap(), json!({"message": "Hello Ada!"}));
+
+        // v2 caller is served as-is
+        let v2 = Envelope::new_request("greeter", "say_hello", "demo/v2/Name", json!({"first": "Grace", "last": "Hopper"}));
+        assert_eq!(bus.request(v2).await.unwrap(), json!({"text": "Hello Grace!"}));
+    }
+
+    #[tokio::test]
+    async fn unsupported_caller_version_is_bad_request() {
+        let bus = LocalBus::new();
+        let _greeter = bus
+            .subscribe(
+                "cbs.greeter.say_hello",
+                versioned_handler(evolution(), "demo/v1/Name", sync_handler(|_| Ok(json!({})))).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let v7 = Envelope::new_request("greeter", "say_hello", "demo/v7/Name", json!({}));
+        assert!(matches!(bus.request(v7).await, Err(BusError::BadRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn middleware_converts_for_unwrapped_handlers() {
+        let middleware = EvolutionMiddleware::new(evolution())
+            .handler_with_reply("cbs.greeter.*", "demo/v2/Name", "demo/v2/Greeting")
+            .unwrap();
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new())).layer(middleware);
+        let _greeter = bus
+            .subscribe(
+                "cbs.greeter.say_hello",
+                sync_handler(|envelope| {
+                    assert_eq!(envelope.schema, "demo/v2/Name");
+                    let payload = envelope.payload.unwrap();
+                    Ok(json!({"text": format!("Hello {}!", payload["first"].as_str().unwrap())}))
+                }),
+            )
+            .await
+            .unwrap();
+        let _echo = bus
+            .subscribe("cbs.echo.ping", sync_handler(|envelope| Ok(envelope.payload.unwrap())))
+            .await
+            .unwrap();
+
+        let v1 = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({"name": "Ada Lovelace"}));
+        assert_eq!(bus.request(v1).await.unwrap(), json!({"message": "Hello Ada!"}));
+
+        // Unmapped subjects pass through unconverted
+        let ping = Envelope::new_request("echo", "ping", "demo/v1/Name", json!({"name": "Ada"}));
+        assert_eq!(bus.request(ping).await.unwrap(), json!({"name": "Ada"}));
+
+        let v7 = Envelope::new_request("greeter", "say_hello", "demo/v7/Name", json!({}));
+        assert!(matches!(bus.request(v7).await, Err(BusError::BadRequest { .. })));
+        assert!(EvolutionMiddleware::new(evolution()).handler("cbs..bad", "demo/v1/Name").is_err());
+    }
+}
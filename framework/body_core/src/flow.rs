@@ -0,0 +1,427 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use thiserror::Error;
+use tracing::info;
+
+use crate::mapping::{MappingError, PathSegment, Template};
+use crate::{headers, BodyBus, BusError, CellConfig, Envelope};
+
+/// Schema of flow step requests that do not name one
+pub const FLOW_STEP_SCHEMA: &str = "cbs/v1/FlowStep";
+
+/// Named sequence of bus requests declared under `flows:` in app.yaml
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FlowConfig {
+    pub name: String,
+    pub steps: Vec<FlowStep>,
+}
+
+/// One request in a flow: `action` on the service of `cell`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FlowStep {
+    /// Name later steps select this step's reply by (`$.steps.<name>`); the cell name when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Cell from the application's `cells:` list that handles the step
+    pub cell: String,
+    /// Verb of the request subject
+    pub action: String,
+    /// Service of the request subject when it differs from the cell name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    /// Envelope schema; `cbs/v1/FlowStep` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Payload `Template`; the previous step's reply (or the flow input) when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+}
+
+impl FlowStep {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.cell)
+    }
+
+    pub fn service(&self) -> &str {
+        self.service.as_deref().unwrap_or(&self.cell)
+    }
+
+    pub fn schema(&self) -> &str {
+        self.schema.as_deref().unwrap_or(FLOW_STEP_SCHEMA)
+    }
+}
+
+/// Errors declaring or running a flow
+#[derive(Debug, Error)]
+pub enum FlowError {
+    #[error("Unknown flow: {0}")]
+    UnknownFlow(String),
+    #[error("Invalid flow {flow}: {reason}")]
+    Invalid { flow: String, reason: String },
+    #[error("Flow {flow} failed at step {step} ({cell}.{action}): {source}")]
+    Step {
+        flow: String,
+        /// Position of the failing step, starting at 1
+        step: usize,
+        cell: String,
+        action: String,
+        #[source]
+        source: Box<BusError>,
+    },
+    #[error("Flow {flow} step {step} ({name}): {source}")]
+    Mapping {
+        flow: String,
+        /// Position of the failing step, starting at 1
+        step: usize,
+        name: String,
+        #[source]
+        source: MappingError,
+    },
+}
+
+/// Check that flow names are unique, every step names a listed cell, and
+/// payload templates compile and only select the flow input, the previous
+/// reply or replies of earlier steps
+///
+/// A step's `name` must differ from every other step name in its flow. Unnamed
+/// steps go by their cell and may repeat it, but templates cannot select a
+/// name more than one earlier step goes by.
+pub fn validate_flows(flows: &[FlowConfig], cells: &[CellConfig]) -> Result<(), FlowError> {
+    let cells: HashSet<&str> = cells.iter().map(|cell| cell.name.as_str()).collect();
+    let mut names = HashSet::new();
+    for flow in flows {
+        let invalid = |reason: String| FlowError::Invalid {
+            flow: flow.name.clone(),
+            reason,
+        };
+        if !names.insert(flow.name.as_str()) {
+            return Err(invalid("declared more than once".to_string()));
+        }
+        if flow.steps.is_empty() {
+            return Err(invalid("has no steps".to_string()));
+        }
+        // Steps answering to each name, and whether one of them chose it
+        let mut earlier: HashMap<&str, (usize, bool)> = HashMap::new();
+        for (index, step) in flow.steps.iter().enumerate() {
+            if !cells.contains(step.cell.as_str()) {
+                return Err(invalid(format!("step {} refers to unknown cell {}", index + 1, step.cell)));
+            }
+            if let Some(payload) = &step.payload {
+                let template = Template::compile(payload)
+                    .map_err(|e| invalid(format!("step {} ({}): {}", index + 1, step.name(), e)))?;
+                for path in template.paths() {
+                    let selects = match path.segments() {
+                        [PathSegment::Key(root), ..] if root == "input" || root == "previous" => true,
+                        [PathSegment::Key(root), PathSegment::Key(name), ..] if root == "steps" => match earlier.get(name.as_str()) {
+                            Some((1, _)) => true,
+                            Some(_) => {
+                                return Err(invalid(format!(
+                                    "step {} ({}): {} is ambiguous; more than one earlier step goes by {}",
+                                    index + 1,
+                                    step.name(),
+                                    path,
+                                    name
+                                )))
+                            }
+                            None => false,
+                        },
+                        _ => false,
+                    };
+                    if !selects {
+                        return Err(invalid(format!(
+                            "step {} ({}): {} must select $.input, $.previous or $.steps.<earlier step>",
+                            index + 1,
+                            step.name(),
+                            path
+                        )));
+                    }
+                }
+            }
+            let named = step.name.is_some();
+            let (count, chosen) = earlier.entry(step.name()).or_default();
+            if *count > 0 && (named || *chosen) {
+                return Err(invalid(format!(
+                    "step {} ({}) has the same name as an earlier step",
+                    index + 1,
+                    step.name()
+                )));
+            }
+            *count += 1;
+            *chosen |= named;
+        }
+    }
+    Ok(())
+}
+
+/// Runs declared flows as sequences of bus requests
+///
+/// The flow's input is the first step's payload, each step's reply is the
+/// next step's payload, and the last reply is the flow's output. A step with
+/// a `payload` template builds its payload instead from
+/// `{"input", "previous", "steps": {<name>: reply}}`. All steps of a run
+/// share one trace id. The first failing step ends the run.
+#[derive(Debug, Clone, Default)]
+pub struct FlowEngine {
+    flows: BTreeMap<String, FlowConfig>,
+}
+
+impl FlowEngine {
+    pub fn new(flows: &[FlowConfig]) -> Self {
+        Self {
+            flows: flows.iter().map(|flow| (flow.name.clone(), flow.clone())).collect(),
+        }
+    }
+
+    /// Declared flow names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.flows.keys().map(String::as_str).collect()
+    }
+
+    /// Run flow `name` with `input` as the first step's payload and return the last reply
+    pub async fn run(&self, bus: &dyn BodyBus, name: &str, input: Value) -> Result<Value, FlowError> {
+        let flow = self.flows.get(name).ok_or_else(|| FlowError::UnknownFlow(name.to_string()))?;
+        let mut trace_id = None;
+        let mut scope = serde_json::json!({"input": input, "previous": input, "steps": {}});
+        for (index, step) in flow.steps.iter().enumerate() {
+            let payload = match &step.payload {
+                Some(template) => Template::compile(template)
+                    .and_then(|template| template.render(&scope))
+                    .map_err(|source| FlowError::Mapping {
+                        flow: flow.name.clone(),
+                        step: index + 1,
+                        name: step.name().to_string(),
+                        source,
+                    })?,
+                None => scope["previous"].take(),
+            };
+            let mut envelope = Envelope::new_request(step.service(), &step.action, step.schema(), payload);
+            let trace_id = trace_id.get_or_insert_with(|| envelope.id.clone());
+            envelope.headers_mut().set(headers::TRACE_ID, trace_id);
+            info!(flow = %flow.name, step = index + 1, id = %envelope.id, service = %envelope.service, verb = %envelope.verb, "Running flow step");
+
+            let reply = bus.request(envelope).await.map_err(|source| FlowError::Step {
+                flow: flow.name.clone(),
+                step: index + 1,
+                cell: step.cell.clone(),
+                action: step.action.clone(),
+                source: Box::new(source),
+            })?;
+            scope["steps"][step.name()] = reply.clone();
+            scope["previous"] = reply;
+        }
+        Ok(scope["previous"].take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync_handler, AppConfig, LocalBus};
+    use serde_json::json;
+
+    const GREETER: &str = r#"
+name: greeter_app
+version: 1.0.0
+description: Prompt, greet and print
+cells:
+  - name: io_prompt_name_rs
+    path: cells/io_prompt_name_rs
+  - name: logic_greet_rs
+    path: cells/logic_greet_rs
+  - name: io_print_greeting_rs
+    path: cells/io_print_greeting_rs
+flows:
+  - name: greet_user
+    steps:
+      - cell: io_prompt_name_rs
+        service: prompt_name
+        action: read
+      - cell: logic_greet_rs
+        service: greeter
+        action: say_hello
+        schema: demo/v1/Name
+        payload:
+          name: ${$.previous.name}
+      - cell: io_print_greeting_rs
+        service: printer
+        action: write
+"#;
+
+    async fn greeter_bus(printed: std::sync::mpsc::Sender<(String, Option<String>)>) -> LocalBus {
+        let bus = LocalBus::new();
+        bus.subscribe("cbs.prompt_name.read", sync_handler(|envelope| {
+            let name = envelope.payload.as_ref().and_then(|p| p["test_input"].as_str()).unwrap_or("World");
+            Ok(json!({"name": name, "length": name.len(), "timestamp": 1_700_000_000}))
+        }))
+        .await
+        .unwrap()
+        .detach();
+        bus.subscribe("cbs.greeter.say_hello", sync_handler(|envelope| {
+            if envelope.payload.as_ref().and_then(|p| p.as_object()).is_some_and(|p| p.len() > 1) {
+                return Err(BusError::bad_request("say_hello takes only {name}"));
+            }
+            let name = envelope.payload.as_ref().and_then(|p| p["name"].as_str()).unwrap_or_default().to_string();
+            Ok(json!({"message": format!("Hello {}!", name)}))
+        }))
+        .await
+        .unwrap()
+        .detach();
+        bus.subscribe("cbs.printer.write", sync_handler(move |envelope| {
+            let message = envelope.payload.as_ref().and_then(|p| p["message"].as_str()).unwrap_or_default();
+            printed.send((message.to_string(), envelope.header(headers::TRACE_ID).map(str::to_string))).unwrap();
+            Ok(json!({"ok": true}))
+        }))
+        .await
+        .unwrap()
+        .detach();
+        bus
+    }
+
+    #[test]
+    fn flows_are_parsed_from_app_yaml() {
+        let app: AppConfig = serde_yaml::from_str(GREETER).unwrap();
+        assert_eq!(app.flows.len(), 1);
+        let steps = &app.flows[0].steps;
+        assert_eq!((steps[0].service(), steps[0].schema()), ("prompt_name", FLOW_STEP_SCHEMA));
+        assert_eq!((steps[1].service(), steps[1].schema()), ("greeter", "demo/v1/Name"));
+        assert!(validate_flows(&app.flows, &app.cells).is_ok());
+    }
+
+    #[test]
+    fn birthmap_flows_refer_to_its_cells() {
+        let birthmap: AppConfig =
+            serde_yaml::from_str(include_str!("../../../applications/flutter_flow_web/birthmap.yaml")).unwrap();
+        assert_eq!(birthmap.flows.len(), 5);
+        assert_eq!(birthmap.flows[0].steps[1].service(), "navigation_manager");
+        assert!(validate_flows(&birthmap.flows, &birthmap.cells).is_ok());
+    }
+
+    #[test]
+    fn invalid_flows_are_rejected() {
+        let mut app: AppConfig = serde_yaml::from_str(GREETER).unwrap();
+        app.flows[0].steps[2].cell = "io_speaker_rs".to_string();
+        assert_eq!(
+            validate_flows(&app.flows, &app.cells).unwrap_err().to_string(),
+            "Invalid flow greet_user: step 3 refers to unknown cell io_speaker_rs"
+        );
+
+        app.flows[0].steps[2].cell = "io_print_greeting_rs".to_string();
+        // Unnamed steps may call the same cell again, but cannot then be selected by name
+        let steps = app.flows[0].steps.clone();
+        app.flows[0].steps.push(steps[0].clone());
+        assert!(validate_flows(&app.flows, &app.cells).is_ok());
+        let mut greet = steps[1].clone();
+        greet.payload = Some(json!({"name": "${$.steps.io_prompt_name_rs.name}"}));
+        app.flows[0].steps.push(greet);
+        assert_eq!(
+            validate_flows(&app.flows, &app.cells).unwrap_err().to_string(),
+            "Invalid flow greet_user: step 5 (logic_greet_rs): $.steps.io_prompt_name_rs.name is ambiguous; \
+             more than one earlier step goes by io_prompt_name_rs"
+        );
+
+        let mut prompt_again = steps[0].clone();
+        prompt_again.name = Some("io_prompt_name_rs".to_string());
+        app.flows[0].steps = vec![steps[0].clone(), prompt_again];
+        assert_eq!(
+            validate_flows(&app.flows, &app.cells).unwrap_err().to_string(),
+            "Invalid flow greet_user: step 2 (io_prompt_name_rs) has the same name as an earlier step"
+        );
+
+        app.flows[0].steps = steps;
+        app.flows.push(FlowConfig {
+            name: "empty".to_string(),
+            steps: vec![],
+        });
+        assert!(matches!(validate_flows(&app.flows, &app.cells), Err(FlowError::Invalid { flow, .. }) if flow == "empty"));
+    }
+
+    #[tokio::test]
+    async fn steps_pass_results_along_one_trace() {
+        let app: AppConfig = serde_yaml::from_str(GREETER).unwrap();
+        let (printed, output) = std::sync::mpsc::channel();
+        let bus = greeter_bus(printed).await;
+        let engine = FlowEngine::new(&app.flows);
+
+        let result = engine.run(&bus, "greet_user", json!({"test_input": "Ada"})).await.unwrap();
+        assert_eq!(result, json!({"ok": true}));
+        let (message, trace_id) = output.recv().unwrap();
+        assert_eq!(message, "Hello Ada!");
+        assert!(trace_id.is_some());
+    }
+
+    #[tokio::test]
+    async fn failing_step_is_named() {
+        let mut app: AppConfig = serde_yaml::from_str(GREETER).unwrap();
+        app.flows[0].steps[1].action = "shout".to_string();
+        let (printed, _output) = std::sync::mpsc::channel();
+        let bus = greeter_bus(printed).await;
+        let engine = FlowEngine::new(&app.flows);
+
+        let error = engine.run(&bus, "greet_user", json!({})).await.unwrap_err();
+        assert!(matches!(&error, FlowError::Step { step: 2, source, .. } if matches!(**source, BusError::NotFound(_))));
+        assert!(error.to_string().starts_with("Flow greet_user failed at step 2 (logic_greet_rs.shout): "));
+        assert!(matches!(engine.run(&bus, "nope", json!({})).await, Err(FlowError::UnknownFlow(_))));
+    }
+
+    /// The greeter flow with payload templates on the greet and print steps
+    fn mapped_greeter() -> AppConfig {
+        let mut app: AppConfig = serde_yaml::from_str(GREETER).unwrap();
+        let steps = &mut app.flows[0].steps;
+        steps[0].name = Some("prompt".to_string());
+        steps[1].payload = Some(json!({"name": "${$.steps.prompt.name}"}));
+        steps[2].payload = Some(json!({
+            "message": "${$.previous.message} (${$.steps.prompt.length} letters, via ${$.input.via ?? \"cli\"})"
+        }));
+        app
+    }
+
+    #[tokio::test]
+    async fn templates_reshape_payloads_between_steps() {
+        let mut app = mapped_greeter();
+        assert!(validate_flows(&app.flows, &app.cells).is_ok());
+        let (printed, output) = std::sync::mpsc::channel();
+        let bus = greeter_bus(printed).await;
+        let engine = FlowEngine::new(&app.flows);
+
+        engine.run(&bus, "greet_user", json!({"test_input": "Ada"})).await.unwrap();
+        assert_eq!(output.recv().unwrap().0, "Hello Ada! (3 letters, via cli)");
+
+        // Without the template the prompt's {name, length, timestamp} reaches say_hello as is
+        app.flows[0].steps[1].payload = None;
+        let engine = FlowEngine::new(&app.flows);
+        let error = engine.run(&bus, "greet_user", json!({})).await.unwrap_err();
+        assert!(matches!(error, FlowError::Step { step: 2, .. }));
+    }
+
+    #[tokio::test]
+    async fn mapping_errors_name_the_step() {
+        let mut app = mapped_greeter();
+        app.flows[0].steps[1].name = Some("greet".to_string());
+        app.flows[0].steps[1].payload = Some(json!({"name": "${$.steps.prompt.nickname}"}));
+        let (printed, _output) = std::sync::mpsc::channel();
+        let bus = greeter_bus(printed).await;
+        let engine = FlowEngine::new(&app.flows);
+
+        let error = engine.run(&bus, "greet_user", json!({})).await.unwrap_err();
+        assert!(matches!(&error, FlowError::Mapping { step: 2, name, .. } if name == "greet"));
+        assert_eq!(
+            error.to_string(),
+            "Flow greet_user step 2 (greet): $.steps.prompt.nickname did not match any value"
+        );
+    }
+
+    #[test]
+    fn templates_are_checked_at_load() {
+        let mut app = mapped_greeter();
+        app.flows[0].steps[0].payload = Some(json!({"name": "${$.steps.logic_greet_rs.message}"}));
+        assert_eq!(
+            validate_flows(&app.flows, &app.cells).unwrap_err().to_string(),
+            "Invalid flow greet_user: step 1 (prompt): $.steps.logic_greet_rs.message must select $.input, $.previous or $.steps.<earlier step>"
+        );
+
+        app.flows[0].steps[0].payload = Some(json!("${$.input.name"));
+        let error = validate_flows(&app.flows, &app.cells).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid flow greet_user: step 1 (prompt): Invalid expression"), "{}", error);
+    }
+}
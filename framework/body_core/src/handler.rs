@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::{BusError, Envelope, RequestContext};
+
+/// Asynchronous message handler invoked by a bus for each delivered envelope
+#[async_trait]
+pub trait Handler: Send + Sync {
+    /// Process an envelope and produce the reply payload
+    async fn handle(&self, envelope: Envelope, ctx: RequestContext) -> Result<Value, BusError>;
+}
+
+/// Shared handler reference stored by bus implementations
+pub type SharedHandler = Arc<dyn Handler>;
+
+/// Synchronous handler closure, kept for cells that do no I/O
+pub type MessageHandler = Box<dyn Fn(Envelope) -> Result<Value, BusError> + Send + Sync>;
+
+/// Adapter that runs a synchronous closure as a `Handler`
+pub struct SyncHandler<F> {
+    f: F,
+}
+
+#[async_trait]
+impl<F> Handler for SyncHandler<F>
+where
+    F: Fn(Envelope) -> Result<Value, BusError> + Send + Sync,
+{
+    async fn handle(&self, envelope: Envelope, _ctx: RequestContext) -> Result<Value, BusError> {
+        (self.f)(envelope)
+    }
+}
+
+/// Adapter that runs an async closure receiving the request context as a `Handler`
+pub struct AsyncHandler<F> {
+    f: F,
+}
+
+#[async_trait]
+impl<F, Fut> Handler for AsyncHandler<F>
+where
+    F: Fn(Envelope, RequestContext) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Value, BusError>> + Send,
+{
+    async fn handle(&self, envelope: Envelope, ctx: RequestContext) -> Result<Value, BusError> {
+        (self.f)(envelope, ctx).await
+    }
+}
+
+/// Wrap a synchronous closure (or a boxed `MessageHandler`) for `BodyBus::subscribe`
+pub fn sync_handler<F>(f: F) -> SharedHandler
+where
+    F: Fn(Envelope) -> Result<Value, BusError> + Send + Sync + 'static,
+{
+    Arc::new(SyncHandler { f })
+}
+
+/// Wrap an async closure taking the envelope and its `RequestContext` for `BodyBus::subscribe`
+pub fn async_handler<F, Fut>(f: F) -> SharedHandler
+where
+    F: Fn(Envelope, RequestContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Value, BusError>> + Send + 'static,
+{
+    Arc::new(AsyncHandler { f })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NullBus;
+    use serde_json::json;
+
+    struct EchoHandler;
+
+    #[async_trait]
+    impl Handler for EchoHandler {
+        async fn handle(&self, envelope: Envelope, _ctx: RequestContext) -> Result<Value, BusError> {
+            Ok(envelope.payload.unwrap_or(json!({})))
+        }
+    }
+
+    fn request(payload: Value) -> Envelope {
+        Envelope::new_request("test", "echo", "demo/v1/Test", payload)
+    }
+
+    fn ctx() -> RequestContext {
+        RequestContext::new(Arc::new(NullBus)).with_metadata("trace_id", "trace-1").unwrap()
+    }
+
+    #[tokio::test]
+    async fn trait_handler_is_invoked() {
+        let handler: SharedHandler = Arc::new(EchoHandler);
+        let result = handler.handle(request(json!({"a": 1})), ctx()).await.unwrap();
+        assert_eq!(result["a"], 1);
+    }
+
+    #[tokio::test]
+    async fn sync_closure_adapter() {
+        let handler = sync_handler(|envelope| Ok(json!({"service": envelope.service})));
+        let result = handler.handle(request(json!({})), ctx()).await.unwrap();
+        assert_eq!(result["service"], "test");
+    }
+
+    #[tokio::test]
+    async fn boxed_message_handler_adapter() {
+        let boxed: MessageHandler =
+            Box::new(|_| Err(BusError::bad_request("nope")));
+        let handler = sync_handler(boxed);
+        let result = handler.handle(request(json!({})), ctx()).await;
+        assert!(matches!(result, Err(BusError::BadRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn async_closure_adapter_can_await() {
+        let handler = async_handler(|envelope: Envelope, ctx: RequestContext| async move {
+            tokio::task::yield_now().await;
+            Ok(json!({"verb": envelope.verb, "trace_id": ctx.trace_id()}))
+        });
+        let result = handler.handle(request(json!({})), ctx()).await.unwrap();
+        assert_eq!(result["verb"], "echo");
+        assert_eq!(result["trace_id"], "trace-1");
+    }
+}
@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{BusError, Envelope, RequestContext};
+
+/// Header format version written by this crate
+pub const HEADERS_VERSION: u32 = 1;
+
+/// Key the format version is written under, next to the entries; reserved
+pub const VERSION: &str = "version";
+
+/// Trace id shared by every envelope in a call chain
+pub const TRACE_ID: &str = "trace_id";
+
+/// Id of the envelope whose handler sent this one
+pub const CAUSATION_ID: &str = "causation_id";
+
+/// On a reply, id of the request it answers
+pub const CORRELATION_ID: &str = "correlation_id";
+
+/// Send time in milliseconds since the Unix epoch
+pub const SENT_AT: &str = "sent_at";
+
+/// Id of the cell that sent the envelope
+pub const SOURCE_CELL: &str = "source_cell";
+
+/// Absolute deadline in milliseconds since the Unix epoch
+pub const DEADLINE: &str = "deadline";
+
+/// Subject a reply should be sent to when not using the request inbox
+pub const REPLY_TO: &str = "reply_to";
+
+/// Position of an item within a streamed reply, starting at 0
+pub const STREAM_SEQ: &str = "stream_seq";
+
+/// Marks the envelope that ends a streamed reply
+pub const STREAM_END: &str = "stream_end";
+
+/// On a reply to a scatter request, id of the subscription that produced it
+pub const RESPONDER: &str = "responder";
+
+/// Attempt number, starting at 1, of a request sent under a retry policy
+pub const ATTEMPT: &str = "attempt";
+
+/// Marks a request sent to every instance of a service with `request_all`
+pub const SCATTER: &str = "scatter";
+
+/// Keys describing a single hop, which are not carried into nested requests
+const PER_HOP: [&str; 11] = [
+    CAUSATION_ID,
+    CORRELATION_ID,
+    SENT_AT,
+    SOURCE_CELL,
+    DEADLINE,
+    REPLY_TO,
+    STREAM_SEQ,
+    STREAM_END,
+    RESPONDER,
+    ATTEMPT,
+    SCATTER,
+];
+
+/// Versioned string map carried in `Envelope::headers`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Headers {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(flatten)]
+    entries: BTreeMap<String, String>,
+}
+
+fn default_version() -> u32 {
+    HEADERS_VERSION
+}
+
+impl Default for Headers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Headers {
+    /// Create an empty header map at the current version
+    pub fn new() -> Self {
+        Self {
+            version: HEADERS_VERSION,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Look up a header value
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Set a header, replacing any previous value
+    ///
+    /// Entries share the wire map with `version`, so that key is rejected
+    /// with `BadRequest`.
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<(), BusError> {
+        check_key(key)?;
+        self.set(key, value);
+        Ok(())
+    }
+
+    /// Set a header only if it is not already present; rejects `version` like `insert`
+    pub fn insert_if_absent(&mut self, key: &str, value: &str) -> Result<(), BusError> {
+        check_key(key)?;
+        self.entries
+            .entry(key.to_string())
+            .or_insert_with(|| value.to_string());
+        Ok(())
+    }
+
+    /// Set one of the well-known headers above, which are never reserved
+    pub(crate) fn set(&mut self, key: &str, value: &str) {
+        debug_assert_ne!(key, VERSION);
+        self.entries.insert(key.to_string(), value.to_string());
+    }
+
+    /// Remove a header, returning its value
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.entries.remove(key)
+    }
+
+    /// Whether no headers are set
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All headers in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Headers that should follow the request into nested calls
+    pub fn propagated(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.iter().filter(|(key, _)| !PER_HOP.contains(key))
+    }
+
+    /// Trace id shared across the call chain
+    pub fn trace_id(&self) -> Option<&str> {
+        self.get(TRACE_ID)
+    }
+
+    /// Id of the envelope that caused this one
+    pub fn causation_id(&self) -> Option<&str> {
+        self.get(CAUSATION_ID)
+    }
+
+    /// Id of the request a reply answers
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.get(CORRELATION_ID)
+    }
+
+    /// Id of the sending cell
+    pub fn source_cell(&self) -> Option<&str> {
+        self.get(SOURCE_CELL)
+    }
+
+    /// Subject the sender expects a reply on
+    pub fn reply_to(&self) -> Option<&str> {
+        self.get(REPLY_TO)
+    }
+
+    /// When the envelope was sent, if stamped
+    pub fn sent_at(&self) -> Option<SystemTime> {
+        self.get(SENT_AT).and_then(parse_timestamp)
+    }
+
+    /// Absolute deadline, if the sender set one
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.get(DEADLINE).and_then(parse_timestamp)
+    }
+
+    /// Record the send time
+    pub fn set_sent_at(&mut self, time: SystemTime) {
+        self.set(SENT_AT, &format_timestamp(time));
+    }
+
+    /// Set the deadline, keeping an earlier one if already present
+    pub fn set_deadline(&mut self, deadline: SystemTime) {
+        let deadline = self
+            .deadline()
+            .map_or(deadline, |current| current.min(deadline));
+        self.set(DEADLINE, &format_timestamp(deadline));
+    }
+}
+
+/// Reject keys that would collide with the map's own fields on the wire
+pub fn check_key(key: &str) -> Result<(), BusError> {
+    if key == VERSION {
+        return Err(BusError::bad_request(format!("Header key {:?} is reserved", key)));
+    }
+    Ok(())
+}
+
+/// Fill in the tracing headers of an outbound envelope
+///
+/// Values already on the envelope win. With a parent context the envelope
+/// inherits its metadata and records the parent envelope as its cause.
+pub fn stamp_outbound(envelope: &mut Envelope, parent: Option<&RequestContext>, timeout: Option<Duration>) {
+    let id = envelope.id.clone();
+    let headers = envelope.headers_mut();
+    if let Some(parent) = parent {
+        // Metadata keys were checked when they were set
+        for (key, value) in parent.metadata() {
+            if headers.get(key).is_none() {
+                headers.set(key, value);
+            }
+        }
+        if let Some(cause) = parent.envelope_id() {
+            if headers.get(CAUSATION_ID).is_none() {
+                headers.set(CAUSATION_ID, cause);
+            }
+        }
+    }
+    if headers.get(TRACE_ID).is_none() {
+        headers.set(TRACE_ID, &id);
+    }
+    if headers.get(SENT_AT).is_none() {
+        headers.set_sent_at(SystemTime::now());
+    }
+    if let Some(timeout) = timeout {
+        headers.set_deadline(SystemTime::now() + timeout);
+    }
+}
+
+/// Fill in the headers of a reply so it can be matched to its request
+pub fn stamp_reply(reply: &mut Envelope, request: &Envelope) {
+    let headers = reply.headers_mut();
+    headers.set(CORRELATION_ID, &request.id);
+    headers.set(CAUSATION_ID, &request.id);
+    if let Some(trace_id) = request.header(TRACE_ID) {
+        headers.set(TRACE_ID, trace_id);
+    }
+    headers.set_sent_at(SystemTime::now());
+}
+
+fn format_timestamp(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .to_string()
+}
+
+fn parse_timestamp(value: &str) -> Option<SystemTime> {
+    value
+        .parse::<u64>()
+        .ok()
+        .map(|millis| UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NullBus;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn envelope() -> Envelope {
+        Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}))
+    }
+
+    #[test]
+    fn headers_serialize_flat_with_version() {
+        let mut headers = Headers::new();
+        headers.insert(TRACE_ID, "trace-1").unwrap();
+        headers.insert("tenant", "acme").unwrap();
+
+        let value = serde_json::to_value(&headers).unwrap();
+        assert_eq!(value, json!({"version": 1, "trace_id": "trace-1", "tenant": "acme"}));
+
+        let parsed: Headers = serde_json::from_value(json!({"trace_id": "trace-2"})).unwrap();
+        assert_eq!(parsed.version, HEADERS_VERSION);
+        assert_eq!(parsed.trace_id(), Some("trace-2"));
+    }
+
+    #[test]
+    fn deadline_only_tightens() {
+        let mut headers = Headers::new();
+        let soon = UNIX_EPOCH + Duration::from_millis(1_000);
+        let later = UNIX_EPOCH + Duration::from_millis(2_000);
+        headers.set_deadline(soon);
+        headers.set_deadline(later);
+        assert_eq!(headers.deadline(), Some(soon));
+    }
+
+    #[test]
+    fn root_envelope_is_stamped() {
+        let mut envelope = envelope();
+        stamp_outbound(&mut envelope, None, Some(Duration::from_secs(5)));
+
+        assert_eq!(envelope.header(TRACE_ID), Some(envelope.id.as_str()));
+        assert!(envelope.header(SENT_AT).is_some());
+        assert!(envelope.header(CAUSATION_ID).is_none());
+
+        let headers = envelope.headers.as_ref().unwrap();
+        assert!(headers.deadline().unwrap() > SystemTime::now());
+    }
+
+    #[test]
+    fn nested_envelope_inherits_parent() {
+        let request = envelope().with_header("tenant", "acme").unwrap();
+        let parent = RequestContext::for_request(Arc::new(NullBus), &request, None);
+
+        let mut nested = Envelope::new_request("printer", "write", "demo/v1/Message", json!({}))
+            .with_header(SOURCE_CELL, "greeter").unwrap();
+        stamp_outbound(&mut nested, Some(&parent), None);
+
+        assert_eq!(nested.header(TRACE_ID), Some(request.id.as_str()));
+        assert_eq!(nested.header(CAUSATION_ID), Some(request.id.as_str()));
+        assert_eq!(nested.header("tenant"), Some("acme"));
+        assert_eq!(nested.header(SOURCE_CELL), Some("greeter"));
+        assert!(nested.header(DEADLINE).is_none());
+    }
+
+    #[test]
+    fn reply_is_correlated_with_request() {
+        let mut request = envelope();
+        stamp_outbound(&mut request, None, None);
+
+        let mut reply = Envelope::new_response(&request.id, "greeter", "say_hello", "demo/v1/Name", json!({}));
+        stamp_reply(&mut reply, &request);
+
+        assert_eq!(reply.header(CORRELATION_ID), Some(request.id.as_str()));
+        assert_eq!(reply.header(TRACE_ID), request.header(TRACE_ID));
+    }
+
+    #[test]
+    fn version_key_is_reserved() {
+        let mut headers = Headers::new();
+        assert!(matches!(headers.insert(VERSION, "2"), Err(BusError::BadRequest { .. })));
+        assert!(headers.insert_if_absent(VERSION, "2").is_err());
+        assert!(envelope().with_header(VERSION, "2").is_err());
+        assert!(RequestContext::new(Arc::new(NullBus)).with_metadata(VERSION, "2").is_err());
+
+        // Nothing collides with the format version on the wire
+        let value = serde_json::to_value(&headers).unwrap();
+        let parsed: Headers = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed, headers);
+    }
+
+    #[test]
+    fn per_hop_headers_are_not_propagated() {
+        let mut headers = Headers::new();
+        headers.insert(TRACE_ID, "trace-1").unwrap();
+        headers.insert(SOURCE_CELL, "greeter").unwrap();
+        headers.insert(CAUSATION_ID, "cause").unwrap();
+        headers.set_deadline(SystemTime::now());
+
+        let propagated: Vec<_> = headers.propagated().collect();
+        assert_eq!(propagated, vec![(TRACE_ID, "trace-1")]);
+    }
+}
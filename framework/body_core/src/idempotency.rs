@@ -0,0 +1,404 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::middleware::{DispatchNext, Middleware};
+use crate::scatter::is_scatter;
+use crate::subject;
+use crate::{BusError, Envelope, RequestContext};
+
+fn default_ttl_ms() -> u64 {
+    300_000
+}
+
+/// Duplicate suppression from the `idempotency:` section of `app.yaml`
+///
+/// ```yaml
+/// idempotency:
+///   ttl_ms: 300000             # how long a reply is remembered
+///   subjects: ["cbs.printer.*"] # omit to cover every handled subject
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdempotencyConfig {
+    #[serde(default = "default_ttl_ms")]
+    pub ttl_ms: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subjects: Vec<String>,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            ttl_ms: default_ttl_ms(),
+            subjects: Vec::new(),
+        }
+    }
+}
+
+impl IdempotencyConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_millis(self.ttl_ms)
+    }
+}
+
+/// Where replies to handled requests are remembered, keyed by subject and envelope id
+///
+/// Implement this over a shared store such as Redis to suppress duplicates
+/// across processes; `MemoryIdempotencyStore` covers a single process.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// The reply remembered for this request, if it has not expired
+    async fn get(&self, subject: &str, id: &str) -> Result<Option<Value>, BusError>;
+
+    /// Remember a reply for `ttl`
+    async fn put(&self, subject: &str, id: &str, reply: Value, ttl: Duration) -> Result<(), BusError>;
+}
+
+/// In-process `IdempotencyStore`; expired entries are dropped as new ones are stored
+#[derive(Debug, Default)]
+pub struct MemoryIdempotencyStore {
+    entries: Mutex<HashMap<(String, String), (Value, Instant)>>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of remembered replies, including expired ones not yet dropped
+    pub fn len(&self) -> usize {
+        self.entries.lock().expect("idempotency store lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn get(&self, subject: &str, id: &str) -> Result<Option<Value>, BusError> {
+        let entries = self.entries.lock().expect("idempotency store lock poisoned");
+        Ok(entries
+            .get(&(subject.to_string(), id.to_string()))
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(reply, _)| reply.clone()))
+    }
+
+    async fn put(&self, subject: &str, id: &str, reply: Value, ttl: Duration) -> Result<(), BusError> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("idempotency store lock poisoned");
+        entries.retain(|_, (_, expires_at)| *expires_at > now);
+        entries.insert((subject.to_string(), id.to_string()), (reply, now + ttl));
+        Ok(())
+    }
+}
+
+/// Middleware that replays the remembered reply when a request id is handled again
+///
+/// Applies at dispatch, so it belongs on the handling side. A duplicate that
+/// arrives while the original is still running waits for it instead of
+/// running the handler a second time. Only successful replies are remembered:
+/// after a failure, a retry runs the handler again.
+pub struct IdempotencyMiddleware {
+    store: Arc<dyn IdempotencyStore>,
+    ttl: Duration,
+    subjects: Vec<String>,
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl IdempotencyMiddleware {
+    /// Remember replies in process memory
+    pub fn in_memory(config: &IdempotencyConfig) -> Result<Self, BusError> {
+        Self::with_store(config, Arc::new(MemoryIdempotencyStore::new()))
+    }
+
+    /// Remember replies in `store`
+    pub fn with_store(config: &IdempotencyConfig, store: Arc<dyn IdempotencyStore>) -> Result<Self, BusError> {
+        for pattern in &config.subjects {
+            subject::validate_pattern(pattern)?;
+        }
+        Ok(Self {
+            store,
+            ttl: config.ttl(),
+            subjects: config.subjects.clone(),
+            in_flight: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn covers(&self, subject: &str) -> bool {
+        self.subjects.is_empty() || self.subjects.iter().any(|pattern| subject::matches(pattern, subject))
+    }
+
+    /// Serialise handling of one request id with any duplicate in flight
+    fn claim(&self, key: String) -> InFlight<'_> {
+        let mut in_flight = self.in_flight.lock().expect("idempotency lock poisoned");
+        let lock = Arc::clone(in_flight.entry(key.clone()).or_default());
+        InFlight {
+            owner: self,
+            key,
+            lock,
+        }
+    }
+
+    async fn handle(
+        &self,
+        subject: &str,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        if let Some(reply) = self.store.get(subject, &envelope.id).await? {
+            debug!(id = %envelope.id, service = %envelope.service, verb = %envelope.verb, "Replaying reply for duplicate request");
+            return Ok(reply);
+        }
+
+        let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+        let result = next.run(envelope, ctx).await;
+        if let Ok(reply) = &result {
+            if let Err(e) = self.store.put(subject, &id, reply.clone(), self.ttl).await {
+                warn!(id = %id, service = %service, verb = %verb, error = %e, "Failed to remember reply");
+            }
+        }
+        result
+    }
+}
+
+/// Claim on a request id; forgets the id's lock once no duplicate holds it,
+/// including when the handler is abandoned at its deadline
+struct InFlight<'a> {
+    owner: &'a IdempotencyMiddleware,
+    key: String,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.owner.in_flight.lock().expect("idempotency lock poisoned");
+        // Only the map and this claim hold it, so no duplicate is waiting
+        if Arc::strong_count(&self.lock) <= 2 {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+impl fmt::Debug for IdempotencyMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdempotencyMiddleware")
+            .field("ttl", &self.ttl)
+            .field("subjects", &self.subjects)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Middleware for IdempotencyMiddleware {
+    async fn dispatch(
+        &self,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        let subject = envelope.subject();
+        // A replayed summary is no substitute for the streamed items, and every
+        // member of a scatter request shares its id but must answer for itself
+        if !self.covers(&subject) || ctx.stream_sink().is_some() || is_scatter(&envelope) {
+            return next.run(envelope, ctx).await;
+        }
+
+        let claim = self.claim(format!("{}/{}", subject, envelope.id));
+        let _guard = claim.lock.lock().await;
+        self.handle(&subject, envelope, ctx, next).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_handler, sync_handler, BodyBus, LocalBus, MiddlewareBus, RetryPolicy, ScatterOptions};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting(calls: &Arc<AtomicUsize>) -> crate::SharedHandler {
+        let calls = Arc::clone(calls);
+        sync_handler(move |_| {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(json!({"printed": n}))
+        })
+    }
+
+    fn print(message: &str) -> Envelope {
+        Envelope::new_request("printer", "write", "demo/v1/Message", json!({"message": message}))
+    }
+
+    #[tokio::test]
+    async fn duplicate_ids_replay_the_first_reply() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(IdempotencyMiddleware::in_memory(&IdempotencyConfig::default()).unwrap());
+        let _printer = bus.subscribe("cbs.printer.write", counting(&calls)).await.unwrap();
+
+        let envelope = print("Hello, Ada!");
+        assert_eq!(bus.request(envelope.clone()).await.unwrap(), json!({"printed": 1}));
+        assert_eq!(bus.request(envelope).await.unwrap(), json!({"printed": 1}));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A new id is a new request
+        assert_eq!(bus.request(print("Hello, Ada!")).await.unwrap(), json!({"printed": 2}));
+    }
+
+    #[tokio::test]
+    async fn scatter_members_answer_for_themselves() {
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(IdempotencyMiddleware::in_memory(&IdempotencyConfig::default()).unwrap());
+        let mut members = Vec::new();
+        for member in 0..2 {
+            let handler = sync_handler(move |_| Ok(json!({"member": member})));
+            members.push(bus.subscribe("cbs.printer.health", handler).await.unwrap());
+        }
+
+        let envelope = Envelope::new_request("printer", "health", "demo/v1/Void", json!({}));
+        let replies = bus.request_all(envelope, ScatterOptions::new()).await.unwrap();
+        let mut answered: Vec<i64> = replies
+            .iter()
+            .map(|reply| reply.result.as_ref().unwrap()["member"].as_i64().unwrap())
+            .collect();
+        answered.sort();
+        assert_eq!(answered, [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn only_configured_subjects_are_deduplicated() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let config = IdempotencyConfig {
+            subjects: vec!["cbs.printer.*".to_string()],
+            ..IdempotencyConfig::default()
+        };
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new())).layer(IdempotencyMiddleware::in_memory(&config).unwrap());
+        let _greeter = bus.subscribe("cbs.greeter.say_hello", counting(&calls)).await.unwrap();
+
+        let envelope = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+        bus.request(envelope.clone()).await.unwrap();
+        bus.request(envelope).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let invalid = IdempotencyConfig {
+            subjects: vec!["cbs.>.bad".to_string()],
+            ..IdempotencyConfig::default()
+        };
+        assert!(IdempotencyMiddleware::in_memory(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn failures_are_not_remembered() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(IdempotencyMiddleware::in_memory(&IdempotencyConfig::default()).unwrap());
+        let _printer = bus
+            .subscribe(
+                "cbs.printer.write",
+                sync_handler(move |_| match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(BusError::Internal("stdout closed".to_string())),
+                    _ => Ok(json!({"printed": true})),
+                }),
+            )
+            .await
+            .unwrap();
+
+        let envelope = print("Hello");
+        assert!(bus.request(envelope.clone()).await.is_err());
+        assert!(bus.request(envelope.clone()).await.is_ok());
+        assert!(bus.request(envelope).await.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_duplicate_waits_for_the_original() {
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(IdempotencyMiddleware::in_memory(&IdempotencyConfig::default()).unwrap());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let _printer = bus
+            .subscribe(
+                "cbs.printer.write",
+                async_handler(move |_envelope, _ctx| {
+                    let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                        Ok(json!({"printed": n}))
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+
+        let envelope = print("Hello, Ada!");
+        let (first, second) = tokio::join!(bus.request(envelope.clone()), async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            bus.request(envelope.clone()).await
+        });
+        assert_eq!(first.unwrap(), json!({"printed": 1}));
+        assert_eq!(second.unwrap(), json!({"printed": 1}));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retried_request_is_handled_once() {
+        let local: Arc<dyn BodyBus> = Arc::new(LocalBus::new());
+        let handling = MiddlewareBus::new(Arc::clone(&local))
+            .layer(IdempotencyMiddleware::in_memory(&IdempotencyConfig::default()).unwrap());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let _printer = handling.subscribe("cbs.printer.write", counting(&calls)).await.unwrap();
+
+        // The caller's link drops the first reply, so it retries with the same id
+        let lost = Arc::new(AtomicUsize::new(0));
+        let policy = RetryPolicy {
+            initial_backoff_ms: 1,
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        let envelope = print("Hello, Ada!");
+        let reply = policy
+            .run(&envelope.subject(), None, |_| {
+                let (local, lost, envelope) = (Arc::clone(&local), Arc::clone(&lost), envelope.clone());
+                async move {
+                    let reply = local.request(envelope).await;
+                    if lost.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return Err(BusError::Connection("reply lost".to_string()));
+                    }
+                    reply
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(reply, json!({"printed": 1}));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn memory_store_expires_entries() {
+        let store = MemoryIdempotencyStore::new();
+        store.put("cbs.printer.write", "a", json!(1), Duration::from_millis(5)).await.unwrap();
+        assert_eq!(store.get("cbs.printer.write", "a").await.unwrap(), Some(json!(1)));
+        assert_eq!(store.get("cbs.printer.other", "a").await.unwrap(), None);
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(store.get("cbs.printer.write", "a").await.unwrap(), None);
+
+        store.put("cbs.printer.write", "b", json!(2), Duration::from_secs(60)).await.unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn parses_app_yaml_section() {
+        let config: IdempotencyConfig = serde_yaml::from_str("subjects: [\"cbs.printer.*\"]").unwrap();
+        assert_eq!(config.ttl(), Duration::from_secs(300));
+        assert_eq!(config.subjects, vec!["cbs.printer.*"]);
+    }
+}
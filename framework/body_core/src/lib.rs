@@ -5,7 +5,59 @@ use thiserror::Error;
 use uuid::Uuid;
 
 pub mod app_loader;
+pub mod cancel;
+pub mod circuit_breaker;
+pub mod codec;
+pub mod compatibility;
+pub mod context;
+pub mod contract;
+pub mod dependencies;
+pub mod evolution;
+pub mod flow;
+pub mod handler;
+pub mod headers;
+pub mod idempotency;
+pub mod lifecycle;
+pub mod limits;
+pub mod local_bus;
+pub mod mapping;
+pub mod middleware;
+pub mod registry;
+pub mod retry;
+pub mod scatter;
+pub mod stream;
+pub mod subject;
+pub mod subscription;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod validation;
 pub use app_loader::{AppConfig, AppLoadError, AppLoader, CellConfig};
+pub use cancel::InFlightRequests;
+pub use context::{CancellationToken, RequestContext};
+pub use circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitSnapshot, CircuitState};
+pub use codec::{codec_by_name, CborCodec, Codec, EncodedMessage, JsonCodec, MessagePackCodec, WireFormat};
+pub use compatibility::{check_compatibility, check_schema_files, CompatibilityReport, SchemaChange, Severity};
+pub use contract::{contract_handler, decode_payload, Contract, TypedBus};
+pub use dependencies::DependencyError;
+pub use evolution::{versioned_handler, versioned_handler_with_reply, EvolutionMiddleware, SchemaEvolution, SchemaId};
+pub use flow::{FlowConfig, FlowEngine, FlowError, FlowStep};
+pub use handler::{async_handler, sync_handler, Handler, MessageHandler, SharedHandler};
+pub use headers::{Headers, HEADERS_VERSION};
+pub use idempotency::{IdempotencyConfig, IdempotencyMiddleware, IdempotencyStore, MemoryIdempotencyStore};
+pub use lifecycle::{CellHost, HealthStatus, LifecycleError, LifecyclePhase};
+pub use limits::{LimitConfig, LimitMiddleware, LimitsConfig};
+pub use local_bus::{LocalBus, LocalBusConfig};
+pub use mapping::{JsonPath, MappingError, Template};
+pub use middleware::{
+    DispatchNext, LoggingMiddleware, MetricsMiddleware, Middleware, MiddlewareBus, PublishNext, RequestNext,
+    ScatterNext, StreamNext, SubjectMetrics,
+};
+pub use registry::{CellFactory, CellRegistry, RegistryError};
+pub use retry::{RetryConfig, RetryMiddleware, RetryPolicy};
+pub use scatter::{ScatterOptions, ScatterReply};
+pub use stream::{stream_handler, ResponseStream, StreamHandler, StreamSink};
+pub use subscription::{Subscription, SubscriptionStats};
+pub use validation::{SchemaError, SchemaRegistry, ValidationMiddleware};
 
 /// Envelope represents a typed message in the CBS system
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -18,6 +70,8 @@ pub struct Envelope {
     pub payload: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<ErrorDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Headers>,
 }
 
 /// Error details for envelope error responses
@@ -34,20 +88,217 @@ pub struct ErrorDetails {
 pub enum BusError {
     #[error("Request timeout: no response received within deadline")]
     Timeout,
-    #[error("Bad request: {0}")]
-    BadRequest(String),
+    #[error("Bad request: {message}")]
+    BadRequest {
+        message: String,
+        /// Structured context such as the offending fields
+        details: Option<Value>,
+    },
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
     #[error("Not found: {0}")]
     NotFound(String),
+    #[error("Conflict: {message}")]
+    Conflict {
+        message: String,
+        /// Structured context such as the conflicting version
+        details: Option<Value>,
+    },
     #[error("Internal error: {0}")]
     Internal(String),
     #[error("Connection error: {0}")]
     Connection(String),
     #[error("Serialization error: {0}")]
     Serialization(String),
+    #[error("Unavailable: {0}")]
+    Unavailable(String),
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+    #[error("Request cancelled")]
+    Cancelled,
+    #[error("Circuit open for service {service}: retry in {}ms", retry_after.as_millis())]
+    CircuitOpen {
+        service: String,
+        /// Time until the breaker lets a trial request through
+        retry_after: std::time::Duration,
+    },
+    /// Application-defined error code outside the built-in taxonomy
+    #[error("{code}: {message}")]
+    Custom {
+        code: String,
+        message: String,
+        details: Option<Value>,
+    },
 }
 
-/// Handler function type for message processing
-pub type MessageHandler = Box<dyn Fn(Envelope) -> Result<Value, BusError> + Send + Sync>;
+impl BusError {
+    /// Bad request without structured details
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::BadRequest {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Bad request carrying structured details, e.g. field-level validation errors
+    pub fn bad_request_with_details(message: impl Into<String>, details: Value) -> Self {
+        Self::BadRequest {
+            message: message.into(),
+            details: Some(details),
+        }
+    }
+
+    /// Conflict without structured details
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::Conflict {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Application-defined error with its own code
+    pub fn custom(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::Custom {
+            code: code.into(),
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Stable error code, as carried in `ErrorDetails::code`
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Timeout => "Timeout",
+            Self::BadRequest { .. } => "BadRequest",
+            Self::Unauthorized(_) => "Unauthorized",
+            Self::Forbidden(_) => "Forbidden",
+            Self::NotFound(_) => "NotFound",
+            Self::Conflict { .. } => "Conflict",
+            Self::Internal(_) => "Internal",
+            Self::Connection(_) => "Connection",
+            Self::Serialization(_) => "Serialization",
+            Self::Unavailable(_) => "Unavailable",
+            Self::Overloaded(_) => "Overloaded",
+            Self::Cancelled => "Cancelled",
+            Self::CircuitOpen { .. } => "CircuitOpen",
+            Self::Custom { code, .. } => code,
+        }
+    }
+
+    /// Human-readable message without the code prefix added by `Display`
+    pub fn message(&self) -> String {
+        match self {
+            Self::BadRequest { message, .. }
+            | Self::Conflict { message, .. }
+            | Self::Custom { message, .. } => message.clone(),
+            Self::Unauthorized(message)
+            | Self::Forbidden(message)
+            | Self::NotFound(message)
+            | Self::Internal(message)
+            | Self::Connection(message)
+            | Self::Serialization(message)
+            | Self::Unavailable(message)
+            | Self::Overloaded(message) => message.clone(),
+            Self::Timeout | Self::Cancelled | Self::CircuitOpen { .. } => self.to_string(),
+        }
+    }
+
+    /// Structured details attached to the error, if any
+    pub fn details(&self) -> Option<&Value> {
+        match self {
+            Self::BadRequest { details, .. }
+            | Self::Conflict { details, .. }
+            | Self::Custom { details, .. } => details.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// HTTP status a gateway should answer with for this error
+    pub fn http_status(&self) -> u16 {
+        status_for_code(self.code())
+    }
+
+    /// Encode the error for an error reply envelope
+    pub fn to_error_details(&self) -> ErrorDetails {
+        let details = match self {
+            Self::CircuitOpen { service, retry_after } => Some(serde_json::json!({
+                "service": service,
+                "retry_after_ms": retry_after.as_millis() as u64,
+            })),
+            _ => self.details().cloned(),
+        };
+        ErrorDetails {
+            code: self.code().to_string(),
+            message: self.message(),
+            details,
+        }
+    }
+
+    /// Decode an error reply; inverse of `to_error_details`
+    ///
+    /// Unknown codes, and known codes whose details cannot be decoded, become
+    /// `Custom` so nothing the responder sent is lost.
+    pub fn from_error_details(error: ErrorDetails) -> Self {
+        let ErrorDetails { code, message, details } = error;
+        match code.as_str() {
+            "Timeout" => Self::Timeout,
+            "BadRequest" => Self::BadRequest { message, details },
+            "Unauthorized" => Self::Unauthorized(message),
+            "Forbidden" => Self::Forbidden(message),
+            "NotFound" => Self::NotFound(message),
+            "Conflict" => Self::Conflict { message, details },
+            "Internal" => Self::Internal(message),
+            "Connection" => Self::Connection(message),
+            "Serialization" => Self::Serialization(message),
+            "Unavailable" => Self::Unavailable(message),
+            "Overloaded" => Self::Overloaded(message),
+            "Cancelled" => Self::Cancelled,
+            "CircuitOpen" => {
+                let service = details.as_ref().and_then(|d| d["service"].as_str());
+                let retry_after = details.as_ref().and_then(|d| d["retry_after_ms"].as_u64());
+                match (service, retry_after) {
+                    (Some(service), Some(retry_after)) => Self::CircuitOpen {
+                        service: service.to_string(),
+                        retry_after: std::time::Duration::from_millis(retry_after),
+                    },
+                    _ => Self::Custom { code, message, details },
+                }
+            }
+            _ => Self::Custom { code, message, details },
+        }
+    }
+}
+
+impl From<ErrorDetails> for BusError {
+    fn from(error: ErrorDetails) -> Self {
+        Self::from_error_details(error)
+    }
+}
+
+impl From<&BusError> for ErrorDetails {
+    fn from(error: &BusError) -> Self {
+        error.to_error_details()
+    }
+}
+
+/// HTTP status for an error code; unknown and server-side codes map to 500
+fn status_for_code(code: &str) -> u16 {
+    match code {
+        "BadRequest" => 400,
+        "Unauthorized" => 401,
+        "Forbidden" => 403,
+        "NotFound" => 404,
+        "Conflict" => 409,
+        "Overloaded" => 429,
+        "Cancelled" => 499,
+        "Connection" => 502,
+        "Unavailable" | "CircuitOpen" => 503,
+        "Timeout" => 504,
+        _ => 500,
+    }
+}
 
 /// Message bus interface for request/reply and subscription patterns
 #[async_trait]
@@ -55,11 +306,57 @@ pub trait BodyBus: Send + Sync {
     /// Send a request and wait for a reply
     async fn request(&self, envelope: Envelope) -> Result<Value, BusError>;
 
-    /// Subscribe to a subject with a handler function
-    async fn subscribe(&self, subject: &str, handler: MessageHandler) -> Result<(), BusError>;
+    /// Send a nested request on behalf of a handler, propagating its deadline and metadata
+    async fn request_with_context(
+        &self,
+        envelope: Envelope,
+        parent: &RequestContext,
+    ) -> Result<Value, BusError>;
+
+    /// Subscribe to a subject with a handler
+    ///
+    /// The returned handle unsubscribes when dropped; call `detach` on it to
+    /// keep the handler registered for the lifetime of the bus.
+    async fn subscribe(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError>;
+
+    /// Send a request whose handler may reply with a stream of envelopes
+    ///
+    /// The returned stream yields each response envelope until the handler's
+    /// end-of-stream marker. Handlers stream through the `StreamSink` in their
+    /// context (see `stream_handler`); a plain handler's reply arrives as a
+    /// single item. Buses that cannot stream return `Unavailable`.
+    async fn request_stream(&self, envelope: Envelope) -> Result<ResponseStream, BusError> {
+        let _ = envelope;
+        Err(BusError::Unavailable("This bus does not support streamed replies".to_string()))
+    }
+
+    /// Send a request to every instance subscribed to its subject and gather the replies
+    ///
+    /// Unlike `request`, the envelope bypasses the queue group. Replies are
+    /// collected until `options.expected` have arrived or the timeout passes;
+    /// each carries its responder's id and that instance's result, so one
+    /// failing instance does not hide the others. Instances that have not
+    /// replied by then are left out. Buses that cannot scatter return `Unavailable`.
+    async fn request_all(&self, envelope: Envelope, options: ScatterOptions) -> Result<Vec<ScatterReply>, BusError> {
+        let _ = (envelope, options);
+        Err(BusError::Unavailable("This bus does not support scatter-gather requests".to_string()))
+    }
+
+    /// Publish a fire-and-forget event to every event subscriber on its subject
+    async fn publish(&self, envelope: Envelope) -> Result<(), BusError>;
+
+    /// Subscribe to published events; each subscriber receives every event
+    ///
+    /// Unlike `subscribe`, event subscriptions do not join the service's queue
+    /// group, and the handler's return value is discarded.
+    async fn subscribe_events(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError>;
 }
 
 /// Cell interface for registering handlers with the bus
+///
+/// The Body drives each cell through `init`, `register` and `start` before
+/// it takes traffic, and `stop` on shutdown (see `CellHost`). Only `id`,
+/// `subjects` and `register` are required.
 #[async_trait]
 pub trait Cell: Send + Sync {
     /// Unique identifier for this cell
@@ -68,8 +365,29 @@ pub trait Cell: Send + Sync {
     /// List of subjects this cell subscribes to
     fn subjects(&self) -> Vec<String>;
 
+    /// Read configuration and open resources, before any handler is registered
+    async fn init(&self, config: &CellConfig) -> Result<(), BusError> {
+        let _ = config;
+        Ok(())
+    }
+
     /// Register this cell's handlers with the bus
     async fn register(&self, bus: &dyn BodyBus) -> Result<(), BusError>;
+
+    /// Begin work that is not driven by messages, once handlers are registered
+    async fn start(&self) -> Result<(), BusError> {
+        Ok(())
+    }
+
+    /// Flush state and release resources on shutdown
+    async fn stop(&self) -> Result<(), BusError> {
+        Ok(())
+    }
+
+    /// Whether the cell can currently serve
+    async fn health(&self) -> HealthStatus {
+        HealthStatus::Healthy
+    }
 }
 
 impl Envelope {
@@ -82,6 +400,7 @@ impl Envelope {
             schema: schema.to_string(),
             payload: Some(payload),
             error: None,
+            headers: None,
         }
     }
 
@@ -100,6 +419,7 @@ impl Envelope {
             schema: schema.to_string(),
             payload: Some(payload),
             error: None,
+            headers: None,
         }
     }
 
@@ -118,6 +438,7 @@ impl Envelope {
             schema: schema.to_string(),
             payload: None,
             error: Some(error),
+            headers: None,
         }
     }
 
@@ -126,6 +447,27 @@ impl Envelope {
         self.error.is_some()
     }
 
+    /// Look up a header value
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.as_ref().and_then(|headers| headers.get(key))
+    }
+
+    /// Headers for modification, created on first use
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        self.headers.get_or_insert_with(Headers::new)
+    }
+
+    /// Set a header, replacing any previous value; `version` is reserved
+    pub fn set_header(&mut self, key: &str, value: &str) -> Result<(), BusError> {
+        self.headers_mut().insert(key, value)
+    }
+
+    /// Builder-style `set_header`
+    pub fn with_header(mut self, key: &str, value: &str) -> Result<Self, BusError> {
+        self.set_header(key, value)?;
+        Ok(self)
+    }
+
     /// Get the NATS subject for this envelope
     pub fn subject(&self) -> String {
         format!("cbs.{}.{}", self.service, self.verb)
@@ -150,6 +492,11 @@ impl ErrorDetails {
             details: Some(details),
         }
     }
+
+    /// HTTP status a gateway should answer with for this error code
+    pub fn http_status(&self) -> u16 {
+        status_for_code(&self.code)
+    }
 }
 
 #[cfg(test)]
@@ -166,6 +513,7 @@ mod tests {
             schema: "demo/v1/Name".to_string(),
             payload: Some(json!({"name": "Ada"})),
             error: None,
+            headers: None,
         };
 
         let serialized = serde_json::to_string(&envelope).unwrap();
@@ -275,7 +623,7 @@ mod tests {
             "Request timeout: no response received within deadline"
         );
         assert_eq!(
-            BusError::BadRequest("missing field".to_string()).to_string(),
+            BusError::bad_request("missing field").to_string(),
             "Bad request: missing field"
         );
         assert_eq!(
@@ -310,4 +658,83 @@ mod tests {
             _ => panic!("Expected Timeout error"),
         }
     }
+
+    #[test]
+    fn error_details_roundtrip_is_lossless() {
+        let errors = vec![
+            BusError::Timeout,
+            BusError::bad_request_with_details(
+                "validation failed",
+                json!({"fields": [{"path": "name", "error": "required"}]}),
+            ),
+            BusError::Unauthorized("missing token".to_string()),
+            BusError::Forbidden("not an admin".to_string()),
+            BusError::NotFound("no such user".to_string()),
+            BusError::Conflict {
+                message: "stale write".to_string(),
+                details: Some(json!({"expected": 3, "actual": 4})),
+            },
+            BusError::Internal("boom".to_string()),
+            BusError::Connection("refused".to_string()),
+            BusError::Serialization("bad json".to_string()),
+            BusError::Unavailable("draining".to_string()),
+            BusError::Overloaded("too many requests".to_string()),
+            BusError::Cancelled,
+            BusError::CircuitOpen {
+                service: "billing".to_string(),
+                retry_after: std::time::Duration::from_millis(1500),
+            },
+            BusError::Custom {
+                code: "QuotaExceeded".to_string(),
+                message: "monthly quota used".to_string(),
+                details: Some(json!({"limit": 100})),
+            },
+        ];
+
+        for error in errors {
+            let envelope = Envelope::new_error("id", "svc", "verb", "demo/v1/Error", error.to_error_details());
+            let wire = serde_json::to_string(&envelope).unwrap();
+            let decoded: Envelope = serde_json::from_str(&wire).unwrap();
+            let restored = BusError::from_error_details(decoded.error.unwrap());
+
+            assert_eq!(restored.code(), error.code());
+            assert_eq!(restored.message(), error.message());
+            assert_eq!(restored.details(), error.details());
+            assert_eq!(restored.to_string(), error.to_string());
+        }
+    }
+
+    #[test]
+    fn unknown_codes_decode_to_custom() {
+        let error = BusError::from(ErrorDetails::with_details("Teapot", "short and stout", json!({"spout": true})));
+        match error {
+            BusError::Custom { code, message, details } => {
+                assert_eq!(code, "Teapot");
+                assert_eq!(message, "short and stout");
+                assert_eq!(details, Some(json!({"spout": true})));
+            }
+            other => panic!("Expected Custom, got {:?}", other),
+        }
+
+        let malformed = BusError::from(ErrorDetails::new("CircuitOpen", "no details"));
+        assert_eq!(malformed.code(), "CircuitOpen");
+        assert!(matches!(malformed, BusError::Custom { .. }));
+    }
+
+    #[test]
+    fn error_codes_map_to_http_status() {
+        assert_eq!(BusError::bad_request("x").http_status(), 400);
+        assert_eq!(BusError::Unauthorized("x".into()).http_status(), 401);
+        assert_eq!(BusError::Forbidden("x".into()).http_status(), 403);
+        assert_eq!(BusError::NotFound("x".into()).http_status(), 404);
+        assert_eq!(BusError::conflict("x").http_status(), 409);
+        assert_eq!(BusError::Overloaded("x".into()).http_status(), 429);
+        assert_eq!(BusError::Cancelled.http_status(), 499);
+        assert_eq!(BusError::Internal("x".into()).http_status(), 500);
+        assert_eq!(BusError::Connection("x".into()).http_status(), 502);
+        assert_eq!(BusError::Unavailable("x".into()).http_status(), 503);
+        assert_eq!(BusError::Timeout.http_status(), 504);
+        assert_eq!(BusError::custom("QuotaExceeded", "x").http_status(), 500);
+        assert_eq!(ErrorDetails::new("Forbidden", "x").http_status(), 403);
+    }
 }
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::{BodyBus, BusError, Cell, CellConfig};
+
+/// What a cell reports from `Cell::health`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    /// Serving, but impaired
+    Degraded(String),
+    /// Not able to serve
+    Unhealthy(String),
+}
+
+impl HealthStatus {
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, HealthStatus::Healthy)
+    }
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthStatus::Healthy => write!(f, "healthy"),
+            HealthStatus::Degraded(reason) => write!(f, "degraded: {}", reason),
+            HealthStatus::Unhealthy(reason) => write!(f, "unhealthy: {}", reason),
+        }
+    }
+}
+
+/// How often `CellHost` re-checks an unhealthy dependency
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lifecycle step a cell failed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecyclePhase {
+    /// Waiting for the cells it depends on to report healthy
+    Dependencies,
+    Init,
+    Register,
+    Start,
+    Stop,
+}
+
+impl fmt::Display for LifecyclePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self {
+            LifecyclePhase::Dependencies => "wait for dependencies",
+            LifecyclePhase::Init => "init",
+            LifecyclePhase::Register => "register",
+            LifecyclePhase::Start => "start",
+            LifecyclePhase::Stop => "stop",
+        };
+        f.write_str(phase)
+    }
+}
+
+/// A lifecycle hook failed for one cell
+#[derive(Debug, Error)]
+#[error("Cell {cell} failed to {phase}: {source}")]
+pub struct LifecycleError {
+    pub cell: String,
+    pub phase: LifecyclePhase,
+    #[source]
+    pub source: BusError,
+}
+
+/// Drives the lifecycle of an application's cells
+///
+/// `start_all` takes each cell, in the order added, through `init`,
+/// `register` and `start`, once every cell named in its `dependencies`
+/// has started and reports healthy. Cells must be added after their
+/// dependencies; `CellRegistry::host_for` adds them in that order. If a
+/// cell fails, it is stopped too when it got past `init`, then the cells
+/// already started are stopped again, newest first, and the error names the
+/// failing cell. `stop_all` stops started cells
+/// newest first and reports every failure.
+pub struct CellHost {
+    cells: Vec<(Arc<dyn Cell>, CellConfig)>,
+    buses: HashMap<String, Arc<dyn BodyBus>>,
+    started: usize,
+    dependency_timeout: Duration,
+}
+
+impl Default for CellHost {
+    fn default() -> Self {
+        Self {
+            cells: Vec::new(),
+            buses: HashMap::new(),
+            started: 0,
+            dependency_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CellHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Longest a cell waits for its dependencies to report healthy
+    pub fn with_dependency_timeout(mut self, timeout: Duration) -> Self {
+        self.dependency_timeout = timeout;
+        self
+    }
+
+    /// Add a cell with its entry from app.yaml
+    pub fn add(&mut self, cell: Arc<dyn Cell>, config: CellConfig) {
+        self.cells.push((cell, config));
+    }
+
+    /// Register the cell named `name` on `bus` instead of the bus passed to
+    /// `start_all`, e.g. one wrapping it in middleware of its own
+    pub fn use_bus(&mut self, name: &str, bus: Arc<dyn BodyBus>) {
+        self.buses.insert(name.to_string(), bus);
+    }
+
+    /// App.yaml entries of the cells, in start order
+    pub fn configs(&self) -> impl Iterator<Item = &CellConfig> {
+        self.cells.iter().map(|(_, config)| config)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// App.yaml names of the cells, in start order
+    pub fn names(&self) -> Vec<&str> {
+        self.cells.iter().map(|(_, config)| config.name.as_str()).collect()
+    }
+
+    /// Ids of the cells currently started
+    pub fn started(&self) -> Vec<&str> {
+        self.cells[..self.started].iter().map(|(cell, _)| cell.id()).collect()
+    }
+
+    /// Initialise, register and start every cell not yet started
+    pub async fn start_all(&mut self, bus: &dyn BodyBus) -> Result<(), LifecycleError> {
+        while self.started < self.cells.len() {
+            let (cell, config) = &self.cells[self.started];
+            let cell_bus = self.buses.get(&config.name).map_or(bus, |bus| bus.as_ref());
+            let started = match self.await_dependencies(cell.as_ref(), config).await {
+                Ok(()) => start_cell(cell.as_ref(), config, cell_bus).await,
+                Err(error) => Err(error),
+            };
+            if let Err(error) = started {
+                warn!(cell = %error.cell, phase = %error.phase, error = %error.source, "Cell failed to start");
+                if matches!(error.phase, LifecyclePhase::Register | LifecyclePhase::Start) {
+                    if let Err(source) = cell.stop().await {
+                        warn!(cell = %error.cell, error = %source, "Cell failed to stop after a failed start");
+                    }
+                }
+                for error in self.stop_all().await {
+                    warn!(cell = %error.cell, error = %error.source, "Cell failed to stop after a failed start");
+                }
+                return Err(error);
+            }
+            info!(cell = %cell.id(), "Cell started");
+            self.started += 1;
+        }
+        Ok(())
+    }
+
+    /// Stop every started cell, newest first, returning the failures
+    pub async fn stop_all(&mut self) -> Vec<LifecycleError> {
+        let mut errors = Vec::new();
+        while self.started > 0 {
+            self.started -= 1;
+            let cell = &self.cells[self.started].0;
+            match cell.stop().await {
+                Ok(()) => info!(cell = %cell.id(), "Cell stopped"),
+                Err(source) => errors.push(LifecycleError {
+                    cell: cell.id().to_string(),
+                    phase: LifecyclePhase::Stop,
+                    source,
+                }),
+            }
+        }
+        errors
+    }
+
+    /// Wait until every dependency of `config` has started and reports healthy
+    async fn await_dependencies(&self, cell: &dyn Cell, config: &CellConfig) -> Result<(), LifecycleError> {
+        let failed = |reason| LifecycleError {
+            cell: cell.id().to_string(),
+            phase: LifecyclePhase::Dependencies,
+            source: BusError::Unavailable(reason),
+        };
+        let deadline = Instant::now() + self.dependency_timeout;
+        for name in &config.dependencies {
+            let dependency = self.cells[..self.started]
+                .iter()
+                .find(|(_, config)| config.name == *name)
+                .map(|(dependency, _)| dependency)
+                .ok_or_else(|| failed(format!("Dependency {} has not been started", name)))?;
+            loop {
+                let status = dependency.health().await;
+                if status.is_healthy() {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    return Err(failed(format!("Dependency {} is {}", name, status)));
+                }
+                tokio::time::sleep(HEALTH_POLL_INTERVAL).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Health of every started cell, in start order
+    pub async fn health(&self) -> Vec<(String, HealthStatus)> {
+        let mut statuses = Vec::with_capacity(self.started);
+        for (cell, _) in &self.cells[..self.started] {
+            statuses.push((cell.id().to_string(), cell.health().await));
+        }
+        statuses
+    }
+}
+
+impl fmt::Debug for CellHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cells: Vec<&str> = self.cells.iter().map(|(cell, _)| cell.id()).collect();
+        f.debug_struct("CellHost")
+            .field("cells", &cells)
+            .field("own_bus", &self.buses.keys().collect::<Vec<_>>())
+            .field("started", &self.started)
+            .field("dependency_timeout", &self.dependency_timeout)
+            .finish()
+    }
+}
+
+async fn start_cell(cell: &dyn Cell, config: &CellConfig, bus: &dyn BodyBus) -> Result<(), LifecycleError> {
+    let failed = |phase, source| LifecycleError {
+        cell: cell.id().to_string(),
+        phase,
+        source,
+    };
+    cell.init(config).await.map_err(|e| failed(LifecyclePhase::Init, e))?;
+    cell.register(bus).await.map_err(|e| failed(LifecyclePhase::Register, e))?;
+    cell.start().await.map_err(|e| failed(LifecyclePhase::Start, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync_handler, Envelope, LocalBus};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    /// Cell that records its hooks in a shared journal and can fail one phase
+    struct Probe {
+        id: String,
+        fail: Option<LifecyclePhase>,
+        journal: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Probe {
+        fn cell(id: &str, fail: Option<LifecyclePhase>, journal: &Arc<Mutex<Vec<String>>>) -> Arc<dyn Cell> {
+            Arc::new(Self {
+                id: id.to_string(),
+                fail,
+                journal: Arc::clone(journal),
+            })
+        }
+
+        fn record(&self, phase: LifecyclePhase) -> Result<(), BusError> {
+            self.journal.lock().unwrap().push(format!("{} {}", phase, self.id));
+            match self.fail {
+                Some(fail) if fail == phase => Err(BusError::Internal(format!("{} broke", self.id))),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Cell for Probe {
+        fn id(&self) -> &str {
+            &self.id
+        }
+
+        fn subjects(&self) -> Vec<String> {
+            vec![format!("cbs.{}.ping", self.id)]
+        }
+
+        async fn init(&self, config: &CellConfig) -> Result<(), BusError> {
+            assert_eq!(config.name, self.id);
+            self.record(LifecyclePhase::Init)
+        }
+
+        async fn register(&self, bus: &dyn BodyBus) -> Result<(), BusError> {
+            bus.subscribe(&self.subjects()[0], sync_handler(|_| Ok(json!({"pong": true}))))
+                .await?
+                .detach();
+            self.record(LifecyclePhase::Register)
+        }
+
+        async fn start(&self) -> Result<(), BusError> {
+            self.record(LifecyclePhase::Start)
+        }
+
+        async fn stop(&self) -> Result<(), BusError> {
+            self.record(LifecyclePhase::Stop)
+        }
+
+        async fn health(&self) -> HealthStatus {
+            match self.fail {
+                Some(_) => HealthStatus::Degraded("configured to fail".to_string()),
+                None => HealthStatus::Healthy,
+            }
+        }
+    }
+
+    /// Cell that reports unhealthy until `ready` is set
+    struct Warming {
+        ready: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Cell for Warming {
+        fn id(&self) -> &str {
+            "store"
+        }
+
+        fn subjects(&self) -> Vec<String> {
+            vec![]
+        }
+
+        async fn register(&self, _bus: &dyn BodyBus) -> Result<(), BusError> {
+            Ok(())
+        }
+
+        async fn health(&self) -> HealthStatus {
+            match self.ready.load(Ordering::SeqCst) {
+                true => HealthStatus::Healthy,
+                false => HealthStatus::Unhealthy("warming up".to_string()),
+            }
+        }
+    }
+
+    fn config(name: &str) -> CellConfig {
+        CellConfig {
+            name: name.to_string(),
+            path: format!("cells/{}", name),
+            dependencies: vec![],
+            limits: None,
+            config: None,
+        }
+    }
+
+    fn depending_on(name: &str, dependency: &str) -> CellConfig {
+        CellConfig {
+            dependencies: vec![dependency.to_string()],
+            ..config(name)
+        }
+    }
+
+    #[tokio::test]
+    async fn cells_start_in_order_and_stop_in_reverse() {
+        let (bus, journal) = (LocalBus::new(), Arc::new(Mutex::new(Vec::new())));
+        let mut host = CellHost::new();
+        host.add(Probe::cell("a", None, &journal), config("a"));
+        host.add(Probe::cell("b", Some(LifecyclePhase::Stop), &journal), config("b"));
+
+        host.start_all(&bus).await.unwrap();
+        assert_eq!(host.started(), vec!["a", "b"]);
+        let ping = Envelope::new_request("b", "ping", "demo/v1/Void", json!({}));
+        assert_eq!(bus.request(ping).await.unwrap()["pong"], true);
+
+        let health = host.health().await;
+        assert_eq!(health[0], ("a".to_string(), HealthStatus::Healthy));
+        assert!(!health[1].1.is_healthy());
+
+        let errors = host.stop_all().await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].cell.as_str(), errors[0].phase), ("b", LifecyclePhase::Stop));
+        assert_eq!(
+            *journal.lock().unwrap(),
+            ["init a", "register a", "start a", "init b", "register b", "start b", "stop b", "stop a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn cells_can_register_on_a_bus_of_their_own() {
+        let (bus, journal) = (LocalBus::new(), Arc::new(Mutex::new(Vec::new())));
+        let own = Arc::new(LocalBus::new());
+        let mut host = CellHost::new();
+        host.add(Probe::cell("a", None, &journal), config("a"));
+        host.add(Probe::cell("b", None, &journal), config("b"));
+        host.use_bus("b", own.clone());
+
+        host.start_all(&bus).await.unwrap();
+        let ping = |cell| Envelope::new_request(cell, "ping", "demo/v1/Void", json!({}));
+        assert!(bus.request(ping("a")).await.is_ok());
+        assert!(bus.request(ping("b")).await.is_err());
+        assert!(own.request(ping("b")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_start_names_the_cell_and_stops_the_others() {
+        let (bus, journal) = (LocalBus::new(), Arc::new(Mutex::new(Vec::new())));
+        let mut host = CellHost::new();
+        host.add(Probe::cell("a", None, &journal), config("a"));
+        host.add(Probe::cell("b", Some(LifecyclePhase::Start), &journal), config("b"));
+        host.add(Probe::cell("c", None, &journal), config("c"));
+
+        let error = host.start_all(&bus).await.unwrap_err();
+        assert_eq!((error.cell.as_str(), error.phase), ("b", LifecyclePhase::Start));
+        assert_eq!(error.to_string(), "Cell b failed to start: Internal error: b broke");
+        assert!(host.started().is_empty());
+        assert_eq!(
+            *journal.lock().unwrap(),
+            ["init a", "register a", "start a", "init b", "register b", "start b", "stop b", "stop a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn cells_failing_init_are_not_stopped() {
+        let (bus, journal) = (LocalBus::new(), Arc::new(Mutex::new(Vec::new())));
+        let mut host = CellHost::new();
+        host.add(Probe::cell("a", None, &journal), config("a"));
+        host.add(Probe::cell("b", Some(LifecyclePhase::Init), &journal), config("b"));
+
+        let error = host.start_all(&bus).await.unwrap_err();
+        assert_eq!(error.to_string(), "Cell b failed to init: Internal error: b broke");
+        assert_eq!(*journal.lock().unwrap(), ["init a", "register a", "start a", "init b", "stop a"]);
+    }
+
+    #[tokio::test]
+    async fn dependents_start_once_dependencies_are_healthy() {
+        let (bus, journal) = (LocalBus::new(), Arc::new(Mutex::new(Vec::new())));
+        let ready = Arc::new(AtomicBool::new(false));
+        let mut host = CellHost::new().with_dependency_timeout(Duration::from_secs(5));
+        host.add(Arc::new(Warming { ready: Arc::clone(&ready) }), config("store"));
+        host.add(Probe::cell("ui", None, &journal), depending_on("ui", "store"));
+
+        let warm_up = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            ready.store(true, Ordering::SeqCst);
+        });
+        let start = Instant::now();
+        host.start_all(&bus).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(host.started(), vec!["store", "ui"]);
+        warm_up.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unhealthy_dependency_fails_the_dependent() {
+        let (bus, journal) = (LocalBus::new(), Arc::new(Mutex::new(Vec::new())));
+        let ready = Arc::new(AtomicBool::new(false));
+        let mut host = CellHost::new().with_dependency_timeout(Duration::from_millis(100));
+        host.add(Arc::new(Warming { ready }), config("store"));
+        host.add(Probe::cell("ui", None, &journal), depending_on("ui", "store"));
+
+        let error = host.start_all(&bus).await.unwrap_err();
+        assert_eq!((error.cell.as_str(), error.phase), ("ui", LifecyclePhase::Dependencies));
+        assert_eq!(
+            error.to_string(),
+            "Cell ui failed to wait for dependencies: Unavailable: Dependency store is unhealthy: warming up"
+        );
+        assert!(host.started().is_empty());
+        assert!(journal.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dependencies_must_be_added_first() {
+        let (bus, journal) = (LocalBus::new(), Arc::new(Mutex::new(Vec::new())));
+        let mut host = CellHost::new();
+        host.add(Probe::cell("ui", None, &journal), depending_on("ui", "a"));
+        host.add(Probe::cell("a", None, &journal), config("a"));
+
+        let error = host.start_all(&bus).await.unwrap_err();
+        assert!(error.to_string().contains("Dependency a has not been started"));
+    }
+}
@@ -0,0 +1,405 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+use crate::middleware::{DispatchNext, Middleware, PublishNext, RequestNext, ScatterNext, StreamNext};
+use crate::subject;
+use crate::{BusError, Envelope, RequestContext, ResponseStream, ScatterOptions, ScatterReply};
+
+/// Rate and concurrency caps for a cell or subject
+///
+/// Unset fields impose no limit.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LimitConfig {
+    /// Sustained messages per second (token refill rate)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_per_sec: Option<f64>,
+    /// Bucket size, i.e. how many messages may arrive at once; defaults to one second's worth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    /// Messages processed at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<u32>,
+}
+
+/// Limits from the `limits:` section of `app.yaml`
+///
+/// ```yaml
+/// limits:
+///   subjects:            # enforced by the handling side
+///     "cbs.greeter.*": { rate_per_sec: 100, burst: 20, max_in_flight: 8 }
+///   outbound:            # caps this process's own calls
+///     "cbs.payment.>": { max_in_flight: 4 }
+/// ```
+///
+/// Per-cell limits live on the cell entry (`cells[].limits`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub subjects: BTreeMap<String, LimitConfig>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outbound: BTreeMap<String, LimitConfig>,
+}
+
+impl LimitsConfig {
+    /// Whether no limit is configured
+    pub fn is_empty(&self) -> bool {
+        self.subjects.is_empty() && self.outbound.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// One limit scope: a cell or a subject pattern
+#[derive(Debug)]
+struct Limiter {
+    scope: String,
+    bucket: Option<Mutex<TokenBucket>>,
+    slots: Option<Arc<Semaphore>>,
+}
+
+impl Limiter {
+    fn new(scope: &str, config: &LimitConfig) -> Self {
+        let bucket = config.rate_per_sec.filter(|rate| *rate > 0.0).map(|rate| {
+            let capacity = config.burst.map_or(rate.ceil(), f64::from).max(1.0);
+            Mutex::new(TokenBucket {
+                capacity,
+                tokens: capacity,
+                refill_per_sec: rate,
+                refilled_at: Instant::now(),
+            })
+        });
+        let slots = config
+            .max_in_flight
+            .map(|max| Arc::new(Semaphore::new(max.max(1) as usize)));
+        Self {
+            scope: scope.to_string(),
+            bucket,
+            slots,
+        }
+    }
+
+    /// Take a token and a slot, or fail with `Overloaded`; the permit frees the slot on drop
+    fn admit(&self) -> Result<Option<OwnedSemaphorePermit>, BusError> {
+        if let Some(bucket) = &self.bucket {
+            if !bucket.lock().expect("rate limiter lock poisoned").try_take() {
+                return Err(BusError::Overloaded(format!("rate limit exceeded for {}", self.scope)));
+            }
+        }
+        match &self.slots {
+            Some(slots) => Arc::clone(slots)
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(|_| BusError::Overloaded(format!("too many in-flight messages for {}", self.scope))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Inbound,
+    Outbound,
+}
+
+/// Middleware that rejects messages beyond a rate or concurrency limit with `BusError::Overloaded`
+///
+/// Inbound limits protect handlers and are checked at dispatch, so callers get
+/// the error back as a reply instead of waiting for a timeout. Outbound limits
+/// cap this process's own requests and events.
+#[derive(Debug)]
+pub struct LimitMiddleware {
+    side: Side,
+    all: Option<Limiter>,
+    subjects: Vec<(String, Limiter)>,
+}
+
+impl LimitMiddleware {
+    /// Limit handled messages per subject pattern; the most specific pattern applies
+    pub fn inbound(subjects: &BTreeMap<String, LimitConfig>) -> Result<Self, BusError> {
+        Self::for_subjects(Side::Inbound, subjects)
+    }
+
+    /// Limit this process's requests and events per subject pattern
+    pub fn outbound(subjects: &BTreeMap<String, LimitConfig>) -> Result<Self, BusError> {
+        Self::for_subjects(Side::Outbound, subjects)
+    }
+
+    /// Limit every message handled through the bus this layer wraps, e.g. one cell's bus
+    pub fn for_cell(cell: &str, config: &LimitConfig) -> Self {
+        Self {
+            side: Side::Inbound,
+            all: Some(Limiter::new(&format!("cell {}", cell), config)),
+            subjects: Vec::new(),
+        }
+    }
+
+    fn for_subjects(side: Side, subjects: &BTreeMap<String, LimitConfig>) -> Result<Self, BusError> {
+        let subjects = subjects
+            .iter()
+            .map(|(pattern, config)| {
+                subject::validate_pattern(pattern)?;
+                Ok((pattern.clone(), Limiter::new(pattern, config)))
+            })
+            .collect::<Result<_, BusError>>()?;
+        Ok(Self {
+            side,
+            all: None,
+            subjects,
+        })
+    }
+
+    fn admit(&self, envelope: &Envelope) -> Result<Vec<OwnedSemaphorePermit>, BusError> {
+        let subject = envelope.subject();
+        let matched = subject::best_match(self.subjects.iter().map(|(pattern, _)| pattern.as_str()), &subject)
+            .and_then(|pattern| self.subjects.iter().find(|(p, _)| p == pattern))
+            .map(|(_, limiter)| limiter);
+
+        let mut permits = Vec::new();
+        for limiter in self.all.iter().chain(matched) {
+            match limiter.admit() {
+                Ok(permit) => permits.extend(permit),
+                Err(e) => {
+                    warn!(id = %envelope.id, service = %envelope.service, verb = %envelope.verb, error = %e, "Rejected message over limit");
+                    return Err(e);
+                }
+            }
+        }
+        Ok(permits)
+    }
+}
+
+#[async_trait]
+impl Middleware for LimitMiddleware {
+    async fn request(
+        &self,
+        envelope: Envelope,
+        parent: Option<&RequestContext>,
+        next: RequestNext,
+    ) -> Result<Value, BusError> {
+        if self.side != Side::Outbound {
+            return next.run(envelope, parent).await;
+        }
+        let _permits = self.admit(&envelope)?;
+        next.run(envelope, parent).await
+    }
+
+    /// Outbound streams keep their in-flight slot until the stream is dropped
+    async fn request_stream(&self, envelope: Envelope, next: StreamNext) -> Result<ResponseStream, BusError> {
+        if self.side != Side::Outbound {
+            return next.run(envelope).await;
+        }
+        let permits = self.admit(&envelope)?;
+        Ok(next.run(envelope).await?.hold(permits))
+    }
+
+    async fn request_all(
+        &self,
+        envelope: Envelope,
+        options: ScatterOptions,
+        next: ScatterNext,
+    ) -> Result<Vec<ScatterReply>, BusError> {
+        if self.side != Side::Outbound {
+            return next.run(envelope, options).await;
+        }
+        let _permits = self.admit(&envelope)?;
+        next.run(envelope, options).await
+    }
+
+    async fn publish(&self, envelope: Envelope, next: PublishNext) -> Result<(), BusError> {
+        if self.side != Side::Outbound {
+            return next.run(envelope).await;
+        }
+        let _permits = self.admit(&envelope)?;
+        next.run(envelope).await
+    }
+
+    async fn dispatch(
+        &self,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        if self.side != Side::Inbound {
+            return next.run(envelope, ctx).await;
+        }
+        let _permits = self.admit(&envelope)?;
+        next.run(envelope, ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_handler, sync_handler, BodyBus, LocalBus, MiddlewareBus};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn request() -> Envelope {
+        Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}))
+    }
+
+    fn limits(pattern: &str, config: LimitConfig) -> BTreeMap<String, LimitConfig> {
+        BTreeMap::from([(pattern.to_string(), config)])
+    }
+
+    #[test]
+    fn token_bucket_allows_burst_then_refills() {
+        let limiter = Limiter::new(
+            "test",
+            &LimitConfig {
+                rate_per_sec: Some(1000.0),
+                burst: Some(2),
+                max_in_flight: None,
+            },
+        );
+        assert!(limiter.admit().is_ok());
+        assert!(limiter.admit().is_ok());
+        assert!(matches!(limiter.admit(), Err(BusError::Overloaded(_))));
+
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(limiter.admit().is_ok());
+    }
+
+    #[tokio::test]
+    async fn inbound_rate_limit_replies_overloaded() {
+        let config = LimitConfig {
+            rate_per_sec: Some(0.001),
+            burst: Some(2),
+            max_in_flight: None,
+        };
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(LimitMiddleware::inbound(&limits("cbs.greeter.*", config)).unwrap());
+        let greeter = bus
+            .subscribe("cbs.greeter.say_hello", sync_handler(|_| Ok(json!({"ok": true}))))
+            .await
+            .unwrap();
+
+        assert!(bus.request(request()).await.is_ok());
+        assert!(bus.request(request()).await.is_ok());
+        match bus.request(request()).await {
+            Err(BusError::Overloaded(message)) => assert!(message.contains("cbs.greeter.*")),
+            other => panic!("Expected Overloaded, got: {:?}", other),
+        }
+        assert_eq!(greeter.failed(), 1);
+    }
+
+    #[tokio::test]
+    async fn cell_concurrency_limit_rejects_extra_in_flight() {
+        let config = LimitConfig {
+            max_in_flight: Some(1),
+            ..LimitConfig::default()
+        };
+        let local: Arc<dyn BodyBus> = Arc::new(LocalBus::new());
+        let cell_bus = MiddlewareBus::new(Arc::clone(&local)).layer(LimitMiddleware::for_cell("greeter", &config));
+        let _greeter = cell_bus
+            .subscribe(
+                "cbs.greeter.say_hello",
+                async_handler(|_envelope, _ctx| async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(json!({"ok": true}))
+                }),
+            )
+            .await
+            .unwrap();
+
+        let (first, second) = tokio::join!(local.request(request()), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            local.request(request()).await
+        });
+        assert!(first.is_ok());
+        match second {
+            Err(BusError::Overloaded(message)) => assert!(message.contains("cell greeter")),
+            other => panic!("Expected Overloaded, got: {:?}", other),
+        }
+
+        // The slot is released once the first request completes
+        assert!(local.request(request()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn outbound_limits_cap_the_caller_only() {
+        let config = LimitConfig {
+            rate_per_sec: Some(0.001),
+            burst: Some(1),
+            max_in_flight: None,
+        };
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(LimitMiddleware::outbound(&limits("cbs.greeter.>", config)).unwrap());
+        let greeter = bus
+            .subscribe("cbs.greeter.say_hello", sync_handler(|_| Ok(json!({}))))
+            .await
+            .unwrap();
+
+        assert!(bus.request(request()).await.is_ok());
+        assert!(matches!(bus.request(request()).await, Err(BusError::Overloaded(_))));
+        let scattered = bus.request_all(request(), ScatterOptions::new()).await;
+        assert!(matches!(scattered, Err(BusError::Overloaded(_))));
+        assert_eq!(greeter.delivered(), 1);
+
+        assert!(LimitMiddleware::outbound(&limits("cbs.>.bad", LimitConfig::default())).is_err());
+    }
+
+    #[tokio::test]
+    async fn outbound_streams_hold_their_slot_until_dropped() {
+        let config = LimitConfig {
+            max_in_flight: Some(1),
+            ..LimitConfig::default()
+        };
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(LimitMiddleware::outbound(&limits("cbs.greeter.>", config)).unwrap());
+        let _greeter = bus
+            .subscribe("cbs.greeter.say_hello", sync_handler(|_| Ok(json!({}))))
+            .await
+            .unwrap();
+
+        let open = bus.request_stream(request()).await.unwrap();
+        assert!(matches!(bus.request_stream(request()).await, Err(BusError::Overloaded(_))));
+        assert!(matches!(bus.request(request()).await, Err(BusError::Overloaded(_))));
+
+        drop(open);
+        assert!(bus.request_stream(request()).await.is_ok());
+    }
+
+    #[test]
+    fn parses_app_yaml_section() {
+        let config: LimitsConfig = serde_yaml::from_str(
+            r#"
+subjects:
+  "cbs.greeter.*": { rate_per_sec: 100, burst: 20, max_in_flight: 8 }
+outbound:
+  "cbs.payment.>": { max_in_flight: 4 }
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.subjects["cbs.greeter.*"].burst, Some(20));
+        assert_eq!(config.outbound["cbs.payment.>"].max_in_flight, Some(4));
+        assert!(config.outbound["cbs.payment.>"].rate_per_sec.is_none());
+    }
+}
@@ -0,0 +1,913 @@
+use async_trait::async_trait;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::{mpsc, oneshot, RwLock, Semaphore};
+use tracing::{info, warn};
+
+use crate::cancel::parent_cancelled;
+use crate::scatter::mark_scatter;
+use crate::{
+    headers, subject, BodyBus, BusError, CancellationToken, Envelope, RequestContext, ResponseStream,
+    ScatterOptions, ScatterReply, SharedHandler, Subscription, SubscriptionStats,
+};
+
+/// Configuration for the in-process bus
+#[derive(Debug, Clone)]
+pub struct LocalBusConfig {
+    /// Longest a request waits for a reply, including time spent queued
+    pub request_timeout: Duration,
+    /// Messages a subscriber may have pending before senders wait for room
+    pub queue_capacity: usize,
+    /// Messages a subscriber handles at the same time; 1 handles them strictly in order
+    pub max_concurrent_handlers: usize,
+}
+
+impl Default for LocalBusConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(5),
+            queue_capacity: 256,
+            max_concurrent_handlers: 16,
+        }
+    }
+}
+
+/// Message waiting in a subscriber's queue
+struct Delivery {
+    envelope: Envelope,
+    ctx: RequestContext,
+    reply: Option<oneshot::Sender<Result<Value, BusError>>>,
+}
+
+/// One subscriber: a bounded queue drained by its own worker task
+#[derive(Clone)]
+struct Member {
+    /// Reported as the responder of scatter replies
+    id: String,
+    queue: mpsc::Sender<Delivery>,
+    stats: Arc<SubscriptionStats>,
+    cancellation: CancellationToken,
+}
+
+impl Member {
+    fn is_active(&self) -> bool {
+        !self.cancellation.is_cancelled() && !self.queue.is_closed()
+    }
+}
+
+/// Subscribers sharing a subject; each request goes to one of them in turn
+#[derive(Default)]
+struct QueueGroup {
+    members: Vec<Member>,
+    next: AtomicUsize,
+}
+
+impl QueueGroup {
+    fn has_active(&self) -> bool {
+        self.members.iter().any(Member::is_active)
+    }
+
+    fn active(&self) -> Vec<Member> {
+        self.members.iter().filter(|m| m.is_active()).cloned().collect()
+    }
+
+    /// Next active member in round-robin order
+    fn pick(&self) -> Option<Member> {
+        let active: Vec<&Member> = self.members.iter().filter(|m| m.is_active()).collect();
+        if active.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % active.len();
+        Some(active[index].clone())
+    }
+}
+
+/// In-process implementation of the BodyBus trait
+///
+/// Mirrors `NatsBus` semantics without a server: handlers on the same subject
+/// form a queue group served round-robin, events fan out to every event
+/// subscriber, and each subscriber drains a bounded queue so fast senders
+/// wait instead of piling up unbounded work.
+#[derive(Clone)]
+pub struct LocalBus {
+    config: LocalBusConfig,
+    groups: Arc<RwLock<HashMap<String, QueueGroup>>>,
+    event_subscribers: Arc<RwLock<HashMap<String, Vec<Member>>>>,
+}
+
+impl std::fmt::Debug for LocalBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalBus")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Default for LocalBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalBus {
+    /// Create a local bus with default configuration
+    pub fn new() -> Self {
+        Self::with_config(LocalBusConfig::default())
+    }
+
+    /// Create a local bus with custom configuration
+    pub fn with_config(config: LocalBusConfig) -> Self {
+        Self {
+            config,
+            groups: Arc::new(RwLock::new(HashMap::new())),
+            event_subscribers: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Member of the best matching queue group that should handle the next request on `subject`
+    async fn route(&self, subject: &str) -> Result<Member, BusError> {
+        let groups = self.groups.read().await;
+        let active = groups
+            .iter()
+            .filter(|(_, group)| group.has_active())
+            .map(|(pattern, _)| pattern.as_str());
+        subject::best_match(active, subject)
+            .and_then(|pattern| groups[pattern].pick())
+            .ok_or_else(|| BusError::NotFound(format!("No handler for subject: {}", subject)))
+    }
+
+    /// Every active member of the best matching queue group for `subject`
+    async fn route_all(&self, subject: &str) -> Result<Vec<Member>, BusError> {
+        let groups = self.groups.read().await;
+        let active = groups
+            .iter()
+            .filter(|(_, group)| group.has_active())
+            .map(|(pattern, _)| pattern.as_str());
+        subject::best_match(active, subject)
+            .map(|pattern| groups[pattern].active())
+            .ok_or_else(|| BusError::NotFound(format!("No handler for subject: {}", subject)))
+    }
+
+    /// Route a request to one member of the best matching queue group and await its reply
+    async fn send_request(
+        &self,
+        mut envelope: Envelope,
+        parent: Option<&RequestContext>,
+    ) -> Result<Value, BusError> {
+        let subject = envelope.subject();
+
+        // Never wait longer than the caller has left
+        let request_timeout = parent
+            .and_then(RequestContext::remaining)
+            .map_or(self.config.request_timeout, |remaining| {
+                remaining.min(self.config.request_timeout)
+            });
+
+        let member = self.route(&subject).await?;
+
+        headers::stamp_outbound(&mut envelope, parent, Some(request_timeout));
+        let ctx = RequestContext::for_request(Arc::new(self.clone()), &envelope, parent);
+        // Giving up on the reply, or dropping this future, cancels the handler's context
+        let cancel_handler = ctx.cancellation().clone().drop_guard();
+        let (reply, response) = oneshot::channel();
+        let delivery = Delivery {
+            envelope,
+            ctx,
+            reply: Some(reply),
+        };
+
+        // Waiting for queue space counts against the same deadline as the reply
+        let exchange = tokio::time::timeout(request_timeout, async {
+            member.queue.send(delivery).await.map_err(|_| {
+                BusError::NotFound(format!("Subscriber for {} has unsubscribed", subject))
+            })?;
+            response.await.map_err(|_| {
+                BusError::NotFound(format!("Subscriber for {} unsubscribed before replying", subject))
+            })?
+        });
+        let result = tokio::select! {
+            result = exchange => result.map_err(|_| BusError::Timeout)?,
+            _ = parent_cancelled(parent) => return Err(BusError::Cancelled),
+        };
+        // The worker may have timed the handler out first; its context still needs cancelling
+        if !matches!(result, Err(BusError::Timeout | BusError::Cancelled)) {
+            cancel_handler.disarm();
+        }
+        result
+    }
+
+    /// Start the worker task that drains a new subscriber's queue
+    fn spawn_member(&self, subject: &str, handler: SharedHandler) -> (Subscription, Member) {
+        let subscription = Subscription::new(subject);
+        let (queue, mut deliveries) = mpsc::channel::<Delivery>(self.config.queue_capacity.max(1));
+        let member = Member {
+            id: uuid::Uuid::new_v4().to_string(),
+            queue,
+            stats: subscription.stats(),
+            cancellation: subscription.cancellation(),
+        };
+
+        let stats = subscription.stats();
+        let cancellation = subscription.cancellation();
+        let slots = Arc::new(Semaphore::new(self.config.max_concurrent_handlers.max(1)));
+        tokio::spawn(async move {
+            loop {
+                // Wait for a free handler slot before taking the next message off the queue
+                let permit = tokio::select! {
+                    _ = cancellation.cancelled() => break,
+                    permit = Arc::clone(&slots).acquire_owned() => permit.expect("handler slots never close"),
+                };
+                let delivery = tokio::select! {
+                    _ = cancellation.cancelled() => break,
+                    delivery = deliveries.recv() => match delivery {
+                        Some(delivery) => delivery,
+                        None => break,
+                    },
+                };
+
+                // Skip requests whose caller has already given up
+                if delivery.ctx.is_cancelled()
+                    || delivery.reply.as_ref().is_some_and(oneshot::Sender::is_closed)
+                    || delivery.ctx.stream_sink().is_some_and(|sink| sink.is_closed())
+                {
+                    continue;
+                }
+
+                let handler = Arc::clone(&handler);
+                let stats = Arc::clone(&stats);
+                tokio::spawn(async move {
+                    let Delivery { envelope, ctx, reply } = delivery;
+                    let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+                    let sink = ctx.stream_sink().cloned();
+
+                    // A handler stuck past its deadline must not hold its slot forever
+                    let result = match ctx.remaining() {
+                        Some(remaining) => tokio::time::timeout(remaining, handler.handle(envelope, ctx))
+                            .await
+                            .unwrap_or(Err(BusError::Timeout)),
+                        None => handler.handle(envelope, ctx).await,
+                    };
+                    stats.record(&result);
+                    drop(permit);
+
+                    match (reply, sink) {
+                        (Some(reply), _) => {
+                            let _ = reply.send(result);
+                        }
+                        (None, Some(sink)) => sink.complete(result).await,
+                        (None, None) => {
+                            if let Err(e) = result {
+                                warn!(id = %id, service = %service, verb = %verb, error = %e, "LocalBus: Event handler failed");
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        (subscription, member)
+    }
+}
+
+#[async_trait]
+impl BodyBus for LocalBus {
+    async fn request(&self, envelope: Envelope) -> Result<Value, BusError> {
+        self.send_request(envelope, None).await
+    }
+
+    async fn request_with_context(
+        &self,
+        envelope: Envelope,
+        parent: &RequestContext,
+    ) -> Result<Value, BusError> {
+        self.send_request(envelope, Some(parent)).await
+    }
+
+    async fn subscribe(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError> {
+        subject::validate_pattern(subject)?;
+        let (subscription, member) = self.spawn_member(subject, handler);
+
+        let mut groups = self.groups.write().await;
+        for group in groups.values_mut() {
+            group.members.retain(Member::is_active);
+        }
+        groups.retain(|_, group| !group.members.is_empty());
+        groups.entry(subject.to_string()).or_default().members.push(member);
+
+        info!(subject = %subject, "LocalBus: Subscribed");
+        Ok(subscription)
+    }
+
+    /// Hands the handler a sink wired to the returned stream; only the idle time between items is limited
+    async fn request_stream(&self, mut envelope: Envelope) -> Result<ResponseStream, BusError> {
+        let subject = envelope.subject();
+        let member = self.route(&subject).await?;
+
+        headers::stamp_outbound(&mut envelope, None, None);
+        let (sink, stream) = ResponseStream::channel(&envelope, Some(self.config.request_timeout));
+        let ctx = RequestContext::for_request(Arc::new(self.clone()), &envelope, None).with_stream_sink(sink);
+        let stream = stream.cancel_on_drop(ctx.cancellation().clone());
+        let delivery = Delivery {
+            envelope,
+            ctx,
+            reply: None,
+        };
+        tokio::time::timeout(self.config.request_timeout, member.queue.send(delivery))
+            .await
+            .map_err(|_| BusError::Timeout)?
+            .map_err(|_| BusError::NotFound(format!("Subscriber for {} has unsubscribed", subject)))?;
+        Ok(stream)
+    }
+
+    /// Expects a reply from every active member unless told otherwise
+    async fn request_all(&self, mut envelope: Envelope, options: ScatterOptions) -> Result<Vec<ScatterReply>, BusError> {
+        let subject = envelope.subject();
+        let members = self.route_all(&subject).await?;
+        let expected = options.expected.unwrap_or(members.len());
+        let request_timeout = options.timeout.unwrap_or(self.config.request_timeout);
+        headers::stamp_outbound(&mut envelope, None, Some(request_timeout));
+        mark_scatter(&mut envelope);
+
+        let mut pending: FuturesUnordered<_> = members
+            .into_iter()
+            .map(|member| {
+                let ctx = RequestContext::for_request(Arc::new(self.clone()), &envelope, None);
+                let cancel_handler = ctx.cancellation().clone().drop_guard();
+                let (reply, response) = oneshot::channel();
+                let delivery = Delivery {
+                    envelope: envelope.clone(),
+                    ctx,
+                    reply: Some(reply),
+                };
+                async move {
+                    let result = match member.queue.send(delivery).await {
+                        Ok(()) => response.await.unwrap_or_else(|_| {
+                            Err(BusError::NotFound(format!("Subscriber {} unsubscribed before replying", member.id)))
+                        }),
+                        Err(_) => Err(BusError::NotFound(format!("Subscriber {} has unsubscribed", member.id))),
+                    };
+                    cancel_handler.disarm();
+                    ScatterReply {
+                        responder: member.id,
+                        result,
+                    }
+                }
+            })
+            .collect();
+
+        // Members still working when the gather ends have their contexts cancelled
+        let mut replies = Vec::new();
+        let deadline = tokio::time::sleep(request_timeout);
+        tokio::pin!(deadline);
+        while replies.len() < expected {
+            tokio::select! {
+                _ = &mut deadline => break,
+                reply = pending.next() => match reply {
+                    Some(reply) => replies.push(reply),
+                    None => break,
+                },
+            }
+        }
+        Ok(replies)
+    }
+
+    async fn publish(&self, mut envelope: Envelope) -> Result<(), BusError> {
+        headers::stamp_outbound(&mut envelope, None, None);
+        let subject = envelope.subject();
+        let subscribers: Vec<Member> = self
+            .event_subscribers
+            .read()
+            .await
+            .iter()
+            .filter(|(pattern, _)| subject::matches(pattern, &subject))
+            .flat_map(|(_, members)| members.iter())
+            .filter(|member| member.is_active())
+            .cloned()
+            .collect();
+
+        for member in subscribers {
+            let delivery = Delivery {
+                envelope: envelope.clone(),
+                ctx: RequestContext::for_request(Arc::new(self.clone()), &envelope, None),
+                reply: None,
+            };
+
+            // Backpressure: wait for room, but drop the event rather than block forever
+            if let Err(SendTimeoutError::Timeout(_)) =
+                member.queue.send_timeout(delivery, self.config.request_timeout).await
+            {
+                warn!(subject = %subject, "LocalBus: Event subscriber queue full, dropping event");
+                member.stats.record_undeliverable();
+            }
+        }
+        Ok(())
+    }
+
+    async fn subscribe_events(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError> {
+        subject::validate_pattern(subject)?;
+        let (subscription, member) = self.spawn_member(subject, handler);
+
+        let mut event_subscribers = self.event_subscribers.write().await;
+        for members in event_subscribers.values_mut() {
+            members.retain(Member::is_active);
+        }
+        event_subscribers.retain(|_, members| !members.is_empty());
+        event_subscribers.entry(subject.to_string()).or_default().push(member);
+
+        info!(subject = %subject, "LocalBus: Subscribed to events");
+        Ok(subscription)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_handler, sync_handler};
+    use serde_json::json;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn local_bus_subscription() {
+        let bus = LocalBus::new();
+        let handler = sync_handler(|_env: Envelope| Ok(json!({"test": true})));
+
+        let subscription = bus.subscribe("test.subject", handler).await.unwrap();
+        assert_eq!(subscription.subject(), "test.subject");
+        assert!(subscription.is_active());
+    }
+
+    #[tokio::test]
+    async fn local_bus_request_with_handler() {
+        let bus = LocalBus::new();
+        let handler = sync_handler(|envelope: Envelope| {
+            Ok(json!({"received": envelope.service}))
+        });
+
+        let _action = bus.subscribe("cbs.test.action", handler).await.unwrap();
+
+        let envelope = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+        let result = bus.request(envelope).await.unwrap();
+
+        assert_eq!(result["received"], "test");
+    }
+
+    #[tokio::test]
+    async fn local_bus_request_with_async_handler() {
+        let bus = LocalBus::new();
+        let handler = async_handler(|envelope: Envelope, _ctx| async move {
+            tokio::task::yield_now().await;
+            Ok(json!({"verb": envelope.verb}))
+        });
+
+        let _async_action = bus.subscribe("cbs.test.async_action", handler).await.unwrap();
+
+        let envelope = Envelope::new_request("test", "async_action", "demo/v1/Test", json!({}));
+        let result = bus.request(envelope).await.unwrap();
+
+        assert_eq!(result["verb"], "async_action");
+    }
+
+    #[tokio::test]
+    async fn local_bus_handler_errors_reach_caller() {
+        let bus = LocalBus::new();
+        let _invalid = bus.subscribe("cbs.test.invalid", sync_handler(|_| {
+            Err(BusError::bad_request("Invalid input"))
+        })).await.unwrap();
+
+        let envelope = Envelope::new_request("test", "invalid", "demo/v1/Test", json!({}));
+        match bus.request(envelope).await {
+            Err(BusError::BadRequest { message, .. }) => assert_eq!(message, "Invalid input"),
+            other => panic!("Expected BadRequest, got: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn local_bus_nested_request_propagates_context() {
+        let bus = LocalBus::new();
+
+        let _printer = bus.subscribe("cbs.printer.write", async_handler(|_envelope, ctx: RequestContext| async move {
+            Ok(json!({
+                "trace_id": ctx.trace_id(),
+                "has_deadline": ctx.deadline().is_some(),
+            }))
+        })).await.unwrap();
+
+        // Greeter calls the printer itself through its request context
+        let _greeter = bus.subscribe("cbs.greeter.say_hello", async_handler(|_envelope, ctx: RequestContext| async move {
+            let ctx = ctx.with_timeout(Duration::from_secs(1));
+            let print = Envelope::new_request("printer", "write", "demo/v1/Message", json!({"message": "hi"}));
+            ctx.request(print).await
+        })).await.unwrap();
+
+        let envelope = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+        let root_id = envelope.id.clone();
+        let result = bus.request(envelope).await.unwrap();
+
+        assert_eq!(result["trace_id"], root_id);
+        assert_eq!(result["has_deadline"], true);
+    }
+
+    #[tokio::test]
+    async fn local_bus_propagates_envelope_headers() {
+        let bus = LocalBus::new();
+
+        let _printer = bus.subscribe("cbs.printer.write", sync_handler(|envelope| {
+            Ok(json!({
+                "trace_id": envelope.header(headers::TRACE_ID),
+                "causation_id": envelope.header(headers::CAUSATION_ID),
+                "source_cell": envelope.header(headers::SOURCE_CELL),
+                "tenant": envelope.header("tenant"),
+                "has_deadline": envelope.header(headers::DEADLINE).is_some(),
+                "has_sent_at": envelope.header(headers::SENT_AT).is_some(),
+            }))
+        })).await.unwrap();
+        let _greeter = bus.subscribe("cbs.greeter.say_hello", async_handler(|_envelope, ctx: RequestContext| async move {
+            let print = Envelope::new_request("printer", "write", "demo/v1/Message", json!({}))
+                .with_header(headers::SOURCE_CELL, "greeter").unwrap();
+            ctx.request(print).await
+        })).await.unwrap();
+
+        let envelope = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}))
+            .with_header("tenant", "acme").unwrap();
+        let root_id = envelope.id.clone();
+        let result = bus.request(envelope).await.unwrap();
+
+        assert_eq!(result["trace_id"], root_id);
+        assert_eq!(result["causation_id"], root_id);
+        assert_eq!(result["source_cell"], "greeter");
+        assert_eq!(result["tenant"], "acme");
+        assert_eq!(result["has_deadline"], true);
+        assert_eq!(result["has_sent_at"], true);
+    }
+
+    #[tokio::test]
+    async fn local_bus_context_publish_carries_trace() {
+        let bus = LocalBus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let _listener = bus.subscribe_events("cbs.audit.logged", sync_handler(move |envelope| {
+            tx.send(envelope.header(headers::TRACE_ID).map(str::to_string)).unwrap();
+            Ok(json!({}))
+        })).await.unwrap();
+
+        let ctx = RequestContext::new(Arc::new(bus.clone())).with_metadata(headers::TRACE_ID, "trace-9").unwrap();
+        ctx.publish(Envelope::new_request("audit", "logged", "demo/v1/Test", json!({}))).await.unwrap();
+
+        assert_eq!(rx.recv().await.unwrap().as_deref(), Some("trace-9"));
+    }
+
+    #[tokio::test]
+    async fn local_bus_nested_request_respects_deadline() {
+        let bus = LocalBus::new();
+
+        let _slow = bus.subscribe("cbs.slow.work", async_handler(|_envelope, _ctx| async move {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(json!({}))
+        })).await.unwrap();
+
+        let ctx = RequestContext::new(Arc::new(bus.clone()))
+            .with_timeout(Duration::from_millis(20));
+        let envelope = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+
+        assert!(matches!(ctx.request(envelope).await, Err(BusError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn local_bus_request_timeout_from_config() {
+        let bus = LocalBus::with_config(LocalBusConfig {
+            request_timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+        let _slow = bus.subscribe("cbs.slow.work", async_handler(|_envelope, _ctx| async move {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(json!({}))
+        })).await.unwrap();
+
+        let start = Instant::now();
+        let envelope = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+        assert!(matches!(bus.request(envelope).await, Err(BusError::Timeout)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn local_bus_round_robins_queue_group() {
+        let bus = LocalBus::new();
+        let mut subscriptions = Vec::new();
+        for member in 0..2 {
+            subscriptions.push(bus.subscribe("cbs.loadtest.work", sync_handler(move |_| {
+                Ok(json!({"member": member}))
+            })).await.unwrap());
+        }
+
+        let mut members = Vec::new();
+        for _ in 0..4 {
+            let envelope = Envelope::new_request("loadtest", "work", "demo/v1/Test", json!({}));
+            members.push(bus.request(envelope).await.unwrap()["member"].as_u64().unwrap());
+        }
+
+        // Duplicates join the group instead of replacing each other
+        assert_ne!(members[0], members[1]);
+        assert_eq!(members[0], members[2]);
+        assert_eq!(members[1], members[3]);
+        assert!(subscriptions.iter().all(|s| s.delivered() == 2));
+    }
+
+    #[tokio::test]
+    async fn local_bus_skips_unsubscribed_members() {
+        let bus = LocalBus::new();
+        let first = bus.subscribe("cbs.test.action", sync_handler(|_| Ok(json!({"member": 1})))).await.unwrap();
+        let _second = bus.subscribe("cbs.test.action", sync_handler(|_| Ok(json!({"member": 2})))).await.unwrap();
+
+        first.unsubscribe();
+        for _ in 0..3 {
+            let envelope = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+            assert_eq!(bus.request(envelope).await.unwrap()["member"], 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn local_bus_unsubscribe_removes_handler() {
+        let bus = LocalBus::new();
+        let subscription = bus.subscribe("cbs.test.action", sync_handler(|_| Ok(json!({})))).await.unwrap();
+
+        let envelope = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+        assert!(bus.request(envelope.clone()).await.is_ok());
+
+        subscription.unsubscribe();
+        assert!(matches!(bus.request(envelope).await, Err(BusError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn local_bus_dropped_subscription_stops_delivery() {
+        let bus = LocalBus::new();
+        {
+            let _scoped = bus.subscribe("cbs.test.action", sync_handler(|_| Ok(json!({})))).await.unwrap();
+        }
+        bus.subscribe("cbs.test.detached", sync_handler(|_| Ok(json!({})))).await.unwrap().detach();
+
+        let dropped = Envelope::new_request("test", "action", "demo/v1/Test", json!({}));
+        assert!(matches!(bus.request(dropped).await, Err(BusError::NotFound(_))));
+
+        let detached = Envelope::new_request("test", "detached", "demo/v1/Test", json!({}));
+        assert!(bus.request(detached).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn local_bus_subscription_counts_deliveries() {
+        let bus = LocalBus::new();
+        let subscription = bus.subscribe("cbs.test.check", sync_handler(|envelope| {
+            match envelope.payload.as_ref().and_then(|p| p["ok"].as_bool()) {
+                Some(true) => Ok(json!({})),
+                _ => Err(BusError::bad_request("not ok")),
+            }
+        })).await.unwrap();
+
+        for ok in [true, true, false] {
+            let envelope = Envelope::new_request("test", "check", "demo/v1/Test", json!({"ok": ok}));
+            let _ = bus.request(envelope).await;
+        }
+
+        assert_eq!(subscription.delivered(), 3);
+        assert_eq!(subscription.failed(), 1);
+    }
+
+    #[tokio::test]
+    async fn local_bus_publish_fans_out_to_every_event_subscriber() {
+        let bus = LocalBus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut subscriptions = Vec::new();
+        for subscriber in 0..2 {
+            let tx = tx.clone();
+            subscriptions.push(bus.subscribe_events("cbs.navigation.screen_changed", sync_handler(move |envelope| {
+                tx.send((subscriber, envelope.payload.unwrap())).unwrap();
+                Ok(json!({}))
+            })).await.unwrap());
+        }
+
+        // A request handler on the same service is unaffected by the events
+        let _set_screen = bus.subscribe("cbs.navigation.set_screen", sync_handler(|_| Ok(json!({"ok": true})))).await.unwrap();
+
+        let event = Envelope::new_request("navigation", "screen_changed", "navigation/v1/Screen", json!({"screen": "home"}));
+        bus.publish(event).await.unwrap();
+
+        let mut received = [rx.recv().await.unwrap(), rx.recv().await.unwrap()];
+        received.sort_by_key(|(subscriber, _)| *subscriber);
+        assert_eq!(received[0], (0, json!({"screen": "home"})));
+        assert_eq!(received[1], (1, json!({"screen": "home"})));
+
+        let request = Envelope::new_request("navigation", "set_screen", "navigation/v1/Screen", json!({}));
+        assert_eq!(bus.request(request).await.unwrap()["ok"], true);
+    }
+
+    #[tokio::test]
+    async fn local_bus_publish_without_subscribers_is_ok() {
+        let bus = LocalBus::new();
+        let event = Envelope::new_request("nobody", "listens", "demo/v1/Test", json!({}));
+        assert!(bus.publish(event).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn local_bus_full_queue_applies_backpressure() {
+        let bus = LocalBus::with_config(LocalBusConfig {
+            request_timeout: Duration::from_millis(50),
+            queue_capacity: 1,
+            max_concurrent_handlers: 1,
+        });
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let handler_gate = Arc::clone(&gate);
+        let subscription = bus.subscribe_events("cbs.busy.tick", async_handler(move |_envelope, _ctx| {
+            let gate = Arc::clone(&handler_gate);
+            async move {
+                let _permit = gate.acquire().await.unwrap();
+                Ok(json!({}))
+            }
+        })).await.unwrap();
+
+        // First event occupies the only handler slot, second fills the queue
+        for _ in 0..2 {
+            bus.publish(Envelope::new_request("busy", "tick", "demo/v1/Test", json!({}))).await.unwrap();
+        }
+        tokio::task::yield_now().await;
+
+        let start = Instant::now();
+        bus.publish(Envelope::new_request("busy", "tick", "demo/v1/Test", json!({}))).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(subscription.failed(), 1);
+
+        gate.add_permits(2);
+    }
+
+    #[tokio::test]
+    async fn local_bus_wildcard_request_routing() {
+        let bus = LocalBus::new();
+
+        let _health = bus.subscribe("cbs.*.health", sync_handler(|envelope| Ok(json!({"health": envelope.service})))).await.unwrap();
+        let _catch_all = bus.subscribe("cbs.greeter.>", sync_handler(|_| Ok(json!({"handler": "greeter_catch_all"})))).await.unwrap();
+        let _exact = bus.subscribe("cbs.greeter.say_hello", sync_handler(|_| Ok(json!({"handler": "exact"})))).await.unwrap();
+
+        let exact = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+        assert_eq!(bus.request(exact).await.unwrap()["handler"], "exact");
+
+        let catch_all = Envelope::new_request("greeter", "wave", "demo/v1/Name", json!({}));
+        assert_eq!(bus.request(catch_all).await.unwrap()["handler"], "greeter_catch_all");
+
+        let health = Envelope::new_request("printer", "health", "demo/v1/Void", json!({}));
+        assert_eq!(bus.request(health).await.unwrap()["health"], "printer");
+
+        let unmatched = Envelope::new_request("printer", "write", "demo/v1/Message", json!({}));
+        assert!(matches!(bus.request(unmatched).await, Err(BusError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn local_bus_wildcard_event_monitoring() {
+        let bus = LocalBus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let _monitor = bus.subscribe_events("cbs.>", sync_handler(move |envelope| {
+            tx.send(envelope.subject()).unwrap();
+            Ok(json!({}))
+        })).await.unwrap();
+
+        let event = Envelope::new_request("flow_text", "content_ready", "flow/v1/Content", json!({}));
+        bus.publish(event).await.unwrap();
+
+        assert_eq!(rx.recv().await.unwrap(), "cbs.flow_text.content_ready");
+    }
+
+    #[tokio::test]
+    async fn local_bus_rejects_invalid_patterns() {
+        let bus = LocalBus::new();
+        let result = bus.subscribe("cbs.>.health", sync_handler(|_| Ok(json!({})))).await;
+        assert!(matches!(result, Err(BusError::BadRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn local_bus_request_all_gathers_every_member() {
+        let bus = LocalBus::new();
+        let mut subscriptions = Vec::new();
+        for member in 0..3 {
+            let handler = sync_handler(move |_| match member {
+                2 => Err(BusError::Unavailable("draining".to_string())),
+                _ => Ok(json!({"member": member})),
+            });
+            subscriptions.push(bus.subscribe("cbs.greeter.health", handler).await.unwrap());
+        }
+
+        let envelope = Envelope::new_request("greeter", "health", "demo/v1/Void", json!({}));
+        let replies = bus.request_all(envelope, ScatterOptions::new()).await.unwrap();
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies.iter().filter(|reply| reply.is_ok()).count(), 2);
+        assert!(replies.iter().any(|reply| matches!(reply.result, Err(BusError::Unavailable(_)))));
+
+        let mut responders: Vec<_> = replies.iter().map(|reply| reply.responder.clone()).collect();
+        responders.sort();
+        responders.dedup();
+        assert_eq!(responders.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn local_bus_request_all_stops_at_timeout_or_expected_count() {
+        let bus = LocalBus::new();
+        let _fast = bus.subscribe("cbs.greeter.stats", sync_handler(|_| Ok(json!({"fast": true})))).await.unwrap();
+        let _slow = bus.subscribe("cbs.greeter.stats", async_handler(|_envelope, _ctx| async move {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(json!({"fast": false}))
+        })).await.unwrap();
+
+        let start = Instant::now();
+        let envelope = Envelope::new_request("greeter", "stats", "demo/v1/Void", json!({}));
+        let options = ScatterOptions::new().with_timeout(Duration::from_millis(50));
+        let replies = bus.request_all(envelope, options).await.unwrap();
+        // The slow member is either left out or timed out by its own deadline
+        assert!(replies.iter().any(|reply| reply.result.as_ref().is_ok_and(|value| value["fast"] == true)));
+        assert!(replies.iter().all(|reply| !matches!(reply.result, Ok(ref value) if value["fast"] == false)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let envelope = Envelope::new_request("greeter", "stats", "demo/v1/Void", json!({}));
+        let replies = bus.request_all(envelope, ScatterOptions::new().expecting(1)).await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let envelope = Envelope::new_request("nobody", "stats", "demo/v1/Void", json!({}));
+        let result = bus.request_all(envelope, ScatterOptions::new()).await;
+        assert!(matches!(result, Err(BusError::NotFound(_))));
+    }
+
+    /// Handler that hands its cancellation token to the test, then waits to be cancelled
+    fn cancellation_probe(tx: tokio::sync::mpsc::UnboundedSender<CancellationToken>) -> SharedHandler {
+        async_handler(move |_envelope, ctx: RequestContext| {
+            let tx = tx.clone();
+            async move {
+                tx.send(ctx.cancellation().clone()).unwrap();
+                ctx.cancellation().cancelled().await;
+                Err(BusError::Cancelled)
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn local_bus_timeout_cancels_handler() {
+        let bus = LocalBus::with_config(LocalBusConfig {
+            request_timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let _slow = bus.subscribe("cbs.slow.work", cancellation_probe(tx)).await.unwrap();
+
+        let envelope = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+        assert!(matches!(bus.request(envelope).await, Err(BusError::Timeout)));
+        assert!(rx.recv().await.unwrap().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn local_bus_dropped_request_cancels_handler() {
+        let bus = LocalBus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let _slow = bus.subscribe("cbs.slow.work", cancellation_probe(tx)).await.unwrap();
+
+        let envelope = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+        let request = bus.request(envelope);
+        let token = tokio::select! {
+            _ = request => unreachable!("the handler never replies"),
+            token = rx.recv() => token.unwrap(),
+        };
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn local_bus_cancelled_parent_cancels_nested_request() {
+        let bus = LocalBus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let _slow = bus.subscribe("cbs.slow.work", cancellation_probe(tx)).await.unwrap();
+
+        let parent = RequestContext::new(Arc::new(bus.clone()));
+        let canceller = parent.cancellation().clone();
+        tokio::spawn(async move {
+            let token = rx.recv().await.unwrap();
+            canceller.cancel();
+            assert!(token.is_cancelled());
+        });
+
+        let envelope = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+        assert!(matches!(parent.request(envelope).await, Err(BusError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn local_bus_dropped_stream_cancels_handler() {
+        let bus = LocalBus::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let _slow = bus.subscribe("cbs.slow.work", cancellation_probe(tx)).await.unwrap();
+
+        let envelope = Envelope::new_request("slow", "work", "demo/v1/Test", json!({}));
+        let stream = bus.request_stream(envelope).await.unwrap();
+        let token = rx.recv().await.unwrap();
+        assert!(!token.is_cancelled());
+        drop(stream);
+        assert!(token.is_cancelled());
+    }
+}
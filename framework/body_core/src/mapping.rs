@@ -0,0 +1,420 @@
+use serde_json::{Map, Value};
+use std::fmt;
+use thiserror::Error;
+
+/// Errors compiling or evaluating a payload mapping
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MappingError {
+    #[error("Invalid expression {expression:?}: {reason}")]
+    Syntax { expression: String, reason: String },
+    #[error("{path} did not match any value")]
+    NoMatch { path: String },
+}
+
+impl MappingError {
+    fn syntax(expression: &str, reason: impl Into<String>) -> Self {
+        Self::Syntax {
+            expression: expression.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// One step of a path: an object key or an array index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(String),
+    /// Negative indexes count from the end of the array
+    Index(i64),
+}
+
+/// A JSONPath-style selector: `$`, then `.key`, `['key']` or `[index]` steps
+///
+/// `$.steps.prompt.name`, `$.input.items[0]`, `$.input.items[-1]` and
+/// `$['odd key']` are all valid. Wildcards and filters are not supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    source: String,
+    segments: Vec<PathSegment>,
+}
+
+impl JsonPath {
+    pub fn parse(source: &str) -> Result<Self, MappingError> {
+        let source = source.trim();
+        let mut chars = source.chars().peekable();
+        if chars.next() != Some('$') {
+            return Err(MappingError::syntax(source, "paths start with $"));
+        }
+
+        let mut segments = Vec::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let mut key = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_alphanumeric() || c == '_' || c == '-') {
+                            break;
+                        }
+                        key.push(c);
+                        chars.next();
+                    }
+                    if key.is_empty() {
+                        return Err(MappingError::syntax(source, "expected a key after ."));
+                    }
+                    segments.push(PathSegment::Key(key));
+                }
+                '[' => {
+                    let mut inner = String::new();
+                    let quote = chars.next_if(|c| matches!(c, '\'' | '"'));
+                    loop {
+                        match chars.next() {
+                            None => return Err(MappingError::syntax(source, "unclosed [")),
+                            Some('\\') if quote.is_some() => match chars.next() {
+                                Some(c) => inner.push(c),
+                                None => return Err(MappingError::syntax(source, "unclosed [")),
+                            },
+                            Some(c) if quote == Some(c) => {
+                                if chars.next() != Some(']') {
+                                    return Err(MappingError::syntax(source, "expected ] after quoted key"));
+                                }
+                                break;
+                            }
+                            Some(']') if quote.is_none() => break,
+                            Some(c) => inner.push(c),
+                        }
+                    }
+                    if quote.is_some() {
+                        segments.push(PathSegment::Key(inner));
+                    } else {
+                        let index = inner
+                            .trim()
+                            .parse()
+                            .map_err(|_| MappingError::syntax(source, format!("invalid index [{}]", inner)))?;
+                        segments.push(PathSegment::Index(index));
+                    }
+                }
+                other => return Err(MappingError::syntax(source, format!("unexpected {:?}", other))),
+            }
+        }
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// The value the path points at in `root`, if any
+    pub fn select<'a>(&self, root: &'a Value) -> Option<&'a Value> {
+        self.segments.iter().try_fold(root, |value, segment| match segment {
+            PathSegment::Key(key) => value.get(key),
+            PathSegment::Index(index) => {
+                let items = value.as_array()?;
+                let index = if *index < 0 { items.len() as i64 + index } else { *index };
+                items.get(usize::try_from(index).ok()?)
+            }
+        })
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// `path` or `path ?? default`, where the default is a JSON literal
+#[derive(Debug, Clone, PartialEq)]
+struct Expression {
+    path: JsonPath,
+    default: Option<Value>,
+}
+
+impl Expression {
+    fn parse(source: &str) -> Result<Self, MappingError> {
+        let (path, default) = match split_default(source) {
+            Some((path, default)) => {
+                let default = serde_json::from_str(default.trim())
+                    .map_err(|e| MappingError::syntax(source, format!("default is not a JSON literal: {}", e)))?;
+                (path, Some(default))
+            }
+            None => (source, None),
+        };
+        Ok(Self {
+            path: JsonPath::parse(path)?,
+            default,
+        })
+    }
+
+    fn evaluate(&self, root: &Value) -> Result<Value, MappingError> {
+        match (self.path.select(root), &self.default) {
+            (Some(value), _) => Ok(value.clone()),
+            (None, Some(default)) => Ok(default.clone()),
+            (None, None) => Err(MappingError::NoMatch {
+                path: self.path.to_string(),
+            }),
+        }
+    }
+}
+
+/// Split `path ?? default` at the first `??` outside quotes
+fn split_default(source: &str) -> Option<(&str, &str)> {
+    unquoted(source)
+        .find(|&(i, c)| c == '?' && source[i..].starts_with("??"))
+        .map(|(i, _)| (&source[..i], &source[i + 2..]))
+}
+
+/// Characters of `source` outside quoted strings, with their offsets
+///
+/// Inside quotes a backslash escapes the next character, as in JSON strings,
+/// so `"say \"hi\""` is one string.
+fn unquoted(source: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    let mut escaped = false;
+    source.char_indices().filter(move |&(_, c)| {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if matches!(c, '\'' | '"') => quote = Some(c),
+            None => return true,
+        }
+        false
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TextPart {
+    Text(String),
+    Expression(Expression),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Constant(Value),
+    /// A string that is exactly one `${...}`; keeps the selected value's type
+    Select(Expression),
+    /// A string mixing text and `${...}`; always renders to a string
+    Text(Vec<TextPart>),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+/// A payload template: JSON whose strings may embed `${path}` expressions
+///
+/// A string that is a single `${path}` is replaced by the selected value,
+/// whatever its type; `${path}` inside longer text is interpolated, with
+/// non-string values written as JSON. `${path ?? literal}` falls back to a
+/// JSON literal when the path matches nothing, and `$${` writes a literal
+/// `${`. Everything else is a constant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    root: Node,
+}
+
+impl Template {
+    pub fn compile(template: &Value) -> Result<Self, MappingError> {
+        Ok(Self {
+            root: compile_node(template)?,
+        })
+    }
+
+    /// Every path the template selects, in order
+    pub fn paths(&self) -> Vec<&JsonPath> {
+        let mut paths = Vec::new();
+        collect_paths(&self.root, &mut paths);
+        paths
+    }
+
+    /// Build a value from the template, selecting paths from `root`
+    pub fn render(&self, root: &Value) -> Result<Value, MappingError> {
+        render_node(&self.root, root)
+    }
+}
+
+fn compile_node(template: &Value) -> Result<Node, MappingError> {
+    Ok(match template {
+        Value::String(text) => compile_text(text)?,
+        Value::Array(items) => Node::Array(items.iter().map(compile_node).collect::<Result<_, _>>()?),
+        Value::Object(fields) => Node::Object(
+            fields
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), compile_node(value)?)))
+                .collect::<Result<_, MappingError>>()?,
+        ),
+        constant => Node::Constant(constant.clone()),
+    })
+}
+
+fn compile_text(text: &str) -> Result<Node, MappingError> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            literal.push_str(&rest[..start - 1]);
+            literal.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        literal.push_str(&rest[..start]);
+        let end = expression_end(&rest[start + 2..]).ok_or_else(|| MappingError::syntax(text, "unclosed ${"))?;
+        if !literal.is_empty() {
+            parts.push(TextPart::Text(std::mem::take(&mut literal)));
+        }
+        parts.push(TextPart::Expression(Expression::parse(&rest[start + 2..start + 2 + end])?));
+        rest = &rest[start + 3 + end..];
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        parts.push(TextPart::Text(literal));
+    }
+
+    Ok(match parts.as_slice() {
+        [] => Node::Constant(Value::String(String::new())),
+        [TextPart::Text(text)] => Node::Constant(Value::String(text.clone())),
+        [TextPart::Expression(expression)] => Node::Select(expression.clone()),
+        _ => Node::Text(parts),
+    })
+}
+
+/// Offset of the `}` closing an expression, skipping quoted text and nested braces
+fn expression_end(source: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in unquoted(source) {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn collect_paths<'a>(node: &'a Node, paths: &mut Vec<&'a JsonPath>) {
+    match node {
+        Node::Constant(_) => {}
+        Node::Select(expression) => paths.push(&expression.path),
+        Node::Text(parts) => paths.extend(parts.iter().filter_map(|part| match part {
+            TextPart::Expression(expression) => Some(&expression.path),
+            TextPart::Text(_) => None,
+        })),
+        Node::Array(items) => items.iter().for_each(|item| collect_paths(item, paths)),
+        Node::Object(fields) => fields.iter().for_each(|(_, value)| collect_paths(value, paths)),
+    }
+}
+
+fn render_node(node: &Node, root: &Value) -> Result<Value, MappingError> {
+    Ok(match node {
+        Node::Constant(value) => value.clone(),
+        Node::Select(expression) => expression.evaluate(root)?,
+        Node::Text(parts) => {
+            let mut text = String::new();
+            for part in parts {
+                match part {
+                    TextPart::Text(literal) => text.push_str(literal),
+                    TextPart::Expression(expression) => match expression.evaluate(root)? {
+                        Value::String(value) => text.push_str(&value),
+                        value => text.push_str(&value.to_string()),
+                    },
+                }
+            }
+            Value::String(text)
+        }
+        Node::Array(items) => Value::Array(items.iter().map(|item| render_node(item, root)).collect::<Result<_, _>>()?),
+        Node::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), render_node(value, root)?)))
+                .collect::<Result<Map<_, _>, MappingError>>()?,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: Value, root: &Value) -> Result<Value, MappingError> {
+        Template::compile(&template)?.render(root)
+    }
+
+    #[test]
+    fn paths_select_keys_and_indexes() {
+        let root = json!({"input": {"items": [1, 2, 3], "odd key": true}});
+        let select = |path: &str| JsonPath::parse(path).unwrap().select(&root).cloned();
+        assert_eq!(select("$.input.items[0]"), Some(json!(1)));
+        assert_eq!(select("$.input.items[-1]"), Some(json!(3)));
+        assert_eq!(select("$['input']['odd key']"), Some(json!(true)));
+        assert_eq!(select("$.input.items[3]"), None);
+        assert_eq!(select("$"), Some(root.clone()));
+    }
+
+    #[test]
+    fn invalid_paths_are_rejected() {
+        for path in ["input.name", "$.", "$.items[x]", "$.items[0", "$.a b"] {
+            assert!(matches!(JsonPath::parse(path), Err(MappingError::Syntax { .. })), "{}", path);
+        }
+    }
+
+    #[test]
+    fn templates_select_interpolate_and_keep_constants() {
+        let root = json!({"steps": {"prompt": {"name": "Ada", "length": 3}}});
+        let payload = render(
+            json!({
+                "name": "${$.steps.prompt.name}",
+                "length": "${$.steps.prompt.length}",
+                "summary": "${$.steps.prompt.name} has ${$.steps.prompt.length} letters",
+                "tags": ["greeting", "${$.steps.prompt.name}"],
+                "loud": false,
+                "price": "$${5}"
+            }),
+            &root,
+        )
+        .unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "name": "Ada",
+                "length": 3,
+                "summary": "Ada has 3 letters",
+                "tags": ["greeting", "Ada"],
+                "loud": false,
+                "price": "${5}"
+            })
+        );
+    }
+
+    #[test]
+    fn defaults_fill_missing_values() {
+        let root = json!({"input": {}});
+        assert_eq!(render(json!("${$.input.name ?? \"World\"}"), &root).unwrap(), json!("World"));
+        assert_eq!(render(json!("${$.input.tags ?? {\"a\": []}}"), &root).unwrap(), json!({"a": []}));
+        assert_eq!(
+            render(json!("${$.input.name}"), &root).unwrap_err().to_string(),
+            "$.input.name did not match any value"
+        );
+        assert!(matches!(Template::compile(&json!("${$.input ?? World}")), Err(MappingError::Syntax { .. })));
+        assert!(matches!(Template::compile(&json!("${$.input")), Err(MappingError::Syntax { .. })));
+    }
+
+    #[test]
+    fn quoted_text_may_escape_quotes() {
+        let root = json!({"input": {"it's": 1}});
+        assert_eq!(render(json!("${$.input.name ?? \"say \\\"hi\\\"\"}"), &root).unwrap(), json!("say \"hi\""));
+        assert_eq!(render(json!("<${$.input.name ?? \"\\\"}\\\" ?? \"}>"), &root).unwrap(), json!("<\"}\" ?? >"));
+        assert_eq!(render(json!("${$['input']['it\\'s']}"), &root).unwrap(), json!(1));
+    }
+
+    #[test]
+    fn paths_are_listed() {
+        let template = Template::compile(&json!({"a": "${$.input.a}", "b": ["x ${$.previous.b} y"]})).unwrap();
+        let paths: Vec<String> = template.paths().iter().map(|path| path.to_string()).collect();
+        assert_eq!(paths, ["$.input.a", "$.previous.b"]);
+    }
+}
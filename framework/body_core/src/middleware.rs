@@ -0,0 +1,725 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::{
+    BodyBus, BusError, Envelope, Handler, RequestContext, ResponseStream, ScatterOptions, ScatterReply, SharedHandler,
+    Subscription,
+};
+
+type Stack = Arc<[Arc<dyn Middleware>]>;
+
+/// Interceptor around outbound requests and events and inbound handler dispatch
+///
+/// Every hook defaults to passing straight through, so a middleware only
+/// implements the sides it cares about. Call `next.run(..)` to continue down the
+/// stack, or return early to short-circuit it.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Outbound request; `parent` is set for nested requests made by a handler
+    async fn request(
+        &self,
+        envelope: Envelope,
+        parent: Option<&RequestContext>,
+        next: RequestNext,
+    ) -> Result<Value, BusError> {
+        next.run(envelope, parent).await
+    }
+
+    /// Outbound request for a streamed reply; runs once, when the stream opens
+    async fn request_stream(&self, envelope: Envelope, next: StreamNext) -> Result<ResponseStream, BusError> {
+        next.run(envelope).await
+    }
+
+    /// Outbound request sent to every instance with `request_all`
+    async fn request_all(
+        &self,
+        envelope: Envelope,
+        options: ScatterOptions,
+        next: ScatterNext,
+    ) -> Result<Vec<ScatterReply>, BusError> {
+        next.run(envelope, options).await
+    }
+
+    /// Outbound event
+    async fn publish(&self, envelope: Envelope, next: PublishNext) -> Result<(), BusError> {
+        next.run(envelope).await
+    }
+
+    /// Inbound request or event, before the subscribed handler runs
+    async fn dispatch(
+        &self,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        next.run(envelope, ctx).await
+    }
+}
+
+/// Remainder of the stack for an outbound request
+///
+/// Cloning it lets a middleware such as retry run the rest of the stack again.
+#[derive(Clone)]
+pub struct RequestNext {
+    stack: Stack,
+    index: usize,
+    bus: Arc<dyn BodyBus>,
+}
+
+impl RequestNext {
+    /// Hand the request to the next middleware, or to the bus
+    pub async fn run(self, envelope: Envelope, parent: Option<&RequestContext>) -> Result<Value, BusError> {
+        match self.stack.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Self {
+                    index: self.index + 1,
+                    ..self
+                };
+                middleware.request(envelope, parent, next).await
+            }
+            None => match parent {
+                Some(parent) => self.bus.request_with_context(envelope, parent).await,
+                None => self.bus.request(envelope).await,
+            },
+        }
+    }
+}
+
+/// Remainder of the stack for an outbound streamed request
+pub struct StreamNext {
+    stack: Stack,
+    index: usize,
+    bus: Arc<dyn BodyBus>,
+}
+
+impl StreamNext {
+    /// Hand the request to the next middleware, or to the bus
+    pub async fn run(self, envelope: Envelope) -> Result<ResponseStream, BusError> {
+        match self.stack.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Self {
+                    index: self.index + 1,
+                    ..self
+                };
+                middleware.request_stream(envelope, next).await
+            }
+            None => self.bus.request_stream(envelope).await,
+        }
+    }
+}
+
+/// Remainder of the stack for an outbound scatter request
+pub struct ScatterNext {
+    stack: Stack,
+    index: usize,
+    bus: Arc<dyn BodyBus>,
+}
+
+impl ScatterNext {
+    /// Hand the request to the next middleware, or to the bus
+    pub async fn run(self, envelope: Envelope, options: ScatterOptions) -> Result<Vec<ScatterReply>, BusError> {
+        match self.stack.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Self {
+                    index: self.index + 1,
+                    ..self
+                };
+                middleware.request_all(envelope, options, next).await
+            }
+            None => self.bus.request_all(envelope, options).await,
+        }
+    }
+}
+
+/// Remainder of the stack for an outbound event
+pub struct PublishNext {
+    stack: Stack,
+    index: usize,
+    bus: Arc<dyn BodyBus>,
+}
+
+impl PublishNext {
+    /// Hand the event to the next middleware, or to the bus
+    pub async fn run(self, envelope: Envelope) -> Result<(), BusError> {
+        match self.stack.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Self {
+                    index: self.index + 1,
+                    ..self
+                };
+                middleware.publish(envelope, next).await
+            }
+            None => self.bus.publish(envelope).await,
+        }
+    }
+}
+
+/// Remainder of the stack for an inbound message
+pub struct DispatchNext {
+    stack: Stack,
+    index: usize,
+    handler: SharedHandler,
+}
+
+impl DispatchNext {
+    /// Hand the message to the next middleware, or to the handler
+    pub async fn run(self, envelope: Envelope, ctx: RequestContext) -> Result<Value, BusError> {
+        match self.stack.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Self {
+                    index: self.index + 1,
+                    ..self
+                };
+                middleware.dispatch(envelope, ctx, next).await
+            }
+            None => self.handler.handle(envelope, ctx).await,
+        }
+    }
+}
+
+/// Any `BodyBus` wrapped in a middleware stack
+///
+/// Layers run in the order they were added, outermost first, on both the
+/// outbound and inbound side. Handlers subscribed through this bus see a
+/// context whose nested requests also pass through the stack.
+#[derive(Clone)]
+pub struct MiddlewareBus {
+    inner: Arc<dyn BodyBus>,
+    stack: Stack,
+}
+
+impl MiddlewareBus {
+    /// Wrap `inner` with an empty stack
+    pub fn new(inner: Arc<dyn BodyBus>) -> Self {
+        Self {
+            inner,
+            stack: Arc::from(Vec::new()),
+        }
+    }
+
+    /// Add a middleware inside the ones already added
+    pub fn layer<M: Middleware + 'static>(self, middleware: M) -> Self {
+        self.layer_shared(Arc::new(middleware))
+    }
+
+    /// `layer` for a middleware that is also kept elsewhere, e.g. to read its metrics
+    pub fn layer_shared(self, middleware: Arc<dyn Middleware>) -> Self {
+        let mut stack = self.stack.to_vec();
+        stack.push(middleware);
+        Self {
+            inner: self.inner,
+            stack: Arc::from(stack),
+        }
+    }
+
+    /// Bus the stack delegates to
+    pub fn inner(&self) -> &Arc<dyn BodyBus> {
+        &self.inner
+    }
+
+    fn request_next(&self) -> RequestNext {
+        RequestNext {
+            stack: Arc::clone(&self.stack),
+            index: 0,
+            bus: Arc::clone(&self.inner),
+        }
+    }
+
+    fn wrap(&self, handler: SharedHandler) -> SharedHandler {
+        Arc::new(MiddlewareHandler {
+            stack: Arc::clone(&self.stack),
+            inner: handler,
+        })
+    }
+}
+
+impl std::fmt::Debug for MiddlewareBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MiddlewareBus")
+            .field("layers", &self.stack.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl BodyBus for MiddlewareBus {
+    async fn request(&self, envelope: Envelope) -> Result<Value, BusError> {
+        self.request_next().run(envelope, None).await
+    }
+
+    async fn request_with_context(
+        &self,
+        envelope: Envelope,
+        parent: &RequestContext,
+    ) -> Result<Value, BusError> {
+        self.request_next().run(envelope, Some(parent)).await
+    }
+
+    async fn subscribe(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError> {
+        self.inner.subscribe(subject, self.wrap(handler)).await
+    }
+
+    async fn request_stream(&self, envelope: Envelope) -> Result<ResponseStream, BusError> {
+        StreamNext {
+            stack: Arc::clone(&self.stack),
+            index: 0,
+            bus: Arc::clone(&self.inner),
+        }
+        .run(envelope)
+        .await
+    }
+
+    async fn request_all(&self, envelope: Envelope, options: ScatterOptions) -> Result<Vec<ScatterReply>, BusError> {
+        ScatterNext {
+            stack: Arc::clone(&self.stack),
+            index: 0,
+            bus: Arc::clone(&self.inner),
+        }
+        .run(envelope, options)
+        .await
+    }
+
+    async fn publish(&self, envelope: Envelope) -> Result<(), BusError> {
+        PublishNext {
+            stack: Arc::clone(&self.stack),
+            index: 0,
+            bus: Arc::clone(&self.inner),
+        }
+        .run(envelope)
+        .await
+    }
+
+    async fn subscribe_events(&self, subject: &str, handler: SharedHandler) -> Result<Subscription, BusError> {
+        self.inner.subscribe_events(subject, self.wrap(handler)).await
+    }
+}
+
+/// Handler that runs the inbound side of the stack before the subscribed handler
+struct MiddlewareHandler {
+    stack: Stack,
+    inner: SharedHandler,
+}
+
+#[async_trait]
+impl Handler for MiddlewareHandler {
+    async fn handle(&self, envelope: Envelope, ctx: RequestContext) -> Result<Value, BusError> {
+        let bus = MiddlewareBus {
+            inner: Arc::clone(ctx.bus()),
+            stack: Arc::clone(&self.stack),
+        };
+        let next = DispatchNext {
+            stack: Arc::clone(&self.stack),
+            index: 0,
+            handler: Arc::clone(&self.inner),
+        };
+        next.run(envelope, ctx.with_bus(Arc::new(bus))).await
+    }
+}
+
+/// Logs every message with `id`, `service`, `verb`, outcome and duration
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoggingMiddleware;
+
+#[async_trait]
+impl Middleware for LoggingMiddleware {
+    async fn request(
+        &self,
+        envelope: Envelope,
+        parent: Option<&RequestContext>,
+        next: RequestNext,
+    ) -> Result<Value, BusError> {
+        let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+        let started = Instant::now();
+        let result = next.run(envelope, parent).await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(_) => info!(id = %id, service = %service, verb = %verb, elapsed_ms, "Request completed"),
+            Err(e) => warn!(id = %id, service = %service, verb = %verb, elapsed_ms, error = %e, "Request failed"),
+        }
+        result
+    }
+
+    async fn request_stream(&self, envelope: Envelope, next: StreamNext) -> Result<ResponseStream, BusError> {
+        let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+        let result = next.run(envelope).await;
+        match &result {
+            Ok(_) => info!(id = %id, service = %service, verb = %verb, "Stream opened"),
+            Err(e) => warn!(id = %id, service = %service, verb = %verb, error = %e, "Stream failed to open"),
+        }
+        result
+    }
+
+    async fn request_all(
+        &self,
+        envelope: Envelope,
+        options: ScatterOptions,
+        next: ScatterNext,
+    ) -> Result<Vec<ScatterReply>, BusError> {
+        let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+        let started = Instant::now();
+        let result = next.run(envelope, options).await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(replies) => {
+                let failed = replies.iter().filter(|reply| !reply.is_ok()).count();
+                info!(id = %id, service = %service, verb = %verb, elapsed_ms, replies = replies.len(), failed, "Scatter request completed")
+            }
+            Err(e) => warn!(id = %id, service = %service, verb = %verb, elapsed_ms, error = %e, "Scatter request failed"),
+        }
+        result
+    }
+
+    async fn publish(&self, envelope: Envelope, next: PublishNext) -> Result<(), BusError> {
+        let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+        let result = next.run(envelope).await;
+        match &result {
+            Ok(()) => info!(id = %id, service = %service, verb = %verb, "Event published"),
+            Err(e) => warn!(id = %id, service = %service, verb = %verb, error = %e, "Event publish failed"),
+        }
+        result
+    }
+
+    async fn dispatch(
+        &self,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        let (id, service, verb) = (envelope.id.clone(), envelope.service.clone(), envelope.verb.clone());
+        let started = Instant::now();
+        let result = next.run(envelope, ctx).await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(_) => info!(id = %id, service = %service, verb = %verb, elapsed_ms, "Handled message"),
+            Err(e) => warn!(id = %id, service = %service, verb = %verb, elapsed_ms, error = %e, "Handler failed"),
+        }
+        result
+    }
+}
+
+/// Counters for one subject
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SubjectMetrics {
+    pub sent: u64,
+    pub published: u64,
+    pub handled: u64,
+    pub errors: u64,
+    /// Sum of request and handler durations
+    pub total_time: Duration,
+}
+
+/// Counts messages, errors and time spent per subject
+#[derive(Debug, Default)]
+pub struct MetricsMiddleware {
+    subjects: Mutex<BTreeMap<String, SubjectMetrics>>,
+}
+
+impl MetricsMiddleware {
+    /// Empty counters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy of the counters recorded so far, keyed by subject
+    pub fn snapshot(&self) -> BTreeMap<String, SubjectMetrics> {
+        self.subjects.lock().expect("metrics lock poisoned").clone()
+    }
+
+    fn record(&self, subject: String, failed: bool, elapsed: Duration, count: impl FnOnce(&mut SubjectMetrics)) {
+        let mut subjects = self.subjects.lock().expect("metrics lock poisoned");
+        let metrics = subjects.entry(subject).or_default();
+        count(metrics);
+        if failed {
+            metrics.errors += 1;
+        }
+        metrics.total_time += elapsed;
+    }
+}
+
+#[async_trait]
+impl Middleware for MetricsMiddleware {
+    async fn request(
+        &self,
+        envelope: Envelope,
+        parent: Option<&RequestContext>,
+        next: RequestNext,
+    ) -> Result<Value, BusError> {
+        let subject = envelope.subject();
+        let started = Instant::now();
+        let result = next.run(envelope, parent).await;
+        self.record(subject, result.is_err(), started.elapsed(), |m| m.sent += 1);
+        result
+    }
+
+    async fn request_stream(&self, envelope: Envelope, next: StreamNext) -> Result<ResponseStream, BusError> {
+        let subject = envelope.subject();
+        let started = Instant::now();
+        let result = next.run(envelope).await;
+        self.record(subject, result.is_err(), started.elapsed(), |m| m.sent += 1);
+        result
+    }
+
+    async fn request_all(
+        &self,
+        envelope: Envelope,
+        options: ScatterOptions,
+        next: ScatterNext,
+    ) -> Result<Vec<ScatterReply>, BusError> {
+        let subject = envelope.subject();
+        let started = Instant::now();
+        let result = next.run(envelope, options).await;
+        self.record(subject, result.is_err(), started.elapsed(), |m| m.sent += 1);
+        result
+    }
+
+    async fn publish(&self, envelope: Envelope, next: PublishNext) -> Result<(), BusError> {
+        let subject = envelope.subject();
+        let result = next.run(envelope).await;
+        self.record(subject, result.is_err(), Duration::ZERO, |m| m.published += 1);
+        result
+    }
+
+    async fn dispatch(
+        &self,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        let subject = envelope.subject();
+        let started = Instant::now();
+        let result = next.run(envelope, ctx).await;
+        self.record(subject, result.is_err(), started.elapsed(), |m| m.handled += 1);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_handler, sync_handler, LocalBus};
+    use serde_json::json;
+    use tokio::time::{sleep, Duration as TokioDuration};
+
+    /// Appends its name to a shared log on every hook
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn push(&self, hook: &str) {
+            self.log.lock().unwrap().push(format!("{}:{}", self.name, hook));
+        }
+    }
+
+    #[async_trait]
+    impl Middleware for Recorder {
+        async fn request(
+            &self,
+            envelope: Envelope,
+            parent: Option<&RequestContext>,
+            next: RequestNext,
+        ) -> Result<Value, BusError> {
+            self.push(if parent.is_some() { "nested" } else { "request" });
+            next.run(envelope, parent).await
+        }
+
+        async fn request_stream(&self, envelope: Envelope, next: StreamNext) -> Result<ResponseStream, BusError> {
+            self.push("stream");
+            next.run(envelope).await
+        }
+
+        async fn request_all(
+            &self,
+            envelope: Envelope,
+            options: ScatterOptions,
+            next: ScatterNext,
+        ) -> Result<Vec<ScatterReply>, BusError> {
+            self.push("scatter");
+            next.run(envelope, options).await
+        }
+
+        async fn publish(&self, envelope: Envelope, next: PublishNext) -> Result<(), BusError> {
+            self.push("publish");
+            next.run(envelope).await
+        }
+
+        async fn dispatch(
+            &self,
+            envelope: Envelope,
+            ctx: RequestContext,
+            next: DispatchNext,
+        ) -> Result<Value, BusError> {
+            self.push("dispatch");
+            next.run(envelope, ctx).await
+        }
+    }
+
+    /// Rejects inbound messages without an `auth` header
+    struct RequireAuth;
+
+    #[async_trait]
+    impl Middleware for RequireAuth {
+        async fn dispatch(
+            &self,
+            envelope: Envelope,
+            ctx: RequestContext,
+            next: DispatchNext,
+        ) -> Result<Value, BusError> {
+            if ctx.metadata_value("auth").is_none() {
+                return Err(BusError::bad_request("missing auth header"));
+            }
+            next.run(envelope, ctx).await
+        }
+    }
+
+    fn recorded_bus(log: &Arc<Mutex<Vec<String>>>) -> MiddlewareBus {
+        MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(Recorder {
+                name: "outer",
+                log: Arc::clone(log),
+            })
+            .layer(Recorder {
+                name: "inner",
+                log: Arc::clone(log),
+            })
+    }
+
+    #[tokio::test]
+    async fn layers_run_in_order_on_both_sides() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let bus = recorded_bus(&log);
+        let _echo = bus
+            .subscribe("cbs.echo.say", sync_handler(|_| Ok(json!({"ok": true}))))
+            .await
+            .unwrap();
+
+        let envelope = Envelope::new_request("echo", "say", "demo/v1/Echo", json!({}));
+        assert_eq!(bus.request(envelope).await.unwrap(), json!({"ok": true}));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["outer:request", "inner:request", "outer:dispatch", "inner:dispatch"]
+        );
+    }
+
+    #[tokio::test]
+    async fn streamed_requests_pass_through_the_stack() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let bus = recorded_bus(&log);
+        let _echo = bus
+            .subscribe("cbs.echo.say", sync_handler(|_| Ok(json!({"ok": true}))))
+            .await
+            .unwrap();
+
+        let envelope = Envelope::new_request("echo", "say", "demo/v1/Echo", json!({}));
+        let items = bus.request_stream(envelope).await.unwrap().collect_payloads().await.unwrap();
+        assert_eq!(items, vec![json!({"ok": true})]);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["outer:stream", "inner:stream", "outer:dispatch", "inner:dispatch"]
+        );
+    }
+
+    #[tokio::test]
+    async fn scatter_requests_pass_through_the_stack() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let bus = recorded_bus(&log);
+        let mut members = Vec::new();
+        for _ in 0..2 {
+            members.push(bus.subscribe("cbs.echo.health", sync_handler(|_| Ok(json!({})))).await.unwrap());
+        }
+
+        let envelope = Envelope::new_request("echo", "health", "demo/v1/Void", json!({}));
+        assert_eq!(bus.request_all(envelope, ScatterOptions::new()).await.unwrap().len(), 2);
+        let log = log.lock().unwrap();
+        assert_eq!(log[..2], ["outer:scatter", "inner:scatter"]);
+        assert_eq!(log.iter().filter(|hook| *hook == "inner:dispatch").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn nested_requests_and_events_pass_through_the_stack() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let bus = recorded_bus(&log);
+        let _echo = bus
+            .subscribe("cbs.echo.say", sync_handler(|_| Ok(json!({}))))
+            .await
+            .unwrap();
+        let _relay = bus
+            .subscribe(
+                "cbs.relay.forward",
+                async_handler(|_envelope, ctx: RequestContext| async move {
+                    ctx.publish(Envelope::new_request("audit", "forwarded", "demo/v1/Event", json!({})))
+                        .await?;
+                    ctx.request(Envelope::new_request("echo", "say", "demo/v1/Echo", json!({})))
+                        .await
+                }),
+            )
+            .await
+            .unwrap();
+        let _audit = bus
+            .subscribe_events("cbs.audit.forwarded", sync_handler(|_| Ok(json!({}))))
+            .await
+            .unwrap();
+
+        let envelope = Envelope::new_request("relay", "forward", "demo/v1/Echo", json!({}));
+        bus.request(envelope).await.unwrap();
+        sleep(TokioDuration::from_millis(50)).await;
+
+        let log = log.lock().unwrap();
+        for hook in ["outer:nested", "inner:nested", "outer:publish", "inner:publish"] {
+            assert!(log.contains(&hook.to_string()), "missing {} in {:?}", hook, log);
+        }
+        // relay, echo and audit handlers
+        assert_eq!(log.iter().filter(|h| *h == "outer:dispatch").count(), 3);
+    }
+
+    #[tokio::test]
+    async fn middleware_can_short_circuit_dispatch() {
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new())).layer(RequireAuth);
+        let echo = bus
+            .subscribe("cbs.echo.say", sync_handler(|_| Ok(json!({"ok": true}))))
+            .await
+            .unwrap();
+
+        let anonymous = Envelope::new_request("echo", "say", "demo/v1/Echo", json!({}));
+        assert!(matches!(bus.request(anonymous).await, Err(BusError::BadRequest { .. })));
+
+        let signed = Envelope::new_request("echo", "say", "demo/v1/Echo", json!({})).with_header("auth", "token").unwrap();
+        assert_eq!(bus.request(signed).await.unwrap(), json!({"ok": true}));
+        assert_eq!(echo.failed(), 1);
+    }
+
+    #[tokio::test]
+    async fn metrics_count_per_subject() {
+        let metrics = Arc::new(MetricsMiddleware::new());
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new()))
+            .layer(LoggingMiddleware)
+            .layer_shared(metrics.clone());
+        let _echo = bus
+            .subscribe(
+                "cbs.echo.say",
+                sync_handler(|envelope| match envelope.payload.unwrap()["fail"].as_bool() {
+                    Some(true) => Err(BusError::bad_request("failed")),
+                    _ => Ok(json!({})),
+                }),
+            )
+            .await
+            .unwrap();
+
+        for fail in [false, false, true] {
+            let envelope = Envelope::new_request("echo", "say", "demo/v1/Echo", json!({"fail": fail}));
+            let _ = bus.request(envelope).await;
+        }
+
+        let snapshot = metrics.snapshot();
+        let echo = &snapshot["cbs.echo.say"];
+        assert_eq!(echo.sent, 3);
+        assert_eq!(echo.handled, 3);
+        // Each failure is seen once by the caller and once by the handler
+        assert_eq!(echo.errors, 2);
+    }
+}
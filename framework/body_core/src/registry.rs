@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::{AppConfig, BusError, Cell, CellConfig, CellHost, DependencyError};
+
+/// Builds a cell from its entry in app.yaml
+pub type CellFactory = Arc<dyn Fn(&CellConfig) -> Result<Arc<dyn Cell>, BusError> + Send + Sync>;
+
+/// Errors turning app.yaml cell entries into cells
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error("Unknown cell(s) in app.yaml: {} (registered: {})", .unknown.join(", "), .registered.join(", "))]
+    UnknownCells {
+        unknown: Vec<String>,
+        registered: Vec<String>,
+    },
+    #[error("Invalid cell dependencies: {0}")]
+    Dependencies(#[from] DependencyError),
+    #[error("Failed to create cell {cell}: {source}")]
+    Factory {
+        cell: String,
+        #[source]
+        source: BusError,
+    },
+}
+
+/// Cells linked into a binary, by the name app.yaml refers to them with
+///
+/// Binaries list their cells once, usually with `cell_registry!`, and the
+/// Body builds every cell an application names from it. Registering a name
+/// again replaces the earlier factory.
+#[derive(Clone, Default)]
+pub struct CellRegistry {
+    factories: BTreeMap<String, CellFactory>,
+}
+
+impl CellRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a factory under `name`
+    pub fn register<F>(&mut self, name: &str, factory: F) -> &mut Self
+    where
+        F: Fn(&CellConfig) -> Result<Arc<dyn Cell>, BusError> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Arc::new(factory));
+        self
+    }
+
+    /// Register a cell built with `Default`, ignoring its configuration
+    pub fn register_default<C: Cell + Default + 'static>(&mut self, name: &str) -> &mut Self {
+        self.register(name, |_| Ok(Arc::new(C::default())))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    /// Registered names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    /// Build the cell for one app.yaml entry
+    pub fn instantiate(&self, config: &CellConfig) -> Result<Arc<dyn Cell>, RegistryError> {
+        let factory = self.factories.get(&config.name).ok_or_else(|| self.unknown(vec![config.name.clone()]))?;
+        factory(config).map_err(|source| RegistryError::Factory {
+            cell: config.name.clone(),
+            source,
+        })
+    }
+
+    /// Build every cell `app` lists, in dependency order, ready to be started
+    ///
+    /// Every name and dependency is checked before any cell is built, so one
+    /// error lists all the unknown names.
+    pub fn host_for(&self, app: &AppConfig) -> Result<CellHost, RegistryError> {
+        let unknown: Vec<String> = app
+            .cells
+            .iter()
+            .filter(|cell| !self.contains(&cell.name))
+            .map(|cell| cell.name.clone())
+            .collect();
+        if !unknown.is_empty() {
+            return Err(self.unknown(unknown));
+        }
+
+        let mut host = CellHost::new();
+        for config in app.startup_order()? {
+            host.add(self.instantiate(config)?, config.clone());
+        }
+        Ok(host)
+    }
+
+    fn unknown(&self, unknown: Vec<String>) -> RegistryError {
+        RegistryError::UnknownCells {
+            unknown,
+            registered: self.names().into_iter().map(str::to_string).collect(),
+        }
+    }
+}
+
+impl fmt::Debug for CellRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CellRegistry")
+            .field("cells", &self.names())
+            .finish()
+    }
+}
+
+/// Build a `CellRegistry` from `name => factory` pairs
+///
+/// ```ignore
+/// let registry = cell_registry! {
+///     "greeter_rs" => |_config: &CellConfig| Ok(Arc::new(GreeterCell::new()) as Arc<dyn Cell>),
+/// };
+/// ```
+#[macro_export]
+macro_rules! cell_registry {
+    ($($name:expr => $factory:expr),* $(,)?) => {{
+        let mut registry = $crate::CellRegistry::new();
+        $(registry.register($name, $factory);)*
+        registry
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BodyBus;
+    use async_trait::async_trait;
+
+    #[derive(Default)]
+    struct Named(String);
+
+    #[async_trait]
+    impl Cell for Named {
+        fn id(&self) -> &str {
+            &self.0
+        }
+
+        fn subjects(&self) -> Vec<String> {
+            vec![]
+        }
+
+        async fn register(&self, _bus: &dyn BodyBus) -> Result<(), BusError> {
+            Ok(())
+        }
+    }
+
+    fn app(names: &[&str]) -> AppConfig {
+        AppConfig {
+            name: "test_app".to_string(),
+            cells: names
+                .iter()
+                .map(|name| CellConfig {
+                    name: name.to_string(),
+                    path: format!("cells/{}", name),
+                    dependencies: vec![],
+                    limits: None,
+                    config: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn registry() -> CellRegistry {
+        let mut registry = cell_registry! {
+            "greeter_rs" => |config: &CellConfig| Ok(Arc::new(Named(config.name.clone())) as Arc<dyn Cell>),
+            "broken_rs" => |_: &CellConfig| Err(BusError::Internal("missing credentials".to_string())),
+        };
+        registry.register_default::<Named>("blank_rs");
+        registry
+    }
+
+    #[test]
+    fn app_cells_are_built_in_order() {
+        let host = registry().host_for(&app(&["greeter_rs", "blank_rs"])).unwrap();
+        assert_eq!(host.len(), 2);
+        assert_eq!(registry().names(), vec!["blank_rs", "broken_rs", "greeter_rs"]);
+    }
+
+    #[test]
+    fn dependencies_are_built_first() {
+        let mut app = app(&["greeter_rs", "blank_rs"]);
+        app.cells[0].dependencies = vec!["blank_rs".to_string()];
+        let host = registry().host_for(&app).unwrap();
+        assert_eq!(host.names(), vec!["blank_rs", "greeter_rs"]);
+
+        app.cells[1].dependencies = vec!["greeter_rs".to_string()];
+        assert!(matches!(registry().host_for(&app), Err(RegistryError::Dependencies(_))));
+    }
+
+    #[test]
+    fn unknown_cells_are_all_named() {
+        let error = registry().host_for(&app(&["greeter_rs", "printer_rs", "prompt_rs"])).unwrap_err();
+        assert!(matches!(&error, RegistryError::UnknownCells { unknown, .. } if unknown == &["printer_rs", "prompt_rs"]));
+        assert_eq!(
+            error.to_string(),
+            "Unknown cell(s) in app.yaml: printer_rs, prompt_rs (registered: blank_rs, broken_rs, greeter_rs)"
+        );
+    }
+
+    #[test]
+    fn factory_failures_name_the_cell() {
+        let error = registry().host_for(&app(&["broken_rs"])).unwrap_err();
+        assert!(matches!(error, RegistryError::Factory { ref cell, .. } if cell == "broken_rs"));
+    }
+}
@@ -0,0 +1,354 @@
+use async_trait::async_trait;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::middleware::{Middleware, RequestNext};
+use crate::subject;
+use crate::{headers, BodyBus, BusError, Envelope, RequestContext};
+
+/// How often and how patiently to retry a failed request
+///
+/// Every field has a default, so `app.yaml` only lists what it changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total attempts including the first; 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff_ms: u64,
+    /// Upper bound for any single delay
+    pub max_backoff_ms: u64,
+    /// Growth factor between consecutive delays
+    pub multiplier: f64,
+    /// Random spread as a fraction of the delay, e.g. 0.2 for +/-20%
+    pub jitter: f64,
+    /// `BusError::code()` values worth retrying
+    pub retry_on: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 50,
+            max_backoff_ms: 2_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_on: vec!["Timeout".to_string(), "Connection".to_string()],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether `error` is worth another attempt under this policy
+    pub fn is_retryable(&self, error: &BusError) -> bool {
+        self.retry_on.iter().any(|code| code == error.code())
+    }
+
+    /// Delay before retry number `retry` (1-based), without jitter
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let millis = self.initial_backoff_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        Duration::from_millis(millis.min(self.max_backoff_ms as f64) as u64)
+    }
+
+    fn jittered(&self, retry: u32) -> Duration {
+        let delay = self.backoff(retry);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+    }
+
+    /// Run `attempt` with attempt numbers 1, 2, ... until it succeeds, fails
+    /// with a non-retryable error, or attempts run out
+    ///
+    /// Stops early when `parent` is cancelled or its deadline would pass during the backoff.
+    pub async fn run<F, Fut>(&self, subject: &str, parent: Option<&RequestContext>, mut attempt: F) -> Result<Value, BusError>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<Value, BusError>>,
+    {
+        let mut attempts = 1;
+        loop {
+            let error = match attempt(attempts).await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if attempts >= self.max_attempts.max(1) || !self.is_retryable(&error) {
+                return Err(error);
+            }
+
+            let delay = self.jittered(attempts);
+            if let Some(parent) = parent {
+                let out_of_time = parent.remaining().is_some_and(|remaining| remaining <= delay);
+                if parent.is_cancelled() || out_of_time {
+                    return Err(error);
+                }
+            }
+
+            warn!(subject = %subject, attempt = attempts, delay_ms = delay.as_millis() as u64, error = %error, "Retrying request");
+            tokio::time::sleep(delay).await;
+            attempts += 1;
+        }
+    }
+
+    /// Send a request under this policy; every attempt reuses the envelope id
+    /// and carries its number in the `attempt` header
+    pub async fn request(&self, bus: &dyn BodyBus, envelope: Envelope) -> Result<Value, BusError> {
+        let subject = envelope.subject();
+        self.run(&subject, None, |attempt| bus.request(numbered(&envelope, attempt))).await
+    }
+
+    /// `request` for a nested request made on behalf of a handler
+    pub async fn request_with_context(
+        &self,
+        bus: &dyn BodyBus,
+        envelope: Envelope,
+        parent: &RequestContext,
+    ) -> Result<Value, BusError> {
+        let subject = envelope.subject();
+        self.run(&subject, Some(parent), |attempt| bus.request_with_context(numbered(&envelope, attempt), parent))
+            .await
+    }
+}
+
+/// Copy of `envelope` for attempt number `attempt`
+fn numbered(envelope: &Envelope, attempt: u32) -> Envelope {
+    let mut envelope = envelope.clone();
+    envelope.headers_mut().set(headers::ATTEMPT, &attempt.to_string());
+    envelope
+}
+
+/// Retry policies from the `retry:` section of `app.yaml`
+///
+/// ```yaml
+/// retry:
+///   default:
+///     max_attempts: 2
+///   subjects:
+///     "cbs.payment.*":
+///       max_attempts: 5
+///       initial_backoff_ms: 100
+///       retry_on: [Timeout, Connection, Internal]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Policy for subjects without a specific entry; no retries when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<RetryPolicy>,
+    /// Policies keyed by subject or wildcard pattern; the most specific match wins
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub subjects: BTreeMap<String, RetryPolicy>,
+}
+
+impl RetryConfig {
+    /// Whether no policy is configured
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.subjects.is_empty()
+    }
+
+    /// Policy that applies to `subject`, if any
+    pub fn policy_for(&self, subject: &str) -> Option<&RetryPolicy> {
+        subject::best_match(self.subjects.keys().map(String::as_str), subject)
+            .and_then(|pattern| self.subjects.get(pattern))
+            .or(self.default.as_ref())
+    }
+}
+
+/// Middleware that retries outbound requests according to a `RetryConfig`
+#[derive(Debug, Clone)]
+pub struct RetryMiddleware {
+    config: RetryConfig,
+}
+
+impl RetryMiddleware {
+    /// Retry with the given policies; invalid subject patterns are rejected
+    pub fn new(config: RetryConfig) -> Result<Self, BusError> {
+        for pattern in config.subjects.keys() {
+            subject::validate_pattern(pattern)?;
+        }
+        Ok(Self { config })
+    }
+
+    /// Policies this middleware applies
+    pub fn config(&self) -> &RetryConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl Middleware for RetryMiddleware {
+    async fn request(
+        &self,
+        envelope: Envelope,
+        parent: Option<&RequestContext>,
+        next: RequestNext,
+    ) -> Result<Value, BusError> {
+        let subject = envelope.subject();
+        let Some(policy) = self.config.policy_for(&subject) else {
+            return next.run(envelope, parent).await;
+        };
+
+        debug!(subject = %subject, id = %envelope.id, max_attempts = policy.max_attempts, "Applying retry policy");
+        policy
+            .run(&subject, parent, |attempt| next.clone().run(numbered(&envelope, attempt), parent))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync_handler, LocalBus, LocalBusConfig, MiddlewareBus};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    fn fast(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Envelope id and `attempt` header of every call a handler received
+    type Seen = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Fails with `error` until it has been called `failures` times, recording what it saw
+    fn flaky(failures: usize, error: fn() -> BusError) -> (crate::SharedHandler, Seen) {
+        let ids = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&ids);
+        let calls = AtomicUsize::new(0);
+        let handler = sync_handler(move |envelope| {
+            let attempt = envelope.header(headers::ATTEMPT).unwrap_or_default().to_string();
+            seen.lock().unwrap().push((envelope.id.clone(), attempt));
+            if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                Err(error())
+            } else {
+                Ok(json!({"ok": true}))
+            }
+        });
+        (handler, ids)
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_cap() {
+        let policy = RetryPolicy {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 350,
+            ..RetryPolicy::default()
+        };
+        let delays: Vec<_> = (1..=4).map(|retry| policy.backoff(retry).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 350, 350]);
+
+        for _ in 0..20 {
+            let delay = policy.jittered(1).as_millis();
+            assert!((80..=120).contains(&delay), "{} outside +/-20%", delay);
+        }
+    }
+
+    #[test]
+    fn policy_lookup_prefers_the_most_specific_subject() {
+        let config: RetryConfig = serde_yaml::from_str(
+            r#"
+default:
+  max_attempts: 2
+subjects:
+  "cbs.payment.*":
+    max_attempts: 5
+  "cbs.payment.charge":
+    max_attempts: 7
+    retry_on: [Timeout]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.policy_for("cbs.payment.charge").unwrap().max_attempts, 7);
+        assert_eq!(config.policy_for("cbs.payment.refund").unwrap().max_attempts, 5);
+        assert_eq!(config.policy_for("cbs.greeter.say_hello").unwrap().max_attempts, 2);
+        // Unlisted fields keep their defaults
+        assert_eq!(config.policy_for("cbs.payment.refund").unwrap().retry_on, vec!["Timeout", "Connection"]);
+        assert!(RetryConfig::default().policy_for("cbs.payment.charge").is_none());
+    }
+
+    #[tokio::test]
+    async fn retries_reuse_the_envelope_id_and_number_attempts() {
+        let bus = LocalBus::new();
+        let (handler, ids) = flaky(2, || BusError::Connection("flaky".to_string()));
+        let _flaky = bus.subscribe("cbs.flaky.call", handler).await.unwrap();
+
+        let envelope = Envelope::new_request("flaky", "call", "demo/v1/Test", json!({}));
+        let id = envelope.id.clone();
+        assert_eq!(fast(3).request(&bus, envelope).await.unwrap(), json!({"ok": true}));
+
+        let ids = ids.lock().unwrap();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.iter().all(|(seen, _)| *seen == id));
+        let attempts: Vec<&str> = ids.iter().map(|(_, attempt)| attempt.as_str()).collect();
+        assert_eq!(attempts, ["1", "2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn non_retryable_errors_and_exhausted_attempts_surface() {
+        let bus = LocalBus::new();
+        let (bad, bad_ids) = flaky(usize::MAX, || BusError::bad_request("nope"));
+        let _bad = bus.subscribe("cbs.flaky.bad", bad).await.unwrap();
+        let (down, down_ids) = flaky(usize::MAX, || BusError::Connection("down".to_string()));
+        let _down = bus.subscribe("cbs.flaky.down", down).await.unwrap();
+
+        let envelope = Envelope::new_request("flaky", "bad", "demo/v1/Test", json!({}));
+        assert!(matches!(fast(3).request(&bus, envelope).await, Err(BusError::BadRequest { .. })));
+        assert_eq!(bad_ids.lock().unwrap().len(), 1);
+
+        let envelope = Envelope::new_request("flaky", "down", "demo/v1/Test", json!({}));
+        assert!(matches!(fast(3).request(&bus, envelope).await, Err(BusError::Connection(_))));
+        assert_eq!(down_ids.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn middleware_retries_timeouts_per_subject() {
+        let local = LocalBus::with_config(LocalBusConfig {
+            request_timeout: Duration::from_millis(30),
+            ..LocalBusConfig::default()
+        });
+        let slow_calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&slow_calls);
+        let _slow = local
+            .subscribe(
+                "cbs.slow.call",
+                crate::async_handler(move |_envelope, _ctx| {
+                    let first = counter.fetch_add(1, Ordering::SeqCst) == 0;
+                    async move {
+                        if first {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                        }
+                        Ok(json!({"ok": true}))
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+
+        let mut config = RetryConfig::default();
+        config.subjects.insert("cbs.slow.*".to_string(), fast(2));
+        let bus = MiddlewareBus::new(Arc::new(local)).layer(RetryMiddleware::new(config).unwrap());
+
+        let envelope = Envelope::new_request("slow", "call", "demo/v1/Test", json!({}));
+        assert_eq!(bus.request(envelope).await.unwrap(), json!({"ok": true}));
+        assert_eq!(slow_calls.load(Ordering::SeqCst), 2);
+
+        let mut invalid = RetryConfig::default();
+        invalid.subjects.insert("cbs.>.bad".to_string(), fast(2));
+        assert!(RetryMiddleware::new(invalid).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::{headers, BusError, Envelope};
+
+/// Subjects scatter requests travel on: `_cbs.all.{service}.{verb}`
+///
+/// Every subscriber also listens here outside its queue group, so one
+/// message reaches all instances of a service. Being outside the `cbs.`
+/// namespace keeps them away from wildcard subscriptions such as `cbs.>`.
+pub const SCATTER_SUBJECT_PREFIX: &str = "_cbs.all";
+
+/// Scatter subject for a request subject or subscription pattern
+pub fn scatter_subject(subject: &str) -> String {
+    let rest = subject.strip_prefix("cbs.").unwrap_or(subject);
+    format!("{}.{}", SCATTER_SUBJECT_PREFIX, rest)
+}
+
+/// Mark `envelope` as a scatter request; buses call this in `request_all`
+pub fn mark_scatter(envelope: &mut Envelope) {
+    envelope.headers_mut().set(headers::SCATTER, "true");
+}
+
+/// Whether `envelope` was sent to every instance rather than to one
+pub fn is_scatter(envelope: &Envelope) -> bool {
+    envelope.header(headers::SCATTER).is_some()
+}
+
+/// When `BodyBus::request_all` stops collecting replies
+#[derive(Debug, Clone, Default)]
+pub struct ScatterOptions {
+    /// Longest to wait for replies; the bus's request timeout when unset
+    pub timeout: Option<Duration>,
+    /// Stop as soon as this many replies have arrived
+    pub expected: Option<usize>,
+}
+
+impl ScatterOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn expecting(mut self, count: usize) -> Self {
+        self.expected = Some(count);
+        self
+    }
+}
+
+/// One instance's answer to a scatter request
+#[derive(Debug)]
+pub struct ScatterReply {
+    /// Id of the subscription that handled the request
+    pub responder: String,
+    pub result: Result<Value, BusError>,
+}
+
+impl ScatterReply {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scatter_subjects_mirror_request_subjects() {
+        assert_eq!(scatter_subject("cbs.greeter.health"), "_cbs.all.greeter.health");
+        assert_eq!(scatter_subject("cbs.*.health"), "_cbs.all.*.health");
+        assert_eq!(scatter_subject("cbs.>"), "_cbs.all.>");
+        assert!(!crate::subject::matches("cbs.>", &scatter_subject("cbs.greeter.health")));
+    }
+
+    #[test]
+    fn options_build_up() {
+        let options = ScatterOptions::new().with_timeout(Duration::from_millis(50)).expecting(3);
+        assert_eq!(options.timeout, Some(Duration::from_millis(50)));
+        assert_eq!(options.expected, Some(3));
+    }
+}
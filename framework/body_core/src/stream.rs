@@ -0,0 +1,467 @@
+use async_trait::async_trait;
+use futures_util::Stream;
+use serde_json::{json, Value};
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Sleep;
+use tokio_util::sync::DropGuard;
+
+use crate::handler::{Handler, SharedHandler};
+use crate::{headers, BusError, CancellationToken, Envelope, RequestContext};
+
+/// Items a sink may have in flight before `send` waits for the caller to catch up
+pub const STREAM_BUFFER: usize = 64;
+
+/// Handler-side end of a streamed reply
+///
+/// Each `send` becomes one response envelope carrying a `stream_seq` header.
+/// The stream ends with a marker envelope carrying `stream_end`, or with an
+/// error envelope, both sent once by `finish`/`fail` or by the bus when the
+/// handler returns. Clones share the same stream.
+#[derive(Clone)]
+pub struct StreamSink {
+    request: Arc<Envelope>,
+    sender: mpsc::Sender<Envelope>,
+    seq: Arc<AtomicU64>,
+    finished: Arc<AtomicBool>,
+}
+
+impl StreamSink {
+    /// Sink for replies to `request`, delivered to `sender` in order
+    pub fn new(request: &Envelope, sender: mpsc::Sender<Envelope>) -> Self {
+        Self {
+            request: Arc::new(request.clone()),
+            sender,
+            seq: Arc::new(AtomicU64::new(0)),
+            finished: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Send one item; fails with `Cancelled` once the caller has dropped the stream
+    pub async fn send(&self, payload: Value) -> Result<(), BusError> {
+        if self.is_finished() {
+            return Err(BusError::Internal("Stream already ended".to_string()));
+        }
+        let mut envelope = self.reply(payload);
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        envelope.headers_mut().set(headers::STREAM_SEQ, &seq.to_string());
+        self.sender.send(envelope).await.map_err(|_| BusError::Cancelled)
+    }
+
+    /// Number of items sent so far
+    pub fn sent(&self) -> u64 {
+        self.seq.load(Ordering::SeqCst)
+    }
+
+    /// Whether the caller has stopped listening
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Whether the end-of-stream marker or an error has been sent
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// End the stream successfully
+    pub async fn finish(&self) {
+        if !self.finished.swap(true, Ordering::SeqCst) {
+            let mut marker = self.reply(json!({}));
+            marker.headers_mut().set(headers::STREAM_END, "true");
+            let _ = self.sender.send(marker).await;
+        }
+    }
+
+    /// End the stream with an error
+    pub async fn fail(&self, error: &BusError) {
+        if !self.finished.swap(true, Ordering::SeqCst) {
+            let mut envelope = Envelope::new_error(
+                &self.request.id,
+                &self.request.service,
+                &self.request.verb,
+                &self.request.schema,
+                error.to_error_details(),
+            );
+            headers::stamp_reply(&mut envelope, &self.request);
+            envelope.headers_mut().set(headers::STREAM_END, "true");
+            let _ = self.sender.send(envelope).await;
+        }
+    }
+
+    /// End the stream with a handler's result, unless the handler already did
+    ///
+    /// A plain handler that never touched the sink streams its reply as a
+    /// single item, so any handler can serve `request_stream`.
+    pub async fn complete(&self, result: Result<Value, BusError>) {
+        if self.is_finished() {
+            return;
+        }
+        match result {
+            Ok(payload) => {
+                if self.sent() == 0 && self.send(payload).await.is_err() {
+                    return;
+                }
+                self.finish().await;
+            }
+            Err(e) => self.fail(&e).await,
+        }
+    }
+
+    fn reply(&self, payload: Value) -> Envelope {
+        let mut envelope = Envelope::new_response(
+            &self.request.id,
+            &self.request.service,
+            &self.request.verb,
+            &self.request.schema,
+            payload,
+        );
+        headers::stamp_reply(&mut envelope, &self.request);
+        envelope
+    }
+}
+
+impl std::fmt::Debug for StreamSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamSink")
+            .field("request", &self.request.id)
+            .field("sent", &self.sent())
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// Whether an envelope ends a stream
+pub fn is_end_of_stream(envelope: &Envelope) -> bool {
+    envelope.is_error() || envelope.header(headers::STREAM_END).is_some()
+}
+
+/// Caller-side end of a streamed reply, as returned by `BodyBus::request_stream`
+///
+/// Yields each response envelope in order and ends after the end-of-stream
+/// marker. An error reply is yielded as `Err` and ends the stream, as does
+/// waiting longer than the idle timeout for the next item. Dropping the
+/// stream tells the handler to stop: its next `send` fails with `Cancelled`,
+/// and its context is cancelled.
+pub struct ResponseStream {
+    receiver: mpsc::Receiver<Envelope>,
+    idle_timeout: Option<Duration>,
+    idle: Option<Pin<Box<Sleep>>>,
+    done: bool,
+    _cancel: Option<DropGuard>,
+    _held: Vec<Box<dyn Any + Send>>,
+}
+
+impl ResponseStream {
+    /// Stream the envelopes arriving on `receiver`
+    pub fn new(receiver: mpsc::Receiver<Envelope>, idle_timeout: Option<Duration>) -> Self {
+        Self {
+            receiver,
+            idle_timeout,
+            idle: idle_timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+            done: false,
+            _cancel: None,
+            _held: Vec::new(),
+        }
+    }
+
+    /// Cancel `token`, typically the handler's, when the stream is dropped
+    pub fn cancel_on_drop(mut self, token: CancellationToken) -> Self {
+        self._cancel = Some(token.drop_guard());
+        self
+    }
+
+    /// Keep `value` alive until the stream is dropped, e.g. a concurrency permit
+    pub fn hold(mut self, value: impl Any + Send) -> Self {
+        self._held.push(Box::new(value));
+        self
+    }
+
+    /// Connected sink and stream for replies to `request`
+    pub fn channel(request: &Envelope, idle_timeout: Option<Duration>) -> (StreamSink, Self) {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        (StreamSink::new(request, sender), Self::new(receiver, idle_timeout))
+    }
+
+    /// Wait for the next item; `None` after the end of the stream
+    pub async fn next_item(&mut self) -> Option<Result<Envelope, BusError>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Read the whole stream and return the item payloads
+    pub async fn collect_payloads(mut self) -> Result<Vec<Value>, BusError> {
+        let mut payloads = Vec::new();
+        while let Some(item) = self.next_item().await {
+            payloads.push(item?.payload.unwrap_or(Value::Null));
+        }
+        Ok(payloads)
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = Result<Envelope, BusError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        match self.receiver.poll_recv(cx) {
+            Poll::Ready(Some(envelope)) => {
+                if let Some(timeout) = self.idle_timeout {
+                    self.idle = Some(Box::pin(tokio::time::sleep(timeout)));
+                }
+                if let Some(error) = envelope.error {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(BusError::from_error_details(error))));
+                }
+                if envelope.header(headers::STREAM_END).is_some() {
+                    self.done = true;
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(Some(Ok(envelope)))
+            }
+            Poll::Ready(None) => {
+                self.done = true;
+                Poll::Ready(Some(Err(BusError::Internal(
+                    "Stream closed before its end-of-stream marker".to_string(),
+                ))))
+            }
+            Poll::Pending => match self.idle.as_mut().map(|idle| idle.as_mut().poll(cx)) {
+                Some(Poll::Ready(())) => {
+                    self.done = true;
+                    Poll::Ready(Some(Err(BusError::Timeout)))
+                }
+                _ => Poll::Pending,
+            },
+        }
+    }
+}
+
+impl std::fmt::Debug for ResponseStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseStream")
+            .field("idle_timeout", &self.idle_timeout)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+/// Adapter that runs an async closure writing to a `StreamSink` as a `Handler`
+pub struct StreamHandler<F> {
+    f: F,
+}
+
+#[async_trait]
+impl<F, Fut> Handler for StreamHandler<F>
+where
+    F: Fn(Envelope, RequestContext, StreamSink) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), BusError>> + Send,
+{
+    async fn handle(&self, envelope: Envelope, ctx: RequestContext) -> Result<Value, BusError> {
+        if let Some(sink) = ctx.stream_sink().cloned() {
+            let result = (self.f)(envelope, ctx, sink.clone()).await;
+            return match result {
+                Ok(()) => {
+                    sink.finish().await;
+                    Ok(json!({"streamed": sink.sent()}))
+                }
+                Err(e) => {
+                    sink.fail(&e).await;
+                    Err(e)
+                }
+            };
+        }
+
+        // Plain request: gather the items into one reply
+        let (sink, mut stream) = ResponseStream::channel(&envelope, None);
+        let collect = async {
+            let mut items = Vec::new();
+            while let Some(Ok(item)) = stream.next_item().await {
+                items.push(item.payload.unwrap_or(Value::Null));
+            }
+            items
+        };
+        let produce = async {
+            let result = (self.f)(envelope, ctx, sink.clone()).await;
+            match &result {
+                Ok(()) => sink.finish().await,
+                Err(e) => sink.fail(e).await,
+            }
+            drop(sink);
+            result
+        };
+        let (result, items) = tokio::join!(produce, collect);
+        result.map(|()| json!({"items": items}))
+    }
+}
+
+/// Wrap an async closure that streams its replies through a `StreamSink` for `BodyBus::subscribe`
+///
+/// Called through `request_stream`, every `sink.send` reaches the caller as it
+/// happens. Called through a plain `request`, the items are gathered into one
+/// reply: `{"items": [...]}`.
+pub fn stream_handler<F, Fut>(f: F) -> SharedHandler
+where
+    F: Fn(Envelope, RequestContext, StreamSink) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), BusError>> + Send + 'static,
+{
+    Arc::new(StreamHandler { f })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync_handler, BodyBus, LocalBus, LocalBusConfig, MiddlewareBus, MetricsMiddleware};
+    use futures_util::StreamExt;
+
+    fn counter(count: u64) -> SharedHandler {
+        stream_handler(move |_envelope, _ctx, sink| async move {
+            for n in 0..count {
+                sink.send(json!({"n": n})).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn list() -> Envelope {
+        Envelope::new_request("monitor", "list", "demo/v1/List", json!({}))
+    }
+
+    #[tokio::test]
+    async fn local_bus_streams_items_in_order_then_ends() {
+        let bus = LocalBus::new();
+        let _monitor = bus.subscribe("cbs.monitor.list", counter(5)).await.unwrap();
+
+        let request = list();
+        let mut stream = bus.request_stream(request.clone()).await.unwrap();
+        let mut seen = Vec::new();
+        while let Some(item) = stream.next().await {
+            let item = item.unwrap();
+            assert_eq!(item.id, request.id);
+            assert_eq!(item.header(headers::CORRELATION_ID), Some(request.id.as_str()));
+            seen.push((item.header(headers::STREAM_SEQ).unwrap().to_string(), item.payload.unwrap()["n"].clone()));
+        }
+        let expected: Vec<_> = (0..5).map(|n| (n.to_string(), json!(n))).collect();
+        assert_eq!(seen, expected);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn handler_errors_end_the_stream() {
+        let bus = LocalBus::new();
+        let _failing = bus
+            .subscribe(
+                "cbs.monitor.list",
+                stream_handler(|_envelope, _ctx, sink| async move {
+                    sink.send(json!({"n": 0})).await?;
+                    Err(BusError::Unavailable("log rotated".to_string()))
+                }),
+            )
+            .await
+            .unwrap();
+
+        let mut stream = bus.request_stream(list()).await.unwrap();
+        assert!(stream.next_item().await.unwrap().is_ok());
+        assert!(matches!(stream.next_item().await, Some(Err(BusError::Unavailable(_)))));
+        assert!(stream.next_item().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn plain_handlers_stream_a_single_item_and_plain_requests_collect() {
+        let bus = LocalBus::new();
+        let _plain = bus
+            .subscribe("cbs.greeter.say_hello", sync_handler(|_| Ok(json!({"message": "Hello!"}))))
+            .await
+            .unwrap();
+        let _monitor = bus.subscribe("cbs.monitor.list", counter(3)).await.unwrap();
+
+        let request = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+        let items = bus.request_stream(request).await.unwrap().collect_payloads().await.unwrap();
+        assert_eq!(items, vec![json!({"message": "Hello!"})]);
+
+        let reply = bus.request(list()).await.unwrap();
+        assert_eq!(reply, json!({"items": [{"n": 0}, {"n": 1}, {"n": 2}]}));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_stream_cancels_the_handler() {
+        let bus = LocalBus::new();
+        let (stopped_tx, stopped_rx) = tokio::sync::oneshot::channel();
+        let stopped_tx = Arc::new(std::sync::Mutex::new(Some(stopped_tx)));
+        let _tail = bus
+            .subscribe(
+                "cbs.logs.tail",
+                stream_handler(move |_envelope, _ctx, sink| {
+                    let stopped_tx = Arc::clone(&stopped_tx);
+                    async move {
+                        let mut n = 0;
+                        let result = loop {
+                            if let Err(e) = sink.send(json!({"line": n})).await {
+                                break e;
+                            }
+                            n += 1;
+                        };
+                        if let Some(tx) = stopped_tx.lock().unwrap().take() {
+                            let _ = tx.send(result);
+                        }
+                        Ok(())
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+
+        let request = Envelope::new_request("logs", "tail", "demo/v1/Tail", json!({}));
+        let mut stream = bus.request_stream(request).await.unwrap();
+        for _ in 0..3 {
+            assert!(stream.next_item().await.unwrap().is_ok());
+        }
+        drop(stream);
+
+        let stopped = tokio::time::timeout(Duration::from_secs(1), stopped_rx).await.unwrap().unwrap();
+        assert!(matches!(stopped, BusError::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn idle_streams_time_out() {
+        let bus = LocalBus::with_config(LocalBusConfig {
+            request_timeout: Duration::from_millis(30),
+            ..LocalBusConfig::default()
+        });
+        let _slow = bus
+            .subscribe(
+                "cbs.monitor.list",
+                stream_handler(|_envelope, _ctx, sink| async move {
+                    sink.send(json!({"n": 0})).await?;
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    Ok(())
+                }),
+            )
+            .await
+            .unwrap();
+
+        let mut stream = bus.request_stream(list()).await.unwrap();
+        assert!(stream.next_item().await.unwrap().is_ok());
+        assert!(matches!(stream.next_item().await, Some(Err(BusError::Timeout))));
+        assert!(stream.next_item().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn streams_pass_through_middleware_and_report_missing_handlers() {
+        let metrics = Arc::new(MetricsMiddleware::new());
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new())).layer_shared(metrics.clone());
+        let _monitor = bus.subscribe("cbs.monitor.list", counter(2)).await.unwrap();
+
+        let items = bus.request_stream(list()).await.unwrap().collect_payloads().await.unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(metrics.snapshot()["cbs.monitor.list"].handled, 1);
+
+        let missing = Envelope::new_request("nobody", "list", "demo/v1/List", json!({}));
+        assert!(matches!(bus.request_stream(missing).await, Err(BusError::NotFound(_))));
+    }
+}
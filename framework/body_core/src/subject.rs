@@ -0,0 +1,190 @@
+use crate::BusError;
+
+/// Wildcard matching exactly one token
+pub const SINGLE_WILDCARD: &str = "*";
+
+/// Wildcard matching one or more trailing tokens
+pub const TAIL_WILDCARD: &str = ">";
+
+/// Whether `subject` is a concrete subject that can be published to
+pub fn is_valid_subject(subject: &str) -> bool {
+    !subject.is_empty()
+        && subject.split('.').all(|token| {
+            !token.is_empty()
+                && token != SINGLE_WILDCARD
+                && token != TAIL_WILDCARD
+                && !token.chars().any(char::is_whitespace)
+        })
+}
+
+/// Whether `pattern` is a valid subscription pattern
+///
+/// `>` is only allowed as the last token, and wildcards must be whole tokens.
+pub fn is_valid_pattern(pattern: &str) -> bool {
+    if pattern.is_empty() {
+        return false;
+    }
+    let tokens: Vec<&str> = pattern.split('.').collect();
+    tokens.iter().enumerate().all(|(index, token)| {
+        if token.is_empty() || token.chars().any(char::is_whitespace) {
+            return false;
+        }
+        if *token == TAIL_WILDCARD {
+            return index == tokens.len() - 1;
+        }
+        *token == SINGLE_WILDCARD || !token.contains(['*', '>'])
+    })
+}
+
+/// Reject subscription subjects that are not valid patterns
+pub fn validate_pattern(pattern: &str) -> Result<(), BusError> {
+    if is_valid_pattern(pattern) {
+        Ok(())
+    } else {
+        Err(BusError::bad_request(format!("Invalid subject pattern: {}", pattern)))
+    }
+}
+
+/// Whether `pattern` contains any wildcard token
+pub fn has_wildcards(pattern: &str) -> bool {
+    pattern
+        .split('.')
+        .any(|token| token == SINGLE_WILDCARD || token == TAIL_WILDCARD)
+}
+
+/// Whether the concrete `subject` matches the subscription `pattern`
+///
+/// Uses NATS semantics: `cbs.>` matches every CBS subject and `cbs.*.health`
+/// matches the health verb of every service.
+pub fn matches(pattern: &str, subject: &str) -> bool {
+    let mut pattern_tokens = pattern.split('.');
+    let mut subject_tokens = subject.split('.');
+    loop {
+        match (pattern_tokens.next(), subject_tokens.next()) {
+            (Some(TAIL_WILDCARD), Some(_)) => return true,
+            (Some(SINGLE_WILDCARD), Some(_)) => continue,
+            (Some(expected), Some(actual)) if expected == actual => continue,
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Pick the most specific pattern matching `subject`
+///
+/// An exact subject wins over wildcards; otherwise the pattern with the most
+/// literal tokens wins, with `*` preferred over `>`. Ties keep the first
+/// candidate so callers with ordered collections get stable results.
+pub fn best_match<'a, I>(patterns: I, subject: &str) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut best: Option<(&'a str, (usize, usize))> = None;
+    for pattern in patterns {
+        if !matches(pattern, subject) {
+            continue;
+        }
+        let score = specificity(pattern);
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((pattern, score));
+        }
+    }
+    best.map(|(pattern, _)| pattern)
+}
+
+/// The literal `service` token of a `cbs.{service}.{verb}` subject or pattern
+pub fn service_token(subject: &str) -> Option<&str> {
+    let service = subject.strip_prefix("cbs.")?.split('.').next()?;
+    if service.is_empty() || service == SINGLE_WILDCARD || service == TAIL_WILDCARD {
+        None
+    } else {
+        Some(service)
+    }
+}
+
+/// Ranking key: (literal tokens, single-token wildcards)
+fn specificity(pattern: &str) -> (usize, usize) {
+    pattern.split('.').fold((0, 0), |(literal, single), token| match token {
+        TAIL_WILDCARD => (literal, single),
+        SINGLE_WILDCARD => (literal, single + 1),
+        _ => (literal + 1, single),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_subjects_match() {
+        assert!(matches("cbs.greeter.say_hello", "cbs.greeter.say_hello"));
+        assert!(!matches("cbs.greeter.say_hello", "cbs.greeter.say_bye"));
+        assert!(!matches("cbs.greeter", "cbs.greeter.say_hello"));
+        assert!(!matches("cbs.greeter.say_hello", "cbs.greeter"));
+    }
+
+    #[test]
+    fn single_wildcard_matches_one_token() {
+        assert!(matches("cbs.*.health", "cbs.greeter.health"));
+        assert!(matches("cbs.greeter.*", "cbs.greeter.say_hello"));
+        assert!(!matches("cbs.*.health", "cbs.greeter.v2.health"));
+        assert!(!matches("cbs.greeter.*", "cbs.greeter"));
+    }
+
+    #[test]
+    fn tail_wildcard_matches_one_or_more_tokens() {
+        assert!(matches("cbs.>", "cbs.greeter.say_hello"));
+        assert!(matches("cbs.>", "cbs.greeter"));
+        assert!(matches(">", "cbs.greeter.say_hello"));
+        assert!(!matches("cbs.>", "cbs"));
+        assert!(!matches("cbs.>", "other.greeter.say_hello"));
+    }
+
+    #[test]
+    fn pattern_validation() {
+        assert!(is_valid_pattern("cbs.>"));
+        assert!(is_valid_pattern("cbs.*.health"));
+        assert!(is_valid_pattern("cbs.greeter.say_hello"));
+        assert!(!is_valid_pattern("cbs.>.health"));
+        assert!(!is_valid_pattern("cbs.gree*.health"));
+        assert!(!is_valid_pattern("cbs..health"));
+        assert!(!is_valid_pattern(""));
+        assert!(!is_valid_pattern("cbs.greeter say"));
+    }
+
+    #[test]
+    fn validate_pattern_reports_bad_request() {
+        assert!(validate_pattern("cbs.*.health").is_ok());
+        assert!(matches!(validate_pattern("cbs.>.health"), Err(BusError::BadRequest { .. })));
+    }
+
+    #[test]
+    fn subject_validation_rejects_wildcards() {
+        assert!(is_valid_subject("cbs.greeter.say_hello"));
+        assert!(!is_valid_subject("cbs.*.say_hello"));
+        assert!(!is_valid_subject("cbs.>"));
+        assert!(!is_valid_subject("cbs."));
+    }
+
+    #[test]
+    fn best_match_prefers_specific_patterns() {
+        let patterns = ["cbs.>", "cbs.*.say_hello", "cbs.greeter.*", "cbs.greeter.say_hello"];
+        assert_eq!(
+            best_match(patterns, "cbs.greeter.say_hello"),
+            Some("cbs.greeter.say_hello")
+        );
+        assert_eq!(best_match(patterns, "cbs.greeter.wave"), Some("cbs.greeter.*"));
+        assert_eq!(best_match(patterns, "cbs.printer.say_hello"), Some("cbs.*.say_hello"));
+        assert_eq!(best_match(patterns, "cbs.printer.write"), Some("cbs.>"));
+        assert_eq!(best_match(patterns, "other.printer.write"), None);
+    }
+
+    #[test]
+    fn service_token_extraction() {
+        assert_eq!(service_token("cbs.greeter.say_hello"), Some("greeter"));
+        assert_eq!(service_token("cbs.greeter.*"), Some("greeter"));
+        assert_eq!(service_token("cbs.*.health"), None);
+        assert_eq!(service_token("cbs.>"), None);
+        assert_eq!(service_token("other.greeter"), None);
+    }
+}
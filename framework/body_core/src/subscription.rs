@@ -0,0 +1,151 @@
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::{BusError, CancellationToken};
+
+/// Delivery counters shared between a subscription handle and its bus
+#[derive(Debug, Default)]
+pub struct SubscriptionStats {
+    delivered: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl SubscriptionStats {
+    /// Record the outcome of one message handed to the handler
+    pub fn record(&self, result: &Result<Value, BusError>) {
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        if result.is_err() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a message that could not be handed to the handler at all
+    pub fn record_undeliverable(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Messages handed to the handler
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Messages whose handler returned an error or that could not be decoded
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+/// Handle to an active subscription returned by `BodyBus::subscribe`
+///
+/// Dropping the handle unsubscribes. Call [`Subscription::detach`] to keep the
+/// subscription alive for the lifetime of the bus instead.
+#[derive(Debug)]
+#[must_use = "dropping a Subscription unsubscribes immediately; call detach() to keep it"]
+pub struct Subscription {
+    subject: String,
+    stats: Arc<SubscriptionStats>,
+    cancellation: CancellationToken,
+    detached: bool,
+}
+
+impl Subscription {
+    /// Create a handle for a bus implementation to hand back to the subscriber
+    pub fn new(subject: &str) -> Self {
+        Self {
+            subject: subject.to_string(),
+            stats: Arc::new(SubscriptionStats::default()),
+            cancellation: CancellationToken::new(),
+            detached: false,
+        }
+    }
+
+    /// Subject or pattern this subscription listens on
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Messages handed to the handler so far
+    pub fn delivered(&self) -> u64 {
+        self.stats.delivered()
+    }
+
+    /// Messages that failed so far
+    pub fn failed(&self) -> u64 {
+        self.stats.failed()
+    }
+
+    /// Whether the subscription still receives messages
+    pub fn is_active(&self) -> bool {
+        !self.cancellation.is_cancelled()
+    }
+
+    /// Counters for the bus to update as it delivers messages
+    pub fn stats(&self) -> Arc<SubscriptionStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Token the bus watches to stop delivering messages
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Stop delivering messages; handlers already running finish normally
+    pub fn unsubscribe(self) {
+        self.cancellation.cancel();
+    }
+
+    /// Keep the subscription active until the bus shuts down
+    pub fn detach(mut self) {
+        self.detached = true;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if !self.detached {
+            self.cancellation.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn stats_count_deliveries_and_failures() {
+        let subscription = Subscription::new("cbs.test.action");
+        let stats = subscription.stats();
+        stats.record(&Ok(json!({})));
+        stats.record(&Err(BusError::bad_request("nope")));
+        stats.record_undeliverable();
+
+        assert_eq!(subscription.delivered(), 2);
+        assert_eq!(subscription.failed(), 2);
+    }
+
+    #[test]
+    fn unsubscribe_cancels_token() {
+        let subscription = Subscription::new("cbs.test.action");
+        let token = subscription.cancellation();
+        assert!(subscription.is_active());
+
+        subscription.unsubscribe();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn drop_unsubscribes_unless_detached() {
+        let dropped = Subscription::new("cbs.test.dropped");
+        let dropped_token = dropped.cancellation();
+        drop(dropped);
+        assert!(dropped_token.is_cancelled());
+
+        let detached = Subscription::new("cbs.test.detached");
+        let detached_token = detached.cancellation();
+        detached.detach();
+        assert!(!detached_token.is_cancelled());
+    }
+}
@@ -0,0 +1,38 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::{BodyBus, BusError, Envelope, RequestContext, SharedHandler, Subscription};
+
+/// `BodyBus` for tests that only need a bus handle
+///
+/// Plain requests answer `{}`. Requests sent with a context answer with that
+/// context's `trace_id` and whether it has a deadline, so tests can check
+/// what was propagated. Subscriptions never deliver and events are dropped.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullBus;
+
+#[async_trait]
+impl BodyBus for NullBus {
+    async fn request(&self, _envelope: Envelope) -> Result<Value, BusError> {
+        Ok(json!({}))
+    }
+
+    async fn request_with_context(&self, _envelope: Envelope, parent: &RequestContext) -> Result<Value, BusError> {
+        Ok(json!({
+            "trace_id": parent.trace_id(),
+            "has_deadline": parent.deadline().is_some(),
+        }))
+    }
+
+    async fn subscribe(&self, subject: &str, _handler: SharedHandler) -> Result<Subscription, BusError> {
+        Ok(Subscription::new(subject))
+    }
+
+    async fn publish(&self, _envelope: Envelope) -> Result<(), BusError> {
+        Ok(())
+    }
+
+    async fn subscribe_events(&self, subject: &str, _handler: SharedHandler) -> Result<Subscription, BusError> {
+        Ok(Subscription::new(subject))
+    }
+}
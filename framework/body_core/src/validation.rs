@@ -0,0 +1,439 @@
+use async_trait::async_trait;
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::{ValidationError, Validator};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::middleware::{DispatchNext, Middleware, PublishNext, RequestNext, ScatterNext, StreamNext};
+use crate::{BusError, Envelope, RequestContext, ResponseStream, ScatterOptions, ScatterReply};
+
+/// Wire contract for envelopes, kept in sync with `docs/schemas/envelope.schema.json`
+const ENVELOPE_SCHEMA: &str = include_str!("../../docs/schemas/envelope.schema.json");
+
+/// Schema id reported in error details for envelope violations
+pub const ENVELOPE_SCHEMA_ID: &str = "cbs/envelope";
+
+/// Errors raised while loading schemas
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("Schema directory not found: {0}")]
+    DirectoryNotFound(String),
+    #[error("Invalid schema {id}: {message}")]
+    InvalidSchema { id: String, message: String },
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON parsing error in {path}: {source}")]
+    Json {
+        path: String,
+        source: serde_json::Error,
+    },
+}
+
+/// Compiled envelope schema plus payload schemas keyed by envelope `schema` id
+pub struct SchemaRegistry {
+    envelope: Validator,
+    payloads: HashMap<String, Validator>,
+}
+
+impl SchemaRegistry {
+    /// Registry that checks envelopes only; payload schemas are added with `register`
+    pub fn new() -> Self {
+        let schema: Value =
+            serde_json::from_str(ENVELOPE_SCHEMA).expect("bundled envelope schema is valid JSON");
+        let envelope = jsonschema::validator_for(&schema).expect("bundled envelope schema compiles");
+        Self {
+            envelope,
+            payloads: HashMap::new(),
+        }
+    }
+
+    /// Load every `*.json` file under `dir` as a payload schema
+    ///
+    /// The schema id is the path relative to `dir` without the extension, so
+    /// `schemas/demo/v1/Name.json` (or `Name.schema.json`) validates `demo/v1/Name`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self, SchemaError> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            return Err(SchemaError::DirectoryNotFound(dir.display().to_string()));
+        }
+
+        let mut registry = Self::new();
+        registry.load_dir(dir, dir)?;
+        Ok(registry)
+    }
+
+    fn load_dir(&mut self, root: &Path, dir: &Path) -> Result<(), SchemaError> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.load_dir(root, &path)?;
+                continue;
+            }
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            let id = relative
+                .strip_suffix(".schema.json")
+                .or_else(|| relative.strip_suffix(".json"))
+                .unwrap_or(&relative)
+                .to_string();
+
+            let content = fs::read_to_string(&path)?;
+            let schema: Value = serde_json::from_str(&content).map_err(|source| SchemaError::Json {
+                path: path.display().to_string(),
+                source,
+            })?;
+            self.register(&id, &schema)?;
+        }
+        Ok(())
+    }
+
+    /// Register the JSON Schema for payloads whose envelope carries `schema_id`
+    pub fn register(&mut self, schema_id: &str, schema: &Value) -> Result<(), SchemaError> {
+        let validator = jsonschema::validator_for(schema).map_err(|e| SchemaError::InvalidSchema {
+            id: schema_id.to_string(),
+            message: e.to_string(),
+        })?;
+        debug!(schema = %schema_id, "SchemaRegistry: Registered payload schema");
+        self.payloads.insert(schema_id.to_string(), validator);
+        Ok(())
+    }
+
+    /// Whether a payload schema is registered for `schema_id`
+    pub fn contains(&self, schema_id: &str) -> bool {
+        self.payloads.contains_key(schema_id)
+    }
+
+    /// Check an envelope against the wire contract
+    pub fn validate_envelope(&self, envelope: &Envelope) -> Result<(), BusError> {
+        let instance = serde_json::to_value(envelope)
+            .map_err(|e| BusError::Serialization(format!("Failed to serialize envelope: {}", e)))?;
+        check(&self.envelope, ENVELOPE_SCHEMA_ID, "envelope", &instance)
+    }
+
+    /// Check the payload against its registered schema; unknown schema ids pass
+    pub fn validate_payload(&self, envelope: &Envelope) -> Result<(), BusError> {
+        let (Some(validator), Some(payload)) =
+            (self.payloads.get(&envelope.schema), envelope.payload.as_ref())
+        else {
+            return Ok(());
+        };
+        check(validator, &envelope.schema, &envelope.schema, payload)
+    }
+
+    /// Check both the envelope and its payload
+    pub fn validate(&self, envelope: &Envelope) -> Result<(), BusError> {
+        self.validate_envelope(envelope)?;
+        self.validate_payload(envelope)
+    }
+}
+
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for SchemaRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut schemas: Vec<_> = self.payloads.keys().collect();
+        schemas.sort();
+        f.debug_struct("SchemaRegistry").field("payloads", &schemas).finish()
+    }
+}
+
+/// Collect every violation into a `BadRequest` shaped like `decode_payload` errors
+fn check(validator: &Validator, schema_id: &str, what: &str, instance: &Value) -> Result<(), BusError> {
+    let fields: Vec<Value> = validator
+        .iter_errors(instance)
+        .map(|error| json!({ "path": field_path(&error), "error": error.to_string() }))
+        .collect();
+
+    let Some(first) = fields.first() else {
+        return Ok(());
+    };
+    let message = format!(
+        "Invalid {} at `{}`: {}",
+        what,
+        first["path"].as_str().unwrap_or_default(),
+        first["error"].as_str().unwrap_or_default()
+    );
+    Err(BusError::bad_request_with_details(
+        message,
+        json!({ "schema": schema_id, "fields": fields }),
+    ))
+}
+
+/// Dotted path of the failing value, including the name of a missing property
+fn field_path(error: &ValidationError<'_>) -> String {
+    let mut path = String::new();
+    for segment in error.instance_path.as_str().split('/').skip(1) {
+        if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+            path.push_str(&format!("[{}]", segment));
+        } else {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(&segment.replace("~1", "/").replace("~0", "~"));
+        }
+    }
+
+    if let ValidationErrorKind::Required { property } = &error.kind {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(property.as_str().unwrap_or_default());
+    }
+
+    if path.is_empty() {
+        ".".to_string()
+    } else {
+        path
+    }
+}
+
+/// Middleware that rejects malformed envelopes and payloads
+///
+/// Outbound requests and events are checked before they reach the wire, and
+/// inbound messages before the handler runs. Violations fail with
+/// `BusError::BadRequest` whose details list every violation as
+/// `{"schema", "fields": [{"path", "error"}]}`.
+#[derive(Debug, Clone)]
+pub struct ValidationMiddleware {
+    registry: Arc<SchemaRegistry>,
+}
+
+impl ValidationMiddleware {
+    /// Validate traffic against `registry`
+    pub fn new(registry: Arc<SchemaRegistry>) -> Self {
+        Self { registry }
+    }
+
+    /// Schemas this middleware validates against
+    pub fn registry(&self) -> &Arc<SchemaRegistry> {
+        &self.registry
+    }
+
+    fn check(&self, envelope: &Envelope, direction: &str) -> Result<(), BusError> {
+        self.registry.validate(envelope).inspect_err(|e| {
+            warn!(id = %envelope.id, service = %envelope.service, verb = %envelope.verb, error = %e, "Rejected invalid {} envelope", direction);
+        })
+    }
+}
+
+#[async_trait]
+impl Middleware for ValidationMiddleware {
+    async fn request(
+        &self,
+        envelope: Envelope,
+        parent: Option<&RequestContext>,
+        next: RequestNext,
+    ) -> Result<Value, BusError> {
+        self.check(&envelope, "outbound")?;
+        next.run(envelope, parent).await
+    }
+
+    async fn request_stream(&self, envelope: Envelope, next: StreamNext) -> Result<ResponseStream, BusError> {
+        self.check(&envelope, "outbound")?;
+        next.run(envelope).await
+    }
+
+    async fn request_all(
+        &self,
+        envelope: Envelope,
+        options: ScatterOptions,
+        next: ScatterNext,
+    ) -> Result<Vec<ScatterReply>, BusError> {
+        self.check(&envelope, "outbound")?;
+        next.run(envelope, options).await
+    }
+
+    async fn publish(&self, envelope: Envelope, next: PublishNext) -> Result<(), BusError> {
+        self.check(&envelope, "outbound")?;
+        next.run(envelope).await
+    }
+
+    async fn dispatch(
+        &self,
+        envelope: Envelope,
+        ctx: RequestContext,
+        next: DispatchNext,
+    ) -> Result<Value, BusError> {
+        self.check(&envelope, "inbound")?;
+        next.run(envelope, ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{async_handler, sync_handler, BodyBus, LocalBus, MiddlewareBus};
+    use tempfile::TempDir;
+
+    fn name_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "address": {
+                    "type": "object",
+                    "properties": { "city": { "type": "string" } },
+                    "required": ["city"]
+                }
+            },
+            "required": ["name"]
+        })
+    }
+
+    fn registry() -> Arc<SchemaRegistry> {
+        let mut registry = SchemaRegistry::new();
+        registry.register("demo/v1/Name", &name_schema()).unwrap();
+        Arc::new(registry)
+    }
+
+    fn fields(error: &BusError) -> Vec<String> {
+        error.details().unwrap()["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["path"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid_envelope_passes() {
+        let envelope = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({"name": "Ada"}));
+        registry().validate(&envelope).unwrap();
+    }
+
+    #[test]
+    fn envelope_casing_and_schema_format_are_enforced() {
+        let envelope = Envelope::new_request("Greeter", "sayHello", "not-a-schema", json!({}));
+        let error = registry().validate_envelope(&envelope).unwrap_err();
+
+        assert!(matches!(error, BusError::BadRequest { .. }));
+        assert_eq!(error.details().unwrap()["schema"], ENVELOPE_SCHEMA_ID);
+        let paths = fields(&error);
+        assert!(paths.contains(&"service".to_string()));
+        assert!(paths.contains(&"verb".to_string()));
+        assert!(paths.contains(&"schema".to_string()));
+    }
+
+    #[test]
+    fn payload_violations_report_nested_paths() {
+        let envelope = Envelope::new_request(
+            "greeter",
+            "say_hello",
+            "demo/v1/Name",
+            json!({"address": {"city": 42}}),
+        );
+        let error = registry().validate(&envelope).unwrap_err();
+
+        assert_eq!(error.details().unwrap()["schema"], "demo/v1/Name");
+        let paths = fields(&error);
+        assert!(paths.contains(&"name".to_string()));
+        assert!(paths.contains(&"address.city".to_string()));
+    }
+
+    #[test]
+    fn unknown_payload_schema_is_not_checked() {
+        let envelope = Envelope::new_request("greeter", "say_hello", "demo/v1/Other", json!({"anything": 1}));
+        registry().validate(&envelope).unwrap();
+    }
+
+    #[test]
+    fn loads_schemas_from_directory() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("demo/v1")).unwrap();
+        fs::write(
+            dir.path().join("demo/v1/Name.schema.json"),
+            serde_json::to_string(&name_schema()).unwrap(),
+        )
+        .unwrap();
+        fs::write(dir.path().join("README.md"), "not a schema").unwrap();
+
+        let registry = SchemaRegistry::from_dir(dir.path()).unwrap();
+        assert!(registry.contains("demo/v1/Name"));
+
+        let envelope = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+        assert!(registry.validate(&envelope).is_err());
+    }
+
+    #[test]
+    fn invalid_schema_file_is_reported() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("Broken.json"), "{ not json").unwrap();
+        assert!(matches!(
+            SchemaRegistry::from_dir(dir.path()),
+            Err(SchemaError::Json { .. })
+        ));
+
+        assert!(matches!(
+            SchemaRegistry::from_dir(dir.path().join("missing")),
+            Err(SchemaError::DirectoryNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn middleware_rejects_outbound_and_inbound() {
+        let local: Arc<dyn BodyBus> = Arc::new(LocalBus::new());
+        let bus = MiddlewareBus::new(Arc::clone(&local)).layer(ValidationMiddleware::new(registry()));
+        let greeter = bus
+            .subscribe("cbs.greeter.say_hello", sync_handler(|_| Ok(json!({"ok": true}))))
+            .await
+            .unwrap();
+
+        let valid = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({"name": "Ada"}));
+        assert_eq!(bus.request(valid).await.unwrap(), json!({"ok": true}));
+
+        // Rejected before reaching the bus
+        let invalid = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({"name": ""}));
+        assert!(matches!(bus.request(invalid.clone()).await, Err(BusError::BadRequest { .. })));
+        assert!(matches!(bus.request_stream(invalid.clone()).await, Err(BusError::BadRequest { .. })));
+        let scattered = bus.request_all(invalid.clone(), crate::ScatterOptions::new()).await;
+        assert!(matches!(scattered, Err(BusError::BadRequest { .. })));
+        assert_eq!(greeter.delivered(), 1);
+
+        // Sent around the middleware, rejected by the wrapped handler
+        match local.request(invalid).await {
+            Err(BusError::BadRequest { details, .. }) => {
+                assert_eq!(details.unwrap()["fields"][0]["path"], "name");
+            }
+            other => panic!("Expected BadRequest, got: {:?}", other),
+        }
+        assert_eq!(greeter.failed(), 1);
+    }
+
+    #[tokio::test]
+    async fn nested_requests_are_validated() {
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new())).layer(ValidationMiddleware::new(registry()));
+        let _relay = bus
+            .subscribe(
+                "cbs.relay.forward",
+                async_handler(|_envelope, ctx: RequestContext| async move {
+                    let nested = Envelope::new_request("greeter", "say_hello", "demo/v1/Name", json!({}));
+                    ctx.request(nested).await
+                }),
+            )
+            .await
+            .unwrap();
+
+        let envelope = Envelope::new_request("relay", "forward", "demo/v1/Void", json!({}));
+        assert!(matches!(bus.request(envelope).await, Err(BusError::BadRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn invalid_events_are_not_published() {
+        let bus = MiddlewareBus::new(Arc::new(LocalBus::new())).layer(ValidationMiddleware::new(registry()));
+        let event = Envelope::new_request("Audit", "logged", "demo/v1/Event", json!({}));
+        assert!(matches!(bus.publish(event).await, Err(BusError::BadRequest { .. })));
+    }
+}
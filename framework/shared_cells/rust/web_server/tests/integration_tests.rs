@@ -44,7 +44,7 @@ async fn test_static_file_serving() {
     // Test serving index.html
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("http://{}/index.html", addr))
+        .get(format!("http://{}/index.html", addr))
         .send()
         .await
         .unwrap();
@@ -55,7 +55,7 @@ async fn test_static_file_serving() {
     
     // Test serving CSS file
     let response = client
-        .get(&format!("http://{}/css/style.css", addr))
+        .get(format!("http://{}/css/style.css", addr))
         .send()
         .await
         .unwrap();
@@ -66,7 +66,7 @@ async fn test_static_file_serving() {
     
     // Test 404 for non-existent file
     let response = client
-        .get(&format!("http://{}/nonexistent.html", addr))
+        .get(format!("http://{}/nonexistent.html", addr))
         .send()
         .await
         .unwrap();
@@ -95,7 +95,7 @@ async fn test_health_check_endpoint() {
     
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("http://{}/health", addr))
+        .get(format!("http://{}/health", addr))
         .send()
         .await
         .unwrap();
@@ -127,7 +127,7 @@ async fn test_cors_headers() {
     
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("http://{}/health", addr))
+        .get(format!("http://{}/health", addr))
         .send()
         .await
         .unwrap();
@@ -187,7 +187,7 @@ async fn test_flutter_web_build_serving() {
     
     // Test serving Flutter web assets
     let response = client
-        .get(&format!("http://{}/main.dart.js", addr))
+        .get(format!("http://{}/main.dart.js", addr))
         .send()
         .await
         .unwrap();
@@ -199,7 +199,7 @@ async fn test_flutter_web_build_serving() {
     );
     
     let response = client
-        .get(&format!("http://{}/flutter.js", addr))
+        .get(format!("http://{}/flutter.js", addr))
         .send()
         .await
         .unwrap();
@@ -208,7 +208,7 @@ async fn test_flutter_web_build_serving() {
     
     // Test serving index.html with proper content type
     let response = client
-        .get(&format!("http://{}/", addr))
+        .get(format!("http://{}/", addr))
         .send()
         .await
         .unwrap();